@@ -0,0 +1,39 @@
+// Times bulk SER frame decoding against per-pixel reads. Run with `cargo bench`; the decoded
+// values are compared in tests/serbench.rs.
+
+#[path = "../tests/common/perpixel.rs"]
+mod perpixel;
+
+use perpixel::decode_per_pixel;
+use sciimg::binfilereader::*;
+use solhat::ser;
+use std::time::Instant;
+
+const TEST_SER_FILE: &str = "testdata/Sun_130540_F0001-0005.ser";
+
+fn main() {
+    let ser_file = ser::SerFile::load_ser(TEST_SER_FILE).expect("Unable to load SER file");
+    let file_reader = BinFileReader::new(&TEST_SER_FILE.to_string());
+
+    let per_pixel_start = Instant::now();
+    for i in 0..ser_file.frame_count {
+        decode_per_pixel(&ser_file, &file_reader, i);
+    }
+    let per_pixel_elapsed = per_pixel_start.elapsed();
+
+    let bulk_start = Instant::now();
+    for i in 0..ser_file.frame_count {
+        ser_file.get_frame(i).unwrap();
+    }
+    let bulk_elapsed = bulk_start.elapsed();
+
+    println!(
+        "Decoded {} frames of {}x{}: per-pixel {:?}, bulk {:?} ({:.1}x)",
+        ser_file.frame_count,
+        ser_file.image_width,
+        ser_file.image_height,
+        per_pixel_elapsed,
+        bulk_elapsed,
+        per_pixel_elapsed.as_secs_f64() / bulk_elapsed.as_secs_f64()
+    );
+}
@@ -23,17 +23,24 @@ struct Cli {
 enum SolHa {
     Add(add::Add),
     Composite(composite::Composite),
+    DefectMap(defectmap::DefectMap),
     ExtractFrame(extractframe::ExtractFrame),
     Extract(extract::Extract),
     FrameStats(framestats::FrameStats),
+    Master(master::Master),
     Mean(mean::Mean),
     Process(process::Process),
+    SerCrop(sercrop::SerCrop),
+    SerCut(sercut::SerCut),
     SerInfo(serinfo::SerInfo),
+    SerRepair(serrepair::SerRepair),
     Subtract(subtract::Subtract),
+    SyntheticFlat(syntheticflat::SyntheticFlat),
     LdCorrect(ldcorrect::LdCorrect),
     ThreshTest(threshtest::ThreshTest),
     Median(median::Median),
     PreProcess(preprocess::PreProcess),
+    Timing(timing::Timing),
 }
 
 fn main() {
@@ -55,6 +62,9 @@ fn main() {
         SolHa::Composite(args) => {
             args.run();
         }
+        SolHa::DefectMap(args) => {
+            args.run();
+        }
         SolHa::ExtractFrame(args) => {
             args.run();
         }
@@ -64,18 +74,33 @@ fn main() {
         SolHa::FrameStats(args) => {
             args.run();
         }
+        SolHa::Master(args) => {
+            args.run();
+        }
         SolHa::Mean(args) => {
             args.run();
         }
         SolHa::Process(args) => {
             args.run();
         }
+        SolHa::SerCrop(args) => {
+            args.run();
+        }
+        SolHa::SerCut(args) => {
+            args.run();
+        }
         SolHa::SerInfo(args) => {
             args.run();
         }
+        SolHa::SerRepair(args) => {
+            args.run();
+        }
         SolHa::Subtract(args) => {
             args.run();
         }
+        SolHa::SyntheticFlat(args) => {
+            args.run();
+        }
         SolHa::LdCorrect(args) => {
             args.run();
         }
@@ -88,5 +113,8 @@ fn main() {
         SolHa::PreProcess(args) => {
             args.run();
         }
+        SolHa::Timing(args) => {
+            args.run();
+        }
     };
 }
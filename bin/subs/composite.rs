@@ -2,6 +2,7 @@ use crate::subs::runnable::RunnableSubcommand;
 
 use sciimg::vector::Vector;
 use sciimg::{enums::ImageMode, imagebuffer, medianblur, path, prelude::ImageBuffer};
+use solhat::limbfit::{LimbFit, LimbFitOptions};
 
 use std::process;
 
@@ -11,8 +12,12 @@ pub struct Composite {
     #[clap(long, short, help = "Input image")]
     input: String,
 
-    #[clap(long, short, help = "Chromosphere radius, in pixels")]
-    radius: f32,
+    #[clap(
+        long,
+        short,
+        help = "Chromosphere radius, in pixels (default: fitted to the limb)"
+    )]
+    radius: Option<f32>,
 
     #[clap(long, short, help = "Output image")]
     output: String,
@@ -66,6 +71,20 @@ impl RunnableSubcommand for Composite {
             process::exit(2);
         }
 
+        let radius = match self.radius {
+            Some(r) => r,
+            None => match LimbFit::from_file(&self.input, &LimbFitOptions::default()) {
+                Ok(fit) => {
+                    info!("Fitted limb radius: {} pixels", fit.radius);
+                    fit.radius
+                }
+                Err(why) => {
+                    error!("Error: {}. Specify the radius with -r", why);
+                    process::exit(1);
+                }
+            },
+        };
+
         // Open input image
         info!("Opening image at {}", self.input);
         let orig_image =
@@ -106,12 +125,12 @@ impl RunnableSubcommand for Composite {
                 // Radial distance from the center of the disc at the pixel
                 let r = mid_vec.distance_to(&p);
 
-                if r <= self.radius as f64 {
+                if r <= radius as f64 {
                     let a = composited.get(x, y);
                     let b = normalized.get(x, y);
 
-                    let f = if self.radius as f64 - r <= border_blur_margin {
-                        let frac = ((self.radius as f64 - r) / border_blur_margin) as f32;
+                    let f = if radius as f64 - r <= border_blur_margin {
+                        let frac = ((radius as f64 - r) / border_blur_margin) as f32;
                         b * frac + (1.0 - frac) * a
                     } else {
                         b
@@ -0,0 +1,102 @@
+use crate::subs::runnable::RunnableSubcommand;
+
+use sciimg::path;
+use solhat::constants::param;
+use solhat::defectmap::{self, DetectOptions};
+use std::process;
+
+#[derive(clap::Args)]
+#[clap(author, version, about = "Build a hot and cold pixel map from master darks and flats", long_about = None)]
+pub struct DefectMap {
+    #[clap(
+        long,
+        short,
+        help = "Dark or flat captures, or master images",
+        multiple_values(true)
+    )]
+    input_files: Vec<String>,
+
+    #[clap(long, short, help = "Output defect map (json)")]
+    output: String,
+
+    #[clap(
+        long = param::PARAM_HPC_WINDOW_SIZE,
+        short = 'w',
+        help = "Hot pixel correction window size"
+    )]
+    hpc_window: Option<usize>,
+
+    #[clap(
+        long,
+        help = "Detection threshold above the local median, in standard deviations"
+    )]
+    hot_sigma: Option<f32>,
+
+    #[clap(
+        long,
+        help = "Detection threshold below the local median, in standard deviations"
+    )]
+    cold_sigma: Option<f32>,
+}
+
+impl RunnableSubcommand for DefectMap {
+    fn run(&self) {
+        if !path::parent_exists_and_writable(self.output.as_str()) {
+            eprintln!(
+                "Error: Output parent directory does not exist or is unwritable: {}",
+                path::get_parent(self.output.as_str())
+            );
+            process::exit(2);
+        }
+
+        let options = DetectOptions {
+            window_size: self.hpc_window.unwrap_or(defectmap::DEFAULT_WINDOW_SIZE),
+            hot_sigma: self.hot_sigma.unwrap_or(defectmap::DEFAULT_HOT_SIGMA),
+            cold_sigma: self.cold_sigma.unwrap_or(defectmap::DEFAULT_COLD_SIGMA),
+        };
+
+        let mut combined: Option<defectmap::DefectMap> = None;
+        for input_file in self.input_files.iter() {
+            let defect_map = match defectmap::DefectMap::from_input(input_file, &options) {
+                Ok(m) => m,
+                Err(why) => {
+                    eprintln!("Error: {}: {}", input_file, why);
+                    process::exit(1);
+                }
+            };
+            println!(
+                "{}: {} hot, {} cold pixels",
+                input_file,
+                defect_map.hot_pixels.len(),
+                defect_map.cold_pixels.len()
+            );
+
+            match &mut combined {
+                Some(c) => {
+                    if let Err(why) = c.merge(&defect_map) {
+                        eprintln!("Error: {}: {}", input_file, why);
+                        process::exit(1);
+                    }
+                }
+                None => combined = Some(defect_map),
+            }
+        }
+
+        match combined {
+            Some(defect_map) => {
+                println!(
+                    "Total: {} hot, {} cold pixels",
+                    defect_map.hot_pixels.len(),
+                    defect_map.cold_pixels.len()
+                );
+                defect_map
+                    .save(&self.output)
+                    .expect("Failed to save defect map");
+            }
+            None => {
+                eprintln!("Error: No input files specified");
+                process::exit(1);
+            }
+        }
+    }
+}
@@ -1,7 +1,10 @@
 use crate::subs::runnable::RunnableSubcommand;
 use rayon::prelude::*;
-use sciimg::{image, path, quality};
-use solhat::{processing, ser};
+use sciimg::path;
+use solhat::calibration::CalibrationSet;
+use solhat::enums::QualityMethod;
+use solhat::framesource::FrameSource;
+use solhat::{quality, ser};
 use std::fs;
 use std::process;
 
@@ -17,6 +20,12 @@ pub struct Extract {
     #[clap(long, short, help = "Quality estimation sorting")]
     quality: bool,
 
+    #[clap(
+        long,
+        help = "Quality metric (sigma, laplacian, tenengrad, contrast, fft)"
+    )]
+    quality_metric: Option<String>,
+
     #[clap(long, short, help = "Minimum sigma value")]
     minsigma: Option<f32>,
 
@@ -31,6 +40,9 @@ pub struct Extract {
 
     #[clap(long, short = 'D', help = "dark Flat frame file")]
     darkflat: Option<String>,
+
+    #[clap(long, short, help = "Bias frame file")]
+    bias: Option<String>,
 }
 
 impl RunnableSubcommand for Extract {
@@ -38,38 +50,26 @@ impl RunnableSubcommand for Extract {
         let min_sigma = self.minsigma.unwrap_or(1.0);
         let max_sigma = self.maxsigma.unwrap_or(100000.0);
 
-        let flat_frame = match &self.flat {
-            Some(f) => {
-                if !path::file_exists(f) {
-                    error!("Error: Flat file not found: {}", f);
+        let quality_method = match &self.quality_metric {
+            Some(m) => match QualityMethod::from(m) {
+                Some(m) => m,
+                None => {
+                    eprintln!("Error: Unrecognized quality metric: {}", m);
+                    process::exit(1);
                 }
-
-                processing::HaProcessing::create_mean_from_ser(f).unwrap()
-            }
-            None => image::Image::new_empty().unwrap(),
+            },
+            None => QualityMethod::Sigma,
         };
-
-        let dark_frame = match &self.dark {
-            Some(f) => {
-                if !path::file_exists(f) {
-                    error!("Error: Dark file not found: {}", f);
+        let quality_metric = quality::metric(quality_method);
+
+        let calibration =
+            match CalibrationSet::load_paths(&self.flat, &self.dark, &self.darkflat, &self.bias) {
+                Ok(c) => c,
+                Err(why) => {
+                    error!("Error: {}", why);
+                    process::exit(1);
                 }
-
-                processing::HaProcessing::create_mean_from_ser(f).unwrap()
-            }
-            None => image::Image::new_empty().unwrap(),
-        };
-
-        let dark_flat_frame = match &self.darkflat {
-            Some(f) => {
-                if !path::file_exists(f) {
-                    error!("Error: Dark flat file not found: {}", f);
-                }
-
-                processing::HaProcessing::create_mean_from_ser(f).unwrap()
-            }
-            None => image::Image::new_empty().unwrap(),
-        };
+            };
 
         self.input_files.iter().for_each(|ser_file_path| {
             if !path::file_exists(ser_file_path) {
@@ -93,16 +93,24 @@ impl RunnableSubcommand for Extract {
             }
 
             let ser_file = ser::SerFile::load_ser(ser_file_path).expect("Unable to load SER file");
-            ser_file.validate();
+            ser_file.validate().warn_issues(ser_file_path);
+            if let Err(why) = calibration.validate(
+                ser_file.image_width,
+                ser_file.image_height,
+                ser_file.num_bands(),
+            ) {
+                error!("Error: {}: {}", ser_file_path, why);
+                process::exit(1);
+            }
 
-            (0..ser_file.frame_count).into_par_iter().for_each(|i| {
-                let mut frame = ser_file.get_frame(i).expect("Failed extracting frame");
+            ser_file.par_frames().for_each(|f| {
+                let sourced_frame = f.expect("Failed extracting frame");
+                let i = sourced_frame.frame_id;
+                let mut frame = sourced_frame.frame;
 
-                frame
-                    .buffer
-                    .calibrate(&flat_frame, &dark_frame, &dark_flat_frame);
+                calibration.apply(&mut frame.buffer);
 
-                let sd = quality::get_quality_estimation(&frame.buffer);
+                let sd = quality_metric.estimate(&frame.buffer);
 
                 if sd < min_sigma || sd > max_sigma {
                     warn!("Frame #{} is outside of sigma range ({})", i, sd);
@@ -33,13 +33,13 @@ impl RunnableSubcommand for ExtractFrame {
         }
 
         let ser_file = ser::SerFile::load_ser(&self.input_file).expect("Unable to load SER file");
-        ser_file.validate();
+        ser_file.validate().warn_issues(&self.input_file);
 
-        if self.frame >= ser_file.frame_count {
+        if self.frame >= ser_file.complete_frame_count() {
             eprintln!(
                 "Error: Requested frame {} exceeds available frames {}",
                 (self.frame + 1),
-                ser_file.frame_count
+                ser_file.complete_frame_count()
             );
             process::exit(5);
         }
@@ -1,7 +1,8 @@
 use crate::subs::runnable::RunnableSubcommand;
 use sciimg::path;
-use sciimg::quality;
-use solhat::{enums::Target, lunar, parallacticangle, ser, solar};
+use solhat::enums::{QualityMethod, Target};
+use solhat::framesource::{self, FrameSource};
+use solhat::{lunar, parallacticangle, quality, solar};
 use std::process;
 
 #[derive(clap::Args)]
@@ -23,6 +24,12 @@ pub struct FrameStats {
         allow_hyphen_values(true)
     )]
     longitude: f32,
+
+    #[clap(
+        long,
+        help = "Quality metric (sigma, laplacian, tenengrad, contrast, fft)"
+    )]
+    quality_metric: Option<String>,
 }
 
 impl RunnableSubcommand for FrameStats {
@@ -38,9 +45,21 @@ impl RunnableSubcommand for FrameStats {
             None => Target::Sun,
         };
 
+        let quality_method = match &self.quality_metric {
+            Some(m) => match QualityMethod::from(m) {
+                Some(m) => m,
+                None => {
+                    eprintln!("Error: Unrecognized quality metric: {}", m);
+                    process::exit(1);
+                }
+            },
+            None => QualityMethod::Sigma,
+        };
+        let quality_metric = quality::metric(quality_method);
+
         println!(
             "{:11} {:26} {:8}    {:9}    {:5} {:5}",
-            "Frame Num:", "Date/Time:", "Sigma:", "Rotation:", "Min DN:", "Max DN:"
+            "Frame Num:", "Date/Time:", "Quality:", "Rotation:", "Min DN:", "Max DN:"
         );
 
         self.input_files.iter().for_each(|sf| {
@@ -48,10 +67,12 @@ impl RunnableSubcommand for FrameStats {
             if ! path::file_exists(sf) {
                 panic!("File not found: {}", sf);
             }
-            let ser_file = ser::SerFile::load_ser(sf).expect("Unable to load SER file");
-            for i in 0..ser_file.frame_count {
+            let frame_source = framesource::open(sf).expect("Unable to open input file");
+            for f in frame_source.frames() {
 
-                let frame_buffer = ser_file.get_frame(i).unwrap();
+                let sourced_frame = f.unwrap();
+                let i = sourced_frame.frame_id;
+                let frame_buffer = sourced_frame.frame;
                 let (alt, az) = match target {
                     Target::Moon => {
                         info!("Calculating position for Moon");
@@ -66,7 +87,7 @@ impl RunnableSubcommand for FrameStats {
                 let rotation = parallacticangle::from_lat_azimuth_altitude(self.latitude as f64, az, alt);
                 let (min, max) = frame_buffer.buffer.get_min_max_all_channel();
 
-                let qual = quality::get_quality_estimation(&frame_buffer.buffer);
+                let qual = quality_metric.estimate(&frame_buffer.buffer);
 
                 println!("{:>10}  {}-{:02}-{:02} {:02}:{:02}:{:02}.{:04}   {:.4} {:>10.4} {:>7}    {:>7}", i, 
                                                         frame_buffer.timestamp.year,
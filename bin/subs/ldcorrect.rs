@@ -1,6 +1,7 @@
 use crate::subs::runnable::RunnableSubcommand;
 use sciimg::path;
 use solhat::ldcorrect;
+use solhat::limbfit::{LimbFit, LimbFitOptions};
 use std::process;
 
 #[derive(clap::Args)]
@@ -9,8 +10,12 @@ pub struct LdCorrect {
     #[clap(long, short, help = "Input image")]
     input_file: String,
 
-    #[clap(long, short, help = "Solar radius in pixels")]
-    radius_pixels: usize,
+    #[clap(
+        long,
+        short,
+        help = "Solar radius in pixels (default: fitted to the limb)"
+    )]
+    radius_pixels: Option<usize>,
 
     #[clap(
         long,
@@ -42,6 +47,20 @@ impl RunnableSubcommand for LdCorrect {
             process::exit(2);
         }
 
+        let radius_pixels = match self.radius_pixels {
+            Some(r) => r,
+            None => match LimbFit::from_file(&self.input_file, &LimbFitOptions::default()) {
+                Ok(fit) => {
+                    info!("Fitted limb radius: {} pixels", fit.radius);
+                    fit.radius.round() as usize
+                }
+                Err(why) => {
+                    error!("Error: {}. Specify the radius with -r", why);
+                    process::exit(1);
+                }
+            },
+        };
+
         let ld_coefficient = match &self.ld_coefficient {
             Some(v) => v.clone(),
             None => vec![0.0_f64], // Zero value (0.0) will trigger the function to attempt to calculate it
@@ -57,7 +76,7 @@ impl RunnableSubcommand for LdCorrect {
         match ldcorrect::limb_darkening_correction(
             &self.input_file,
             &self.output,
-            self.radius_pixels,
+            radius_pixels,
             &ld_coefficient,
             composite_gradient_margin,
             self.inverted_chromosphere,
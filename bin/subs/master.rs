@@ -0,0 +1,137 @@
+use crate::subs::runnable::RunnableSubcommand;
+
+use sciimg::path;
+use solhat::{calibration, enums, master};
+use std::process;
+
+#[derive(clap::Args)]
+#[clap(author, version, about = "Build a master calibration frame", long_about = None)]
+pub struct Master {
+    #[clap(
+        long,
+        short,
+        help = "Input ser, avi or fits files, or directories of png/tiff frames",
+        multiple_values(true)
+    )]
+    input_files: Vec<String>,
+
+    #[clap(long, short, help = "Output image")]
+    output: String,
+
+    #[clap(
+        long,
+        short,
+        help = "Combine method (mean, median, sigma-clip, winsorized)"
+    )]
+    method: Option<String>,
+
+    #[clap(
+        long,
+        short = 't',
+        help = "Calibration frame type (bias, dark, flat, dark-flat)"
+    )]
+    frame_type: Option<String>,
+
+    #[clap(
+        long,
+        help = "Rejection bound below the median, in standard deviations"
+    )]
+    sigma_low: Option<f32>,
+
+    #[clap(
+        long,
+        help = "Rejection bound above the median, in standard deviations"
+    )]
+    sigma_high: Option<f32>,
+
+    #[clap(
+        long,
+        short,
+        help = "Master bias to subtract from each frame (for flats)"
+    )]
+    bias: Option<String>,
+
+    #[clap(long, short, help = "Normalize the master (for flats)")]
+    normalize: bool,
+
+    #[clap(
+        long,
+        help = "Frame data held in memory at once while combining, in megabytes"
+    )]
+    max_memory: Option<usize>,
+}
+
+impl RunnableSubcommand for Master {
+    fn run(&self) {
+        if !path::parent_exists_and_writable(self.output.as_str()) {
+            eprintln!(
+                "Error: Output parent directory does not exist or is unwritable: {}",
+                path::get_parent(self.output.as_str())
+            );
+            process::exit(2);
+        }
+
+        let method = match &self.method {
+            Some(m) => match master::CombineMethod::from(m) {
+                Some(m) => m,
+                None => {
+                    eprintln!("Error: Unrecognized combine method: {}", m);
+                    process::exit(1);
+                }
+            },
+            None => master::CombineMethod::SigmaClip,
+        };
+
+        let frame_type =
+            self.frame_type
+                .as_ref()
+                .map(|t| match enums::CalibrationFrameType::from(t) {
+                    Some(t) => t,
+                    None => {
+                        eprintln!("Error: Unrecognized calibration frame type: {}", t);
+                        process::exit(1);
+                    }
+                });
+
+        let bias = self
+            .bias
+            .as_ref()
+            .map(|b| match calibration::load_master(b) {
+                Ok(img) => img,
+                Err(e) => {
+                    eprintln!("Error: Failed to load bias frame {}: {}", b, e);
+                    process::exit(1);
+                }
+            });
+
+        let options = master::MasterOptions {
+            method,
+            frame_type,
+            sigma_low: self.sigma_low.unwrap_or(master::DEFAULT_SIGMA),
+            sigma_high: self.sigma_high.unwrap_or(master::DEFAULT_SIGMA),
+            bias,
+            normalize: self.normalize,
+            max_memory_mb: self.max_memory.unwrap_or(master::DEFAULT_MAX_MEMORY_MB),
+        };
+
+        let input_files: Vec<&str> = self.input_files.iter().map(|s| s.as_str()).collect();
+
+        match master::build_master(&input_files, &options) {
+            Ok(master_frame) => {
+                vprintln!(
+                    "Combined {} frames, {:.4}% of values rejected",
+                    master_frame.metadata.frame_count,
+                    master_frame.metadata.rejected_fraction * 100.0
+                );
+                info!("Saving master frame to {}", self.output);
+                master_frame
+                    .save(&self.output)
+                    .expect("Failed to save master frame");
+            }
+            Err(why) => {
+                eprintln!("Error: {}", why);
+                process::exit(1);
+            }
+        }
+    }
+}
@@ -6,7 +6,12 @@ use std::process;
 #[derive(clap::Args)]
 #[clap(author, version, about = "Compute mean of images", long_about = None)]
 pub struct Mean {
-    #[clap(long, short, help = "Input ser file", multiple_values(false))]
+    #[clap(
+        long,
+        short,
+        help = "Input ser, avi or fits file",
+        multiple_values(false)
+    )]
     input_file: String,
 
     #[clap(long, short, help = "Output image")]
@@ -129,14 +129,21 @@ macro_rules! pb_done_with_error {
 
 pub mod add;
 pub mod composite;
+pub mod defectmap;
 pub mod extract;
 pub mod extractframe;
 pub mod framestats;
 pub mod ldcorrect;
+pub mod master;
 pub mod mean;
 pub mod median;
 pub mod preprocess;
 pub mod process;
+pub mod sercrop;
+pub mod sercut;
 pub mod serinfo;
+pub mod serrepair;
 pub mod subtract;
+pub mod syntheticflat;
 pub mod threshtest;
+pub mod timing;
@@ -1,10 +1,11 @@
 use crate::subs::runnable::RunnableSubcommand;
 use rayon::prelude::*;
-use sciimg::prelude::*;
-use sciimg::{path, quality};
-use solhat::enums::Target;
+use sciimg::path;
+use solhat::calibration::CalibrationSet;
+use solhat::enums::{QualityMethod, Target};
+use solhat::framesource::FrameSource;
 use solhat::processing::HaProcessing;
-use solhat::{drizzle, processing, ser};
+use solhat::{drizzle, imageseq, quality, ser};
 use std::fs;
 use std::process;
 use std::sync::{Arc, Mutex};
@@ -25,6 +26,12 @@ pub struct PreProcess {
     #[clap(long, short, help = "Quality estimation sorting")]
     quality: bool,
 
+    #[clap(
+        long,
+        help = "Quality metric (sigma, laplacian, tenengrad, contrast, fft)"
+    )]
+    quality_metric: Option<String>,
+
     #[clap(long, short = 's', help = "Minimum sigma value")]
     minsigma: Option<f32>,
 
@@ -97,6 +104,18 @@ impl RunnableSubcommand for PreProcess {
             None => Target::Sun,
         };
 
+        let quality_method = match &self.quality_metric {
+            Some(m) => match QualityMethod::from(m) {
+                Some(m) => m,
+                None => {
+                    eprintln!("Error: Unrecognized quality metric: {}", m);
+                    process::exit(1);
+                }
+            },
+            None => QualityMethod::Sigma,
+        };
+        let quality_metric = quality::metric(quality_method);
+
         let obj_detect_threshold = self.threshold.unwrap_or(40.0);
         let initial_rotation = self.rotation.unwrap_or(0.0);
         let obs_latitude = self.latitude;
@@ -122,66 +141,20 @@ impl RunnableSubcommand for PreProcess {
             None => drizzle::Scale::Scale1_0,
         };
 
-        let flat_frame = match &self.flat {
-            Some(f) => {
-                if !path::file_exists(f) {
-                    error!("Error: Flat file not found: {}", f);
-                }
-
-                match path::get_extension(f).unwrap().to_uppercase().as_str() {
-                    "SER" => Some(processing::HaProcessing::create_mean_from_ser(f).unwrap()),
-                    _ => Some(Image::open_str(f).unwrap()),
-                }
-            }
-            None => None,
-        };
-
-        let dark_frame = match &self.dark {
-            Some(f) => {
-                if !path::file_exists(f) {
-                    error!("Error: Dark file not found: {}", f);
-                }
-
-                match path::get_extension(f).unwrap().to_uppercase().as_str() {
-                    "SER" => Some(processing::HaProcessing::create_mean_from_ser(f).unwrap()),
-                    _ => Some(Image::open_str(f).unwrap()),
-                }
-            }
-            None => None,
-        };
-
-        let dark_flat_frame = match &self.darkflat {
-            Some(f) => {
-                if !path::file_exists(f) {
-                    error!("Error: Dark Flat file not found: {}", f);
-                }
-
-                match path::get_extension(f).unwrap().to_uppercase().as_str() {
-                    "SER" => Some(processing::HaProcessing::create_mean_from_ser(f).unwrap()),
-                    _ => Some(Image::open_str(f).unwrap()),
-                }
-            }
-            None => None,
-        };
-
-        let bias_frame = match &self.dark {
-            Some(f) => {
-                if !path::file_exists(f) {
-                    error!("Error: Bias file not found: {}", f);
-                }
-
-                match path::get_extension(f).unwrap().to_uppercase().as_str() {
-                    "SER" => Some(processing::HaProcessing::create_mean_from_ser(f).unwrap()),
-                    _ => Some(Image::open_str(f).unwrap()),
+        let calibration =
+            match CalibrationSet::load_paths(&self.flat, &self.dark, &self.darkflat, &self.bias) {
+                Ok(c) => c,
+                Err(why) => {
+                    error!("Error: {}", why);
+                    process::exit(1);
                 }
-            }
-            None => None,
-        };
+            };
 
         let report_mtx = Arc::new(Mutex::new(solhat::processing::ProcessReport {
             max_sigma: std::f32::MIN,
             min_sigma: std::f32::MAX,
             initial_rotation: initial_rotation as f32,
+            quality_method,
             ..Default::default()
         }));
 
@@ -207,31 +180,41 @@ impl RunnableSubcommand for PreProcess {
             }
 
             let ser_file = ser::SerFile::load_ser(ser_file_path).expect("Unable to load SER file");
-            ser_file.validate();
+            ser_file.validate().warn_issues(ser_file_path);
+            if let Err(why) = calibration.validate(
+                ser_file.image_width,
+                ser_file.image_height,
+                ser_file.num_bands(),
+            ) {
+                error!("Error: {}: {}", ser_file_path, why);
+                process::exit(1);
+            }
 
             let num_frames = if let Some(nf) = self.number_of_frames {
-                if nf <= ser_file.frame_count {
+                if nf <= ser_file.complete_frame_count() {
                     nf
                 } else {
-                    ser_file.frame_count
+                    ser_file.complete_frame_count()
                 }
             } else {
-                ser_file.frame_count
+                ser_file.complete_frame_count()
             };
 
+            let timestamps_mtx: Mutex<Vec<(String, u64)>> = Mutex::new(vec![]);
+
             pb_set_length!(num_frames);
             pb_zero!();
-            (0..num_frames).into_par_iter().for_each(|i| {
+            ser_file.par_frames().take(num_frames).for_each(|f| {
                 // Report Update
                 report_mtx.lock().unwrap().total_frames += 1;
 
-                let mut frame = ser_file.get_frame(i).expect("Failed extracting frame");
+                let sourced_frame = f.expect("Failed extracting frame");
+                let i = sourced_frame.frame_id;
+                let mut frame = sourced_frame.frame;
 
-                frame
-                    .buffer
-                    .calibrate2(&flat_frame, &dark_frame, &dark_flat_frame, &bias_frame);
+                calibration.apply(&mut frame.buffer);
 
-                let sd = quality::get_quality_estimation(&frame.buffer);
+                let sd = quality_metric.estimate(&frame.buffer);
                 if sd.is_nan() {
                     warn!("Frame quality is NaN!");
                     process::exit(2);
@@ -336,8 +319,22 @@ impl RunnableSubcommand for PreProcess {
                     .save(&frame_output_path)
                     .expect("Failed to save image");
 
+                timestamps_mtx.lock().unwrap().push((
+                    path::basename(&frame_output_path),
+                    frame.timestamp.timestamp,
+                ));
+
                 pb_inc!();
             });
+
+            // Lets the frame directory be read back as a frame source with the original times.
+            // The frames are marked derotated so stacking them doesn't rotate them again.
+            imageseq::write_timestamps_sidecar(
+                &output_directory,
+                &timestamps_mtx.lock().unwrap(),
+                true,
+            )
+            .expect("Failed to write frame timestamps");
         });
 
         report_mtx.lock().unwrap().check_total_discarded();
@@ -1,13 +1,22 @@
 use crate::subs::runnable::RunnableSubcommand;
 
 use sciimg::path;
-use solhat::{drizzle, enums::Target, processing};
+use solhat::alignment::{self, AlignmentOptions};
+use solhat::enums::{AlignmentMethod, QualityMethod, Target};
+use solhat::multipoint::{self, MultiPointOptions};
+use solhat::patchquality::{self, PatchQualityOptions};
+use solhat::{drizzle, processing};
 use std::process;
 
 #[derive(clap::Args)]
 #[clap(author, version, about = "Process a full observation", long_about = None)]
 pub struct Process {
-    #[clap(long, short, help = "Input ser files", multiple_values(true))]
+    #[clap(
+        long,
+        short,
+        help = "Input ser, avi or fits files, or directories of png/tiff frames",
+        multiple_values(true)
+    )]
     input_files: Vec<String>,
 
     #[clap(long, short, help = "Output image")]
@@ -25,6 +34,22 @@ pub struct Process {
     #[clap(long, short, help = "Bias frame file")]
     bias: Option<String>,
 
+    #[clap(
+        long,
+        short = 'C',
+        help = "Calibration library directory, for any calibration frames not given"
+    )]
+    calibration_library: Option<String>,
+
+    #[clap(
+        long,
+        help = "Build the flat from the input frames, for etalon rings and banding"
+    )]
+    synthetic_flat: bool,
+
+    #[clap(long, short = 'M', help = "Hot and cold pixel map")]
+    defect_map: Option<String>,
+
     #[clap(long, short, help = "Crop width")]
     width: Option<usize>,
 
@@ -45,12 +70,62 @@ pub struct Process {
     #[clap(long, short, help = "Object detection threshold")]
     threshold: Option<f32>,
 
+    #[clap(
+        long,
+        short = 'a',
+        help = "Alignment method (com, phase-correlation, limb-fit)"
+    )]
+    alignment: Option<String>,
+
+    #[clap(
+        long,
+        help = "Phase correlation confidence below which frames fall back to center of mass"
+    )]
+    min_correlation: Option<f32>,
+
+    #[clap(
+        long,
+        help = "Multi-point alignment, following local seeing distortion across the frame"
+    )]
+    multi_point: bool,
+
+    #[clap(long, help = "Multi-point alignment patch size, in pixels")]
+    patch_size: Option<usize>,
+
+    #[clap(
+        long,
+        help = "Multi-point alignment search radius around the global alignment, in pixels"
+    )]
+    search_radius: Option<usize>,
+
+    #[clap(
+        long,
+        help = "Local quality limit (top % frames per patch), stacking each region from its sharpest frames"
+    )]
+    local_quality: Option<u8>,
+
+    #[clap(long, help = "Local quality patch size, in pixels")]
+    quality_patch_size: Option<usize>,
+
+    #[clap(
+        long,
+        help = "Local quality, relative to the sharpest frame at a patch, below which frames are left out of it (0-1)"
+    )]
+    min_local_quality: Option<f32>,
+
     #[clap(long, short, help = "Image mask")]
     mask: Option<String>,
 
     #[clap(long, short, help = "Quality limit (top % frames)")]
     quality: Option<u8>,
 
+    #[clap(
+        long,
+        help = "Quality metric (sigma, laplacian, tenengrad, contrast, fft). Frames default to \
+                sigma, local quality patches to laplacian"
+    )]
+    quality_metric: Option<String>,
+
     #[clap(long, short = 's', help = "Minimum sigma value")]
     minsigma: Option<f32>,
 
@@ -109,6 +184,34 @@ impl RunnableSubcommand for Process {
             None => Target::Sun,
         };
 
+        let alignment = AlignmentOptions {
+            method: match &self.alignment {
+                Some(a) => match AlignmentMethod::from(a) {
+                    Some(a) => a,
+                    None => {
+                        eprintln!("Error: Unrecognized alignment method: {}", a);
+                        process::exit(1);
+                    }
+                },
+                None => AlignmentMethod::CenterOfMass,
+            },
+            min_confidence: self
+                .min_correlation
+                .unwrap_or(alignment::DEFAULT_MIN_CONFIDENCE),
+        };
+
+        let multipoint = if self.multi_point {
+            Some(MultiPointOptions {
+                patch_size: self.patch_size.unwrap_or(multipoint::DEFAULT_PATCH_SIZE),
+                search_radius: self
+                    .search_radius
+                    .unwrap_or(multipoint::DEFAULT_SEARCH_RADIUS),
+                ..Default::default()
+            })
+        } else {
+            None
+        };
+
         let obj_detect_threshold = self.threshold.unwrap_or(40.0);
         let crop_width = self.width.unwrap_or(0);
         let crop_height = self.height.unwrap_or(0);
@@ -158,6 +261,33 @@ impl RunnableSubcommand for Process {
             None => String::from(""),
         };
 
+        let calibration_library = match &self.calibration_library {
+            Some(d) => {
+                if !path::file_exists(d) {
+                    eprintln!("Error: Calibration library not found: {}", d);
+                    process::exit(1);
+                }
+                d.clone()
+            }
+            None => String::from(""),
+        };
+
+        if self.synthetic_flat && (self.flat.is_some() || self.darkflat.is_some()) {
+            eprintln!("Error: A synthetic flat cannot be combined with flat or dark flat frames");
+            process::exit(1);
+        }
+
+        let defect_map = match &self.defect_map {
+            Some(f) => {
+                if !path::file_exists(f) {
+                    eprintln!("Error: Defect map not found: {}", f);
+                    process::exit(1);
+                }
+                f.clone()
+            }
+            None => String::from(""),
+        };
+
         let mask_file = match &self.mask {
             Some(f) => {
                 if !path::file_exists(f) {
@@ -221,16 +351,43 @@ impl RunnableSubcommand for Process {
             None => drizzle::Scale::Scale1_0,
         };
 
+        let quality_method = match &self.quality_metric {
+            Some(m) => match QualityMethod::from(m) {
+                Some(m) => Some(m),
+                None => {
+                    eprintln!("Error: Unrecognized quality metric: {}", m);
+                    process::exit(1);
+                }
+            },
+            None => None,
+        };
+
+        let patch_quality = self
+            .local_quality
+            .map(|top_percentage| PatchQualityOptions {
+                patch_size: self
+                    .quality_patch_size
+                    .unwrap_or(patchquality::DEFAULT_PATCH_SIZE),
+                top_percentage,
+                min_relative_quality: self
+                    .min_local_quality
+                    .unwrap_or(patchquality::DEFAULT_MIN_RELATIVE_QUALITY),
+                quality_method: quality_method.unwrap_or(patchquality::DEFAULT_QUALITY_METHOD),
+            });
+
         let enable_rotation = !self.norot;
 
         let input_files: Vec<&str> = self.input_files.iter().map(|s| s.as_str()).collect();
 
-        let mut ha_processing = processing::HaProcessing::init_new(
+        let mut ha_processing = match processing::HaProcessing::init_new(
             &input_files,
             &flat_frame,
             &dark_frame,
             &dark_flat_frame,
             &bias_frame,
+            &calibration_library,
+            self.synthetic_flat,
+            &defect_map,
             &mask_file,
             crop_width,
             crop_height,
@@ -246,8 +403,17 @@ impl RunnableSubcommand for Process {
             number_of_frames,
             target,
             drizzle_scale,
-        )
-        .expect("Failed to create processing context");
+            alignment,
+            multipoint,
+            patch_quality,
+            quality_method.unwrap_or(QualityMethod::Sigma),
+        ) {
+            Ok(p) => p,
+            Err(why) => {
+                eprintln!("Error: {}", why);
+                process::exit(1);
+            }
+        };
         ha_processing.process_ser_files(
             &input_files,
             limit_top_pct,
@@ -0,0 +1,65 @@
+use crate::subs::runnable::RunnableSubcommand;
+
+use sciimg::path;
+use solhat::sercrop;
+use std::process;
+
+#[derive(clap::Args)]
+#[clap(author, version, about = "Export SER frames cropped around the object", long_about = None)]
+pub struct SerCrop {
+    #[clap(long, short, help = "Input ser files", multiple_values(true))]
+    input_files: Vec<String>,
+
+    #[clap(long, short, help = "Output ser file")]
+    output: String,
+
+    #[clap(long, short, help = "Crop width")]
+    width: usize,
+
+    #[clap(long, short = 'H', help = "Crop height")]
+    height: usize,
+
+    #[clap(long, short, help = "Object detection threshold")]
+    threshold: Option<f32>,
+}
+
+impl RunnableSubcommand for SerCrop {
+    fn run(&self) {
+        if !path::parent_exists_and_writable(&self.output) {
+            eprintln!(
+                "Error: Output parent directory does not exist or is unwritable: {}",
+                path::get_parent(&self.output)
+            );
+            process::exit(2);
+        }
+
+        if self.input_files.contains(&self.output) {
+            eprintln!("Error: Output file cannot also be an input file");
+            process::exit(1);
+        }
+
+        if self.width == 0 || self.height == 0 {
+            eprintln!("Error: Crop width and height must be greater than zero");
+            process::exit(1);
+        }
+
+        let obj_detect_threshold = self.threshold.unwrap_or(40.0);
+        let input_files: Vec<&str> = self.input_files.iter().map(|s| s.as_str()).collect();
+
+        match sercrop::crop_ser_files(
+            &input_files,
+            &self.output,
+            self.width,
+            self.height,
+            obj_detect_threshold,
+        ) {
+            Ok(frames_written) => {
+                vprintln!("Wrote {} frames to {}", frames_written, self.output)
+            }
+            Err(why) => {
+                eprintln!("Error: {}", why);
+                process::exit(1);
+            }
+        }
+    }
+}
@@ -0,0 +1,75 @@
+use crate::subs::runnable::RunnableSubcommand;
+
+use sciimg::path;
+use solhat::sercut;
+use std::process;
+
+#[derive(clap::Args)]
+#[clap(author, version, about = "Trim, decimate and concatenate SER files", long_about = None)]
+pub struct SerCut {
+    #[clap(long, short, help = "Input ser files", multiple_values(true))]
+    input_files: Vec<String>,
+
+    #[clap(long, short, help = "Output ser file")]
+    output: String,
+
+    #[clap(long, short, help = "First frame to keep (beginning at 0)")]
+    start: Option<usize>,
+
+    #[clap(long, short, help = "Last frame to keep (inclusive)")]
+    end: Option<usize>,
+
+    #[clap(long, short = 'n', help = "Keep every Nth frame")]
+    every: Option<usize>,
+
+    #[clap(
+        long,
+        short = 'x',
+        help = "Frames to drop (beginning at 0)",
+        multiple_values(true)
+    )]
+    drop: Vec<usize>,
+}
+
+impl RunnableSubcommand for SerCut {
+    fn run(&self) {
+        if !path::parent_exists_and_writable(&self.output) {
+            eprintln!(
+                "Error: Output parent directory does not exist or is unwritable: {}",
+                path::get_parent(&self.output)
+            );
+            process::exit(2);
+        }
+
+        if self.input_files.contains(&self.output) {
+            eprintln!("Error: Output file cannot also be an input file");
+            process::exit(1);
+        }
+
+        let selection = sercut::FrameSelection {
+            start_frame: self.start.unwrap_or(0),
+            end_frame: self.end,
+            every_nth: self.every.unwrap_or(1),
+            drop_frames: self.drop.clone(),
+        };
+
+        if let Some(end_frame) = selection.end_frame {
+            if end_frame < selection.start_frame {
+                eprintln!("Error: End frame cannot be before start frame");
+                process::exit(1);
+            }
+        }
+
+        let input_files: Vec<&str> = self.input_files.iter().map(|s| s.as_str()).collect();
+
+        match sercut::cut_ser_files(&input_files, &self.output, &selection) {
+            Ok(frames_written) => {
+                vprintln!("Wrote {} frames to {}", frames_written, self.output)
+            }
+            Err(why) => {
+                eprintln!("Error: {}", why);
+                process::exit(1);
+            }
+        }
+    }
+}
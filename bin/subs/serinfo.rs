@@ -8,6 +8,9 @@ use solhat::ser;
 pub struct SerInfo {
     #[clap(long, short, help = "Input ser file", multiple_values(false))]
     input_file: String,
+
+    #[clap(long, short, help = "Print header details as JSON")]
+    json: bool,
 }
 
 impl RunnableSubcommand for SerInfo {
@@ -15,9 +18,18 @@ impl RunnableSubcommand for SerInfo {
         if path::file_exists(self.input_file.as_str()) {
             let ser_file =
                 ser::SerFile::load_ser(&self.input_file).expect("Unable to load SER file");
-            ser_file.validate();
+            let report = ser_file.validate();
 
-            ser_file.print_header_details();
+            if self.json {
+                println!(
+                    "{}",
+                    serde_json::to_string_pretty(&ser_file.header_summary())
+                        .expect("Failed to serialize SER header")
+                );
+            } else {
+                ser_file.print_header_details();
+                report.print();
+            }
         }
     }
 }
@@ -0,0 +1,55 @@
+use crate::subs::runnable::RunnableSubcommand;
+
+use sciimg::path;
+use solhat::ser;
+use std::process;
+
+#[derive(clap::Args)]
+#[clap(author, version, about = "Rewrite a damaged SER file keeping every complete frame", long_about = None)]
+pub struct SerRepair {
+    #[clap(long, short, help = "Input ser file", multiple_values(false))]
+    input_file: String,
+
+    #[clap(long, short, help = "Output ser file")]
+    output: String,
+}
+
+impl RunnableSubcommand for SerRepair {
+    fn run(&self) {
+        if !path::file_exists(&self.input_file) {
+            eprintln!("Error: File not found: {}", self.input_file);
+            process::exit(1);
+        }
+
+        if !path::parent_exists_and_writable(&self.output) {
+            eprintln!(
+                "Error: Output parent directory does not exist or is unwritable: {}",
+                path::get_parent(&self.output)
+            );
+            process::exit(2);
+        }
+
+        if self.input_file == self.output {
+            eprintln!("Error: Output file cannot also be the input file");
+            process::exit(1);
+        }
+
+        let ser_file = ser::SerFile::load_ser(&self.input_file).expect("Unable to load SER file");
+        let report = ser_file.validate();
+        report.print();
+
+        if report.is_intact() {
+            vprintln!("No damage found, writing an unchanged copy");
+        }
+
+        match ser_file.write_repaired(&self.output) {
+            Ok(frames_written) => {
+                println!("Wrote {} frames to {}", frames_written, self.output)
+            }
+            Err(why) => {
+                eprintln!("Error: {}", why);
+                process::exit(1);
+            }
+        }
+    }
+}
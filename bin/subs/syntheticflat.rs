@@ -0,0 +1,92 @@
+use crate::subs::runnable::RunnableSubcommand;
+
+use sciimg::{image, path};
+use solhat::synthflat;
+use std::process;
+
+#[derive(clap::Args)]
+#[clap(author, version, about = "Build a flat from the disk in light frames", long_about = None)]
+pub struct SyntheticFlat {
+    #[clap(
+        long,
+        short,
+        help = "Input ser, avi or fits files, or directories of png/tiff frames",
+        multiple_values(true)
+    )]
+    input_files: Vec<String>,
+
+    #[clap(long, short, help = "Output image")]
+    output: String,
+
+    #[clap(long, short, help = "Dark frame file")]
+    dark: Option<String>,
+
+    #[clap(long, short, help = "Object detection threshold")]
+    threshold: Option<f32>,
+
+    #[clap(long, short, help = "Maximum number of frames to use")]
+    number_of_frames: Option<usize>,
+
+    #[clap(
+        long,
+        short = 'm',
+        help = "Fraction of the disk radius covered by the flat"
+    )]
+    limb_margin: Option<f32>,
+
+    #[clap(
+        long,
+        short = 'r',
+        help = "Radius broad variations are removed over, in pixels (0 to keep them)"
+    )]
+    highpass_radius: Option<usize>,
+}
+
+impl RunnableSubcommand for SyntheticFlat {
+    fn run(&self) {
+        if !path::parent_exists_and_writable(self.output.as_str()) {
+            eprintln!(
+                "Error: Output parent directory does not exist or is unwritable: {}",
+                path::get_parent(self.output.as_str())
+            );
+            process::exit(2);
+        }
+
+        let dark = self.dark.as_ref().map(|d| {
+            if !path::file_exists(d) {
+                eprintln!("Error: Dark file not found: {}", d);
+                process::exit(1);
+            }
+            image::Image::open_str(d).expect("Failed to open dark frame")
+        });
+
+        let options = synthflat::SyntheticFlatOptions {
+            max_frames: self
+                .number_of_frames
+                .unwrap_or(synthflat::DEFAULT_MAX_FRAMES),
+            obj_detect_threshold: self.threshold.unwrap_or(40.0),
+            limb_margin: self.limb_margin.unwrap_or(synthflat::DEFAULT_LIMB_MARGIN),
+            highpass_radius: self
+                .highpass_radius
+                .unwrap_or(synthflat::DEFAULT_HIGHPASS_RADIUS),
+        };
+
+        let input_files: Vec<&str> = self.input_files.iter().map(|s| s.as_str()).collect();
+
+        match synthflat::build_synthetic_flat(&input_files, &dark, &options) {
+            Ok(flat) => {
+                vprintln!(
+                    "Built synthetic flat from {} frames",
+                    flat.metadata.frame_count
+                );
+                info!("Saving synthetic flat to {}", self.output);
+                flat.save(&self.output)
+                    .expect("Failed to save synthetic flat");
+            }
+            Err(why) => {
+                eprintln!("Error: {}", why);
+                process::exit(1);
+            }
+        }
+    }
+}
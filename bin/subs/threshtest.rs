@@ -1,8 +1,8 @@
 // Test an object detection threshold against an input frame. Optionally calibrate
 use crate::subs::runnable::RunnableSubcommand;
 use sciimg::path;
-use sciimg::prelude::*;
-use solhat::{processing::HaProcessing, ser, threshtest};
+use solhat::calibration::CalibrationSet;
+use solhat::{ser, threshtest};
 use std::process;
 
 #[derive(clap::Args)]
@@ -25,6 +25,9 @@ pub struct ThreshTest {
 
     #[clap(long, short = 'D', help = "dark Flat frame file")]
     darkflat: Option<String>,
+
+    #[clap(long, short, help = "Bias frame file")]
+    bias: Option<String>,
 }
 
 impl RunnableSubcommand for ThreshTest {
@@ -39,47 +42,14 @@ impl RunnableSubcommand for ThreshTest {
             process::exit(2);
         }
 
-        let flat_frame = match &self.flat {
-            Some(f) => {
-                if !path::file_exists(f) {
-                    error!("Error: Flat file not found: {}", f);
-                }
-                if HaProcessing::is_ser_file(f) {
-                    HaProcessing::create_mean_from_ser(f).unwrap()
-                } else {
-                    Image::open_str(f).unwrap()
-                }
-            }
-            None => Image::new_empty().unwrap(),
-        };
-
-        let dark_frame = match &self.dark {
-            Some(f) => {
-                if !path::file_exists(f) {
-                    error!("Error: Dark file not found: {}", f);
-                }
-                if HaProcessing::is_ser_file(f) {
-                    HaProcessing::create_mean_from_ser(f).unwrap()
-                } else {
-                    Image::open_str(f).unwrap()
-                }
-            }
-            None => Image::new_empty().unwrap(),
-        };
-
-        let dark_flat_frame = match &self.darkflat {
-            Some(f) => {
-                if !path::file_exists(f) {
-                    error!("Error: Dark flat file not found: {}", f);
-                }
-                if HaProcessing::is_ser_file(f) {
-                    HaProcessing::create_mean_from_ser(f).unwrap()
-                } else {
-                    Image::open_str(f).unwrap()
+        let calibration =
+            match CalibrationSet::load_paths(&self.flat, &self.dark, &self.darkflat, &self.bias) {
+                Ok(c) => c,
+                Err(why) => {
+                    error!("Error: {}", why);
+                    process::exit(1);
                 }
-            }
-            None => Image::new_empty().unwrap(),
-        };
+            };
 
         info!("Loading SER file from {}", self.input_file);
         let ser_file = ser::SerFile::load_ser(&self.input_file).expect("Failed to load SER file");
@@ -91,7 +61,11 @@ impl RunnableSubcommand for ThreshTest {
 
         let frame = ser_file.get_frame(0).expect("Failed to retrieve frame");
         let mut buffer = frame.buffer;
-        buffer.calibrate(&flat_frame, &dark_frame, &dark_flat_frame);
+        if let Err(why) = calibration.validate(buffer.width, buffer.height, buffer.num_bands()) {
+            error!("Error: {}", why);
+            process::exit(1);
+        }
+        calibration.apply(&mut buffer);
 
         let out_img = threshtest::threshtest(&buffer, self.threshold);
 
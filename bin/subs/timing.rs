@@ -0,0 +1,72 @@
+use crate::subs::runnable::RunnableSubcommand;
+
+use sciimg::path;
+use solhat::{ser, timing};
+use std::process;
+
+#[derive(clap::Args)]
+#[clap(author, version, about = "Analyze frame timestamps for gaps and clock problems", long_about = None)]
+pub struct Timing {
+    #[clap(long, short, help = "Input ser files", multiple_values(true))]
+    input_files: Vec<String>,
+
+    #[clap(
+        long,
+        short,
+        help = "Report intervals longer than this multiple of the median interval as gaps"
+    )]
+    gap_factor: Option<f64>,
+}
+
+impl RunnableSubcommand for Timing {
+    fn run(&self) {
+        let gap_factor = self.gap_factor.unwrap_or(timing::DEFAULT_GAP_FACTOR);
+        if gap_factor <= 1.0 {
+            eprintln!("Error: Gap factor must be greater than 1");
+            process::exit(1);
+        }
+
+        for input_file in self.input_files.iter() {
+            if !path::file_exists(input_file) {
+                eprintln!("Error: File not found: {}", input_file);
+                process::exit(1);
+            }
+
+            let ser_file = ser::SerFile::load_ser(input_file).expect("Unable to load SER file");
+            ser_file.validate().warn_issues(input_file);
+
+            // Without a trailer, a capture log listing each frame's time is just as good. Times
+            // interpolated from the capture start and end would say nothing about gaps.
+            let sidecar_timestamps = ser_file
+                .capture_metadata
+                .as_ref()
+                .map(|m| m.frame_timestamps.clone())
+                .unwrap_or_default();
+            let timestamps: Vec<u64> = if ser_file.has_timestamps() {
+                (0..ser_file.complete_frame_count())
+                    .map(|i| ser_file.get_frame_timestamp(i).unwrap())
+                    .collect()
+            } else if !sidecar_timestamps.is_empty() {
+                vprintln!(
+                    "{} has no timestamp trailer, using the capture log",
+                    input_file
+                );
+                sidecar_timestamps
+            } else {
+                eprintln!("Error: {} has no frame timestamps", input_file);
+                continue;
+            };
+
+            match timing::TimingReport::from_timestamps(&timestamps, gap_factor) {
+                Ok(mut report) => {
+                    report.check_header_times(
+                        ser_file.date_time.timestamp,
+                        ser_file.date_time_utc.timestamp,
+                    );
+                    println!("{}:\n{}", input_file, report);
+                }
+                Err(why) => eprintln!("Error: {}: {}", input_file, why),
+            }
+        }
+    }
+}
@@ -226,7 +226,6 @@ solha -v process -i $DATAROOT/$CHROME_ROOT/*/*ser \
  
 # echo "Creating Limb Darkening Corrected Image..."
 # solha -v ld-correct -i $DATAROOT/Sun_Chrome_${DATA_TS}${VERSION}.png \
-#                     -r 770 \
 #                     -l 0.56 \
 #                     -m 10 \
 #                     -I \
@@ -234,7 +233,6 @@ solha -v process -i $DATAROOT/$CHROME_ROOT/*/*ser \
 
 # echo "Creating Invert Composited Image..."
 # solha -v composite -i $DATAROOT/Sun_Chrome_${DATA_TS}${VERSION}.png \
-#                     -r 770 \
 
 
 if [ $HAS_PROM -eq 1 ]; then
@@ -0,0 +1,302 @@
+// Frame to frame alignment. Center of mass over a threshold is quick, but prominences, clouds
+// and a disk clipped at the frame edge all pull it off. Phase correlation against a reference
+// frame registers the whole of the image content instead, and a circle fitted to the limb
+// anchors full disk frames on their edge. Both fall back to the center of mass when they can't
+// find what they're looking for.
+
+use crate::enums::AlignmentMethod;
+use crate::fft::{fft2, frequency, spectrum, Spectrum};
+use crate::limbfit::{LimbFit, LimbFitOptions};
+use rayon::prelude::*;
+use sciimg::image;
+use sciimg::imagebuffer::{ImageBuffer, Offset};
+use serde::Serialize;
+
+pub const DEFAULT_MIN_CONFIDENCE: f32 = 10.0;
+
+// Half width of the area around the correlation peak left out of the sidelobe statistics
+const PEAK_EXCLUSION_RADIUS: usize = 5;
+
+// Width, in pixels, of the Gaussian the correlation peak is shaped into. Gives the subpixel fit
+// a known peak shape and keeps pixel noise out of the correlation.
+const PEAK_SIGMA: f32 = 1.0;
+
+#[derive(Debug, Clone, Copy)]
+pub struct AlignmentOptions {
+    pub method: AlignmentMethod,
+
+    // Peak to sidelobe ratio below which phase correlation is not trusted
+    pub min_confidence: f32,
+}
+
+impl Default for AlignmentOptions {
+    fn default() -> Self {
+        AlignmentOptions {
+            method: AlignmentMethod::CenterOfMass,
+            min_confidence: DEFAULT_MIN_CONFIDENCE,
+        }
+    }
+}
+
+/// Translation of a frame relative to the reference, in pixels
+#[derive(Debug, Clone, Copy)]
+pub struct Correlation {
+    pub dx: f32,
+    pub dy: f32,
+
+    // Peak to sidelobe ratio of the correlation surface
+    pub confidence: f32,
+}
+
+/// The offset a frame was stacked with and how it was arrived at
+#[derive(Debug, Clone, Serialize)]
+pub struct FrameOffset {
+    pub source_file: String,
+    pub frame_id: usize,
+    pub offset_h: f32,
+    pub offset_v: f32,
+    pub method: AlignmentMethod,
+    pub confidence: Option<f32>,
+    pub radius: Option<f32>,
+
+    // Patches a local shift was measured at, and the mean size of those shifts, in multi-point
+    // alignment
+    pub num_local_shifts: Option<usize>,
+    pub mean_local_shift: Option<f32>,
+}
+
+// Center of the Gaussian through three samples, relative to the middle one
+fn gaussian_peak(left: f32, center: f32, right: f32) -> f32 {
+    if left <= 0.0 || center <= 0.0 || right <= 0.0 {
+        return 0.0;
+    }
+    let (l, c, r) = (left.ln(), center.ln(), right.ln());
+    let denom = l - 2.0 * c + r;
+    if denom.abs() < f32::EPSILON {
+        0.0
+    } else {
+        ((l - r) / (2.0 * denom)).clamp(-0.5, 0.5)
+    }
+}
+
+pub struct PhaseCorrelator {
+    width: usize,
+    height: usize,
+    fft_width: usize,
+    fft_height: usize,
+    reference: Spectrum,
+}
+
+impl PhaseCorrelator {
+    pub fn new(reference: &ImageBuffer) -> PhaseCorrelator {
+        let fft_width = reference.width.next_power_of_two();
+        let fft_height = reference.height.next_power_of_two();
+        PhaseCorrelator {
+            width: reference.width,
+            height: reference.height,
+            fft_width,
+            fft_height,
+            reference: spectrum(reference, fft_width, fft_height),
+        }
+    }
+
+    /// Finds how far the frame's content is shifted from the reference's. None if the frame is
+    /// a different size, or has no content to correlate.
+    pub fn correlate(&self, frame: &ImageBuffer) -> Option<Correlation> {
+        if frame.width != self.width || frame.height != self.height {
+            return None;
+        }
+        let (fw, fh) = (self.fft_width, self.fft_height);
+        let Spectrum { mut re, mut im } = spectrum(frame, fw, fh);
+
+        // Normalized cross power spectrum, keeping only the phase difference, tapered so the
+        // peak comes out Gaussian
+        let taper = -2.0 * std::f32::consts::PI.powi(2) * PEAK_SIGMA * PEAK_SIGMA;
+        re.par_iter_mut()
+            .zip(im.par_iter_mut())
+            .enumerate()
+            .zip(
+                self.reference
+                    .re
+                    .par_iter()
+                    .zip(self.reference.im.par_iter()),
+            )
+            .for_each(|((i, (a_re, a_im)), (b_re, b_im))| {
+                let c_re = *a_re * b_re + *a_im * b_im;
+                let c_im = *a_im * b_re - *a_re * b_im;
+                let mag = (c_re * c_re + c_im * c_im).sqrt();
+                if mag > f32::EPSILON {
+                    let fx = frequency(i % fw, fw);
+                    let fy = frequency(i / fw, fh);
+                    let w = (taper * (fx * fx + fy * fy)).exp() / mag;
+                    *a_re = c_re * w;
+                    *a_im = c_im * w;
+                } else {
+                    *a_re = 0.0;
+                    *a_im = 0.0;
+                }
+            });
+        fft2(&mut re, &mut im, fw, fh, true);
+        let surface = re;
+
+        let mut peak_index = 0;
+        for (i, v) in surface.iter().enumerate() {
+            if *v > surface[peak_index] {
+                peak_index = i;
+            }
+        }
+        let peak = surface[peak_index];
+        let (px, py) = (peak_index % fw, peak_index / fw);
+        let at = |x: isize, y: isize| {
+            let x = x.rem_euclid(fw as isize) as usize;
+            let y = y.rem_euclid(fh as isize) as usize;
+            surface[y * fw + x]
+        };
+
+        let (ix, iy) = (px as isize, py as isize);
+        let sub_x = gaussian_peak(at(ix - 1, iy), peak, at(ix + 1, iy));
+        let sub_y = gaussian_peak(at(ix, iy - 1), peak, at(ix, iy + 1));
+
+        // The surface wraps, so peaks past the middle are negative shifts
+        let wrap = |p: usize, n: usize| {
+            if p > n / 2 {
+                p as f32 - n as f32
+            } else {
+                p as f32
+            }
+        };
+
+        // Sidelobe statistics, away from the peak
+        let near = |p: usize, q: usize, n: usize| {
+            let d = p.abs_diff(q);
+            d.min(n - d) <= PEAK_EXCLUSION_RADIUS
+        };
+        let (sum, sum_sq, count) = surface
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| !(near(i % fw, px, fw) && near(i / fw, py, fh)))
+            .fold((0.0_f64, 0.0_f64, 0_usize), |(s, s2, c), (_, v)| {
+                (s + *v as f64, s2 + (*v as f64).powi(2), c + 1)
+            });
+        if count == 0 {
+            return None;
+        }
+        let mean = sum / count as f64;
+        let stddev = (sum_sq / count as f64 - mean * mean).max(0.0).sqrt();
+        if peak as f64 <= mean {
+            return None;
+        }
+
+        // A flat surface around a peak is a perfect match
+        let confidence = if stddev > 0.0 {
+            ((peak as f64 - mean) / stddev).min(f32::MAX as f64) as f32
+        } else {
+            f32::MAX
+        };
+
+        Some(Correlation {
+            dx: wrap(px, fw) + sub_x,
+            dy: wrap(py, fh) + sub_y,
+            confidence,
+        })
+    }
+}
+
+/// How a single frame was aligned
+#[derive(Debug, Clone, Copy)]
+pub struct Alignment {
+    pub offset: Offset,
+    pub method: AlignmentMethod,
+
+    // Peak to sidelobe ratio for phase correlation, fraction of edge points on the limb for limb
+    // fitting
+    pub confidence: Option<f32>,
+
+    // Fitted limb radius, in input pixels
+    pub radius: Option<f32>,
+}
+
+/// Aligns frames by the configured method. With phase correlation, frames are registered
+/// against the reference, which is itself centered by its center of mass.
+pub struct Aligner {
+    options: AlignmentOptions,
+    obj_detect_threshold: f32,
+    reference_offset: Offset,
+    correlator: Option<PhaseCorrelator>,
+}
+
+impl Aligner {
+    pub fn new(
+        reference: &image::Image,
+        options: &AlignmentOptions,
+        obj_detect_threshold: f32,
+    ) -> Aligner {
+        let correlator = match options.method {
+            AlignmentMethod::PhaseCorrelation => Some(PhaseCorrelator::new(reference.get_band(0))),
+            AlignmentMethod::CenterOfMass | AlignmentMethod::LimbFit => None,
+        };
+        Aligner {
+            options: *options,
+            obj_detect_threshold,
+            reference_offset: reference.calc_center_of_mass_offset(obj_detect_threshold, 0),
+            correlator,
+        }
+    }
+
+    pub fn align(&self, frame: &image::Image) -> Alignment {
+        if self.options.method == AlignmentMethod::LimbFit {
+            let options = LimbFitOptions {
+                obj_detect_threshold: Some(self.obj_detect_threshold),
+                ..Default::default()
+            };
+            match LimbFit::find(frame, &options) {
+                Some(fit) => {
+                    return Alignment {
+                        offset: Offset {
+                            h: frame.width as f32 / 2.0 - fit.center_x,
+                            v: frame.height as f32 / 2.0 - fit.center_y,
+                        },
+                        method: AlignmentMethod::LimbFit,
+                        confidence: Some(fit.inlier_fraction()),
+                        radius: Some(fit.radius),
+                    };
+                }
+                None => {
+                    warn!("Limb fit failed, falling back to center of mass");
+                }
+            }
+        }
+
+        if let Some(correlator) = &self.correlator {
+            match correlator.correlate(frame.get_band(0)) {
+                Some(c) if c.confidence >= self.options.min_confidence => {
+                    return Alignment {
+                        offset: Offset {
+                            h: self.reference_offset.h - c.dx,
+                            v: self.reference_offset.v - c.dy,
+                        },
+                        method: AlignmentMethod::PhaseCorrelation,
+                        confidence: Some(c.confidence),
+                        radius: None,
+                    };
+                }
+                Some(c) => {
+                    warn!(
+                        "Low phase correlation confidence ({}), falling back to center of mass",
+                        c.confidence
+                    );
+                }
+                None => {
+                    warn!("Phase correlation failed, falling back to center of mass");
+                }
+            }
+        }
+
+        Alignment {
+            offset: frame.calc_center_of_mass_offset(self.obj_detect_threshold, 0),
+            method: AlignmentMethod::CenterOfMass,
+            confidence: None,
+            radius: None,
+        }
+    }
+}
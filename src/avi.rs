@@ -0,0 +1,288 @@
+// Reader for uncompressed AVI captures, as written by SharpCap and some FireCapture profiles.
+// RIFF reference: https://learn.microsoft.com/en-us/windows/win32/directshow/avi-riff-file-reference
+// OpenDML (AVIX) extensions are supported by walking every top-level RIFF chunk for 'movi' data.
+
+use crate::{capturemeta, ser, timestamp};
+use anyhow::{anyhow, Result};
+use memmap::Mmap;
+use sciimg::{enums::ImageMode, imagebuffer};
+use std::fs::{self, File};
+
+const CHUNK_HEADER_SIZE_BYTES: usize = 8;
+const BITMAPINFOHEADER_SIZE_BYTES: usize = 40;
+const BI_RGB: [u8; 4] = [0, 0, 0, 0];
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum AviPixelFormat {
+    Mono8, // Y800, GREY, Y8 and 8 bit BI_RGB (assumed to carry a grayscale palette)
+    Bgr24, // 24 bit BI_RGB
+}
+
+impl AviPixelFormat {
+    pub fn bytes_per_pixel(&self) -> usize {
+        match self {
+            AviPixelFormat::Mono8 => 1,
+            AviPixelFormat::Bgr24 => 3,
+        }
+    }
+}
+
+pub struct AviFile {
+    pub source_file: String,
+    pub image_width: usize,
+    pub image_height: usize,
+    pub frame_count: usize,
+    pub pixel_format: AviPixelFormat,
+    pub micro_sec_per_frame: u32,
+    pub capture_metadata: Option<capturemeta::CaptureMetadata>,
+    bottom_up: bool,
+    row_stride: usize,
+    frame_ranges: Vec<(usize, usize)>,
+    start_time: u64,
+    file_map: Mmap,
+}
+
+#[derive(Default)]
+struct AviParseState {
+    micro_sec_per_frame: u32,
+    stream_count: usize,
+    video_stream: Option<usize>,
+    video_fourcc: Option<[u8; 2]>,
+    bitmap_info: Option<(i32, i32, u16, [u8; 4])>,
+    frame_ranges: Vec<(usize, usize)>,
+}
+
+fn read_u16(data: &[u8], index: usize) -> u16 {
+    u16::from_le_bytes([data[index], data[index + 1]])
+}
+
+fn read_u32(data: &[u8], index: usize) -> u32 {
+    u32::from_le_bytes([
+        data[index],
+        data[index + 1],
+        data[index + 2],
+        data[index + 3],
+    ])
+}
+
+fn read_fourcc(data: &[u8], index: usize) -> [u8; 4] {
+    [
+        data[index],
+        data[index + 1],
+        data[index + 2],
+        data[index + 3],
+    ]
+}
+
+fn parse_chunks(data: &[u8], start: usize, end: usize, state: &mut AviParseState) -> Result<()> {
+    let mut pos = start;
+    while pos + CHUNK_HEADER_SIZE_BYTES <= end {
+        let fourcc = read_fourcc(data, pos);
+        let size = read_u32(data, pos + 4) as usize;
+        let body_start = pos + CHUNK_HEADER_SIZE_BYTES;
+        let body_end = body_start + size;
+
+        if &fourcc == b"RIFF" || &fourcc == b"LIST" {
+            if body_start + 4 > end {
+                break;
+            }
+            match &read_fourcc(data, body_start) {
+                b"AVI " | b"AVIX" | b"hdrl" | b"strl" | b"movi" | b"rec " => {
+                    parse_chunks(data, body_start + 4, body_end.min(end), state)?
+                }
+                _ => {}
+            }
+        } else if body_end > end {
+            // Truncated capture. Whatever was complete before this point is still usable.
+            warn!(
+                "AVI chunk at byte index {} extends past the end of its container",
+                pos
+            );
+            break;
+        } else if &fourcc == b"avih" && size >= 4 {
+            state.micro_sec_per_frame = read_u32(data, body_start);
+        } else if &fourcc == b"strh" && size >= 4 {
+            if &read_fourcc(data, body_start) == b"vids" && state.video_stream.is_none() {
+                state.video_stream = Some(state.stream_count);
+                let id = format!("{:02}", state.stream_count);
+                state.video_fourcc = Some([id.as_bytes()[0], id.as_bytes()[1]]);
+            }
+            state.stream_count += 1;
+        } else if &fourcc == b"strf"
+            && state.video_stream == Some(state.stream_count.wrapping_sub(1))
+            && state.bitmap_info.is_none()
+        {
+            if size < BITMAPINFOHEADER_SIZE_BYTES {
+                return Err(anyhow!("AVI video stream format header is too short"));
+            }
+            state.bitmap_info = Some((
+                read_u32(data, body_start + 4) as i32,
+                read_u32(data, body_start + 8) as i32,
+                read_u16(data, body_start + 14),
+                read_fourcc(data, body_start + 16),
+            ));
+        } else if let Some(id) = state.video_fourcc {
+            // Zero length chunks are dropped frames, which players show by repeating the previous one
+            if fourcc[0..2] == id && (&fourcc[2..4] == b"db" || &fourcc[2..4] == b"dc") && size > 0
+            {
+                state.frame_ranges.push((body_start, body_end));
+            }
+        }
+
+        pos = body_end + (size & 1);
+    }
+    Ok(())
+}
+
+impl AviFile {
+    pub fn open(file_path: &str) -> Result<AviFile> {
+        let file_map = unsafe { Mmap::map(&File::open(file_path)?)? };
+
+        if file_map.len() < 12 || &file_map[0..4] != b"RIFF" || &file_map[8..12] != b"AVI " {
+            return Err(anyhow!("Not an AVI file: {}", file_path));
+        }
+
+        let mut state = AviParseState::default();
+        parse_chunks(&file_map, 0, file_map.len(), &mut state)?;
+
+        let (width, height, bit_count, compression) = match state.bitmap_info {
+            Some(b) => b,
+            None => return Err(anyhow!("AVI file contains no video stream")),
+        };
+
+        let image_width = width.unsigned_abs() as usize;
+        let image_height = height.unsigned_abs() as usize;
+
+        let (pixel_format, bottom_up, row_stride) = match (&compression, bit_count) {
+            (b"Y800", 8) | (b"GREY", 8) | (b"Y8  ", 8) => {
+                (AviPixelFormat::Mono8, false, image_width)
+            }
+            (&BI_RGB, 8) => (AviPixelFormat::Mono8, height > 0, (image_width + 3) & !3),
+            (&BI_RGB, 24) => (
+                AviPixelFormat::Bgr24,
+                height > 0,
+                (image_width * 3 + 3) & !3,
+            ),
+            _ => {
+                return Err(anyhow!(
+                    "Unsupported AVI video format: {} at {} bits per pixel",
+                    String::from_utf8_lossy(&compression),
+                    bit_count
+                ))
+            }
+        };
+
+        // AVI carries no per-frame timestamps. Unless the capture software's sidecar says
+        // otherwise, assume the file was last written as the capture ended and space the frames
+        // at the nominal frame interval.
+        let end_time = timestamp::TimeStamp::from_system_time(fs::metadata(file_path)?.modified()?);
+        let duration = state.frame_ranges.len() as u64 * state.micro_sec_per_frame as u64 * 10;
+
+        Ok(AviFile {
+            source_file: file_path.to_string(),
+            image_width,
+            image_height,
+            frame_count: state.frame_ranges.len(),
+            pixel_format,
+            micro_sec_per_frame: state.micro_sec_per_frame,
+            capture_metadata: capturemeta::CaptureMetadata::load_for_capture(file_path),
+            bottom_up,
+            row_stride,
+            frame_ranges: state.frame_ranges,
+            start_time: end_time.timestamp.saturating_sub(duration),
+            file_map,
+        })
+    }
+
+    pub fn image_frame_size_bytes(&self) -> usize {
+        self.row_stride * self.image_height
+    }
+
+    pub fn get_frame_timestamp(&self, frame_num: usize) -> Result<u64> {
+        if frame_num >= self.frame_count {
+            return Err(anyhow!("Frame number out of range"));
+        }
+
+        if let Some(ts) = self
+            .capture_metadata
+            .as_ref()
+            .and_then(|m| m.frame_timestamp(frame_num))
+        {
+            return Ok(ts);
+        }
+        Ok(self.start_time + frame_num as u64 * self.micro_sec_per_frame as u64 * 10)
+    }
+
+    pub fn get_frame_bytes(&self, frame_num: usize) -> Result<&[u8]> {
+        if frame_num >= self.frame_count {
+            return Err(anyhow!("Frame number out of range"));
+        }
+
+        let (start, end) = self.frame_ranges[frame_num];
+        if end - start < self.image_frame_size_bytes() {
+            return Err(anyhow!(
+                "Frame {} is {} bytes, expected {}",
+                frame_num,
+                end - start,
+                self.image_frame_size_bytes()
+            ));
+        }
+        Ok(&self.file_map[start..start + self.image_frame_size_bytes()])
+    }
+
+    pub fn get_frame(&self, frame_num: usize) -> Result<ser::SerFrame> {
+        let frame_bytes = self.get_frame_bytes(frame_num)?;
+        let timestamp = self.get_frame_timestamp(frame_num)?;
+
+        info!(
+            "Extracting image frame #{} of {} from {}",
+            frame_num, self.frame_count, self.source_file
+        );
+
+        let bytes_per_pixel = self.pixel_format.bytes_per_pixel();
+        let row_bytes = self.image_width * bytes_per_pixel;
+
+        // Rows are reordered top-down with their padding dropped
+        let mut values: Vec<f32> = Vec::with_capacity(row_bytes * self.image_height);
+        for y in 0..self.image_height {
+            let row = if self.bottom_up {
+                self.image_height - 1 - y
+            } else {
+                y
+            };
+            let row_start = row * self.row_stride;
+            values.extend(
+                frame_bytes[row_start..row_start + row_bytes]
+                    .iter()
+                    .map(|v| *v as f32),
+            );
+        }
+
+        match self.pixel_format {
+            AviPixelFormat::Mono8 => {
+                let frame_buffer = imagebuffer::ImageBuffer::from_vec_as_mode(
+                    &values,
+                    self.image_width,
+                    self.image_height,
+                    ImageMode::U8BIT,
+                )?;
+                Ok(ser::SerFrame::new(&frame_buffer, timestamp))
+            }
+            AviPixelFormat::Bgr24 => {
+                let planes = (0..3)
+                    .map(|p| {
+                        imagebuffer::ImageBuffer::from_vec_as_mode(
+                            &values.iter().skip(p).step_by(3).copied().collect(),
+                            self.image_width,
+                            self.image_height,
+                            ImageMode::U8BIT,
+                        )
+                    })
+                    .collect::<Result<Vec<imagebuffer::ImageBuffer>>>()?;
+                Ok(ser::SerFrame::new_three_channel(
+                    &planes[2], &planes[1], &planes[0], timestamp,
+                ))
+            }
+        }
+    }
+}
@@ -0,0 +1,142 @@
+// Calibration frames as loaded for a run, shared by every subcommand that calibrates lights
+
+use crate::callibrary::CalibrationFiles;
+use crate::defectmap::DefectMap;
+use crate::{framesource, mean, ser};
+use anyhow::{anyhow, Result};
+use sciimg::{image, path};
+
+#[derive(Debug, Clone, Default)]
+pub struct CalibrationSet {
+    pub flat: Option<image::Image>,
+    pub dark: Option<image::Image>,
+    pub dark_flat: Option<image::Image>,
+    pub bias: Option<image::Image>,
+}
+
+fn is_image_file(file_path: &str) -> bool {
+    matches!(
+        path::get_extension(file_path)
+            .unwrap_or_default()
+            .to_uppercase()
+            .as_str(),
+        "PNG" | "TIF" | "TIFF" | "JPG" | "JPEG"
+    )
+}
+
+/// Loads a master frame. Captures (SER, AVI, FITS or frame directories) are averaged, image
+/// files are taken as they are.
+pub fn load_master(file_path: &str) -> Result<image::Image> {
+    if is_image_file(file_path) {
+        if !path::file_exists(file_path) {
+            return Err(anyhow!("File not found: {}", file_path));
+        }
+        image::Image::open_str(file_path)
+    } else {
+        if !framesource::exists(file_path) {
+            return Err(anyhow!("File not found: {}", file_path));
+        }
+        mean::compute_mean(&vec![file_path], true)
+    }
+}
+
+fn load_optional(file_path: &Option<String>) -> Result<Option<image::Image>> {
+    match file_path {
+        Some(f) => Ok(Some(load_master(f).map_err(|e| {
+            anyhow!("Failed to load calibration frame {}: {}", f, e)
+        })?)),
+        None => Ok(None),
+    }
+}
+
+impl CalibrationSet {
+    pub fn load(files: &CalibrationFiles) -> Result<CalibrationSet> {
+        Ok(CalibrationSet {
+            flat: load_optional(&files.flat)?,
+            dark: load_optional(&files.dark)?,
+            dark_flat: load_optional(&files.dark_flat)?,
+            bias: load_optional(&files.bias)?,
+        })
+    }
+
+    /// Loads from paths as given on the command line, where any may be missing
+    pub fn load_paths(
+        flat: &Option<String>,
+        dark: &Option<String>,
+        dark_flat: &Option<String>,
+        bias: &Option<String>,
+    ) -> Result<CalibrationSet> {
+        CalibrationSet::load(&CalibrationFiles {
+            flat: flat.clone(),
+            dark: dark.clone(),
+            dark_flat: dark_flat.clone(),
+            bias: bias.clone(),
+            ..Default::default()
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.flat.is_none()
+            && self.dark.is_none()
+            && self.dark_flat.is_none()
+            && self.bias.is_none()
+    }
+
+    fn frames(&self) -> [(&str, &Option<image::Image>); 4] {
+        [
+            ("Flat", &self.flat),
+            ("Dark", &self.dark),
+            ("Dark flat", &self.dark_flat),
+            ("Bias", &self.bias),
+        ]
+    }
+
+    /// Checks that every frame matches the dimensions and band count of the lights
+    pub fn validate(&self, width: usize, height: usize, num_bands: usize) -> Result<()> {
+        for (name, frame) in self.frames() {
+            if let Some(frame) = frame {
+                if frame.width != width || frame.height != height || frame.num_bands() != num_bands
+                {
+                    return Err(anyhow!(
+                        "{} frame is {}x{}x{}, lights are {}x{}x{}",
+                        name,
+                        frame.width,
+                        frame.height,
+                        frame.num_bands(),
+                        width,
+                        height,
+                        num_bands
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Corrects the defects of a pixel map in every frame, so calibration doesn't put back what
+    /// was corrected in the lights
+    pub fn correct_defects(&mut self, defect_map: &DefectMap) -> Result<()> {
+        for frame in [
+            &mut self.flat,
+            &mut self.dark,
+            &mut self.dark_flat,
+            &mut self.bias,
+        ]
+        .into_iter()
+        .flatten()
+        {
+            // Masters are combined from decoded frames
+            let color_id = if frame.num_bands() > 1 {
+                ser::ColorFormatId::Rgb
+            } else {
+                ser::ColorFormatId::Mono
+            };
+            defect_map.correct(frame, color_id)?;
+        }
+        Ok(())
+    }
+
+    pub fn apply(&self, buffer: &mut image::Image) {
+        buffer.calibrate2(&self.flat, &self.dark, &self.dark_flat, &self.bias);
+    }
+}
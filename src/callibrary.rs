@@ -0,0 +1,245 @@
+// A calibration library is a directory of master frames written by `solha master`, each with
+// its metadata alongside. Masters are matched to captures by camera, frame size, ROI position,
+// binning, bit depth, gain, exposure and sensor temperature so they don't have to be picked by hand every session.
+
+use crate::capturemeta::CaptureSettings;
+use crate::enums::CalibrationFrameType;
+use crate::master::{self, MasterMetadata};
+use anyhow::{anyhow, Result};
+use sciimg::path;
+use serde::Serialize;
+use std::fs;
+use std::path::Path;
+
+// Match score penalties. A score of 1.0 is about a 10% exposure difference or 5°C.
+const GAIN_MISMATCH_PENALTY: f32 = 10.0;
+const EXPOSURE_LOG_RATIO_WEIGHT: f32 = 10.0;
+const TEMPERATURE_STEP_DEGREES: f32 = 5.0;
+const UNKNOWN_SETTING_PENALTY: f32 = 0.5;
+
+/// The calibration frames used for a run, however they were chosen
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+pub struct CalibrationFiles {
+    pub flat: Option<String>,
+    pub dark: Option<String>,
+    pub dark_flat: Option<String>,
+    pub bias: Option<String>,
+
+    // The flat is built from the light frames themselves
+    pub synthetic_flat: bool,
+
+    // Those of the above that were picked from a calibration library
+    pub matched_from_library: Vec<String>,
+}
+
+impl CalibrationFiles {
+    fn get_mut(&mut self, frame_type: CalibrationFrameType) -> &mut Option<String> {
+        match frame_type {
+            CalibrationFrameType::Flat => &mut self.flat,
+            CalibrationFrameType::Dark => &mut self.dark,
+            CalibrationFrameType::DarkFlat => &mut self.dark_flat,
+            CalibrationFrameType::Bias => &mut self.bias,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LibraryEntry {
+    pub image_path: String,
+    pub frame_type: CalibrationFrameType,
+    pub metadata: MasterMetadata,
+}
+
+/// What a master needs to match
+#[derive(Debug, Clone)]
+pub struct MatchCriteria {
+    pub image_width: usize,
+    pub image_height: usize,
+    pub settings: CaptureSettings,
+}
+
+impl MatchCriteria {
+    pub fn for_input(input_path: &str, image_width: usize, image_height: usize) -> MatchCriteria {
+        MatchCriteria {
+            image_width,
+            image_height,
+            settings: CaptureSettings::for_input(input_path),
+        }
+    }
+}
+
+fn both<T>(a: &Option<T>, b: &Option<T>) -> Option<(T, T)>
+where
+    T: Copy,
+{
+    match (a, b) {
+        (Some(a), Some(b)) => Some((*a, *b)),
+        _ => None,
+    }
+}
+
+impl LibraryEntry {
+    /// Whether the master can be used at all: the frame size must match, as must the camera, bit
+    /// depth, ROI offset and binning where both sides record them
+    fn is_compatible(&self, criteria: &MatchCriteria) -> bool {
+        let settings = &self.metadata.settings;
+        self.metadata.image_width == criteria.image_width
+            && self.metadata.image_height == criteria.image_height
+            && match (&settings.camera, &criteria.settings.camera) {
+                (Some(a), Some(b)) => a == b,
+                _ => true,
+            }
+            && match both(&settings.pixel_depth, &criteria.settings.pixel_depth) {
+                Some((a, b)) => a == b,
+                None => true,
+            }
+            && match both(&settings.roi_offset, &criteria.settings.roi_offset) {
+                Some((a, b)) => a == b,
+                None => true,
+            }
+            && match both(&settings.binning, &criteria.settings.binning) {
+                Some((a, b)) => a == b,
+                None => true,
+            }
+    }
+
+    /// How far the master's settings are from the criteria. Lower is better.
+    fn match_score(&self, criteria: &MatchCriteria) -> f32 {
+        let settings = &self.metadata.settings;
+        let mut score = 0.0;
+
+        score += match both(&settings.gain, &criteria.settings.gain) {
+            Some((a, b)) if a != b => GAIN_MISMATCH_PENALTY,
+            Some(_) => 0.0,
+            None => UNKNOWN_SETTING_PENALTY,
+        };
+
+        // Flats and bias frames don't depend on the exposure of the lights
+        if matches!(
+            self.frame_type,
+            CalibrationFrameType::Dark | CalibrationFrameType::DarkFlat
+        ) {
+            score += match both(&settings.exposure_ms, &criteria.settings.exposure_ms) {
+                Some((a, b)) if a > 0.0 && b > 0.0 => {
+                    (a / b).ln().abs() * EXPOSURE_LOG_RATIO_WEIGHT
+                }
+                _ => UNKNOWN_SETTING_PENALTY,
+            };
+        }
+
+        score += match both(
+            &settings.sensor_temperature,
+            &criteria.settings.sensor_temperature,
+        ) {
+            Some((a, b)) => (a - b).abs() / TEMPERATURE_STEP_DEGREES,
+            None => UNKNOWN_SETTING_PENALTY,
+        };
+
+        score
+    }
+}
+
+pub struct CalibrationLibrary {
+    pub entries: Vec<LibraryEntry>,
+}
+
+impl CalibrationLibrary {
+    /// Indexes the masters in a directory. Masters without a frame type, or whose image is
+    /// missing, are skipped.
+    pub fn open(dir: &str) -> Result<CalibrationLibrary> {
+        if !Path::new(dir).is_dir() {
+            return Err(anyhow!("Calibration library not found: {}", dir));
+        }
+
+        let mut image_paths: Vec<String> = fs::read_dir(dir)?
+            .filter_map(|e| e.ok())
+            .map(|e| e.path().to_string_lossy().to_string())
+            .filter(|p| path::file_exists(&master::metadata_path(p)))
+            .collect();
+        image_paths.sort();
+
+        let mut entries = vec![];
+        for image_path in image_paths {
+            let metadata: MasterMetadata = match serde_json::from_str(&fs::read_to_string(
+                master::metadata_path(&image_path),
+            )?) {
+                Ok(m) => m,
+                Err(why) => {
+                    warn!("Unable to read metadata of {}: {}", image_path, why);
+                    continue;
+                }
+            };
+            match metadata.frame_type {
+                Some(frame_type) => entries.push(LibraryEntry {
+                    image_path,
+                    frame_type,
+                    metadata,
+                }),
+                None => warn!(
+                    "Skipping {}, its metadata doesn't say what type of frame it is",
+                    image_path
+                ),
+            }
+        }
+
+        info!("Indexed {} masters in {}", entries.len(), dir);
+        Ok(CalibrationLibrary { entries })
+    }
+
+    /// The closest compatible master of a type
+    pub fn find(
+        &self,
+        frame_type: CalibrationFrameType,
+        criteria: &MatchCriteria,
+    ) -> Option<&LibraryEntry> {
+        self.entries
+            .iter()
+            .filter(|e| e.frame_type == frame_type && e.is_compatible(criteria))
+            .map(|e| (e, e.match_score(criteria)))
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(e, _)| e)
+    }
+
+    /// Picks masters for whichever of the calibration files aren't already set. The dark flat
+    /// is matched to the flat's exposure rather than that of the lights.
+    pub fn fill_missing(&self, files: &mut CalibrationFiles, criteria: &MatchCriteria) {
+        for frame_type in [CalibrationFrameType::Dark, CalibrationFrameType::Bias] {
+            self.fill_one(files, frame_type, criteria);
+        }
+
+        // A synthetic flat needs neither a flat nor a dark flat
+        if files.synthetic_flat {
+            return;
+        }
+        self.fill_one(files, CalibrationFrameType::Flat, criteria);
+
+        let flat_criteria = match self
+            .entries
+            .iter()
+            .find(|e| Some(&e.image_path) == files.flat.as_ref())
+        {
+            Some(flat) => MatchCriteria {
+                settings: flat.metadata.settings.clone(),
+                ..criteria.clone()
+            },
+            None => criteria.clone(),
+        };
+        self.fill_one(files, CalibrationFrameType::DarkFlat, &flat_criteria);
+    }
+
+    fn fill_one(
+        &self,
+        files: &mut CalibrationFiles,
+        frame_type: CalibrationFrameType,
+        criteria: &MatchCriteria,
+    ) {
+        if files.get_mut(frame_type).is_some() {
+            return;
+        }
+        if let Some(entry) = self.find(frame_type, criteria) {
+            vprintln!("Using {:?} master {}", frame_type, entry.image_path);
+            *files.get_mut(frame_type) = Some(entry.image_path.clone());
+            files.matched_from_library.push(entry.image_path.clone());
+        }
+    }
+}
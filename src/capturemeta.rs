@@ -0,0 +1,340 @@
+// Parses the capture settings files FireCapture (`<capture>.txt`) and SharpCap
+// (`<capture>.CameraSettings.txt`) write next to each capture.
+
+use crate::{ser, timestamp};
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Duration, NaiveDate, NaiveDateTime, NaiveTime};
+use sciimg::path;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum CaptureSoftware {
+    FireCapture,
+    SharpCap,
+}
+
+#[derive(Debug, Clone)]
+pub struct CaptureMetadata {
+    pub software: CaptureSoftware,
+    pub camera: Option<String>,
+    pub filter: Option<String>,
+    pub gain: Option<f32>,
+    pub exposure_ms: Option<f32>,
+    pub sensor_temperature: Option<f32>,
+
+    // Position of the region of interest on the sensor, in pixels
+    pub roi_offset: Option<(usize, usize)>,
+
+    // Horizontal and vertical binning
+    pub binning: Option<(usize, usize)>,
+
+    // Local time minus UT, in hours (FireCapture's "LT=UT -7h" is -7.0)
+    pub utc_offset_hours: Option<f32>,
+    pub frame_count: Option<usize>,
+
+    // Capture start and end as UTC SER ticks
+    pub start_utc: Option<u64>,
+    pub end_utc: Option<u64>,
+
+    // UTC SER ticks for each frame, when the software lists them
+    pub frame_timestamps: Vec<u64>,
+
+    // Every key/value setting in the file, as written
+    pub settings: HashMap<String, String>,
+}
+
+/// The camera settings a capture was taken with, from whichever source records them
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CaptureSettings {
+    pub camera: Option<String>,
+    pub exposure_ms: Option<f32>,
+    pub gain: Option<f32>,
+    pub sensor_temperature: Option<f32>,
+    pub pixel_depth: Option<usize>,
+    pub roi_offset: Option<(usize, usize)>,
+    pub binning: Option<(usize, usize)>,
+}
+
+impl CaptureSettings {
+    /// Settings of an input file. The capture software's sidecar is preferred, with the SER
+    /// header's recorder strings filling in whatever it doesn't have.
+    pub fn for_input(input_path: &str) -> CaptureSettings {
+        let is_ser = path::get_extension(input_path)
+            .map(|e| e.to_uppercase() == "SER")
+            .unwrap_or(false);
+
+        let (header, capture_metadata, pixel_depth) = if is_ser {
+            match ser::SerFile::load_ser(input_path) {
+                Ok(ser_file) => (
+                    ser_file.header_metadata,
+                    ser_file.capture_metadata,
+                    Some(ser_file.pixel_depth),
+                ),
+                Err(_) => (ser::HeaderMetadata::default(), None, None),
+            }
+        } else if path::file_exists(input_path) && !std::path::Path::new(input_path).is_dir() {
+            (
+                ser::HeaderMetadata::default(),
+                CaptureMetadata::load_for_capture(input_path),
+                None,
+            )
+        } else {
+            (ser::HeaderMetadata::default(), None, None)
+        };
+
+        match capture_metadata {
+            Some(m) => CaptureSettings {
+                camera: m.camera.or(header.camera_model),
+                exposure_ms: m.exposure_ms.or(header.exposure_ms),
+                gain: m.gain.or(header.gain),
+                sensor_temperature: m.sensor_temperature.or(header.sensor_temperature),
+                pixel_depth,
+                roi_offset: m.roi_offset,
+                binning: m.binning,
+            },
+            None => CaptureSettings {
+                camera: header.camera_model,
+                exposure_ms: header.exposure_ms,
+                gain: header.gain,
+                sensor_temperature: header.sensor_temperature,
+                pixel_depth,
+                ..Default::default()
+            },
+        }
+    }
+}
+
+/// Parses the leading number of values such as "160 (40%)" or "46.5°C"
+fn leading_number(value: &str) -> Option<f32> {
+    let value = value.trim();
+    let end = value
+        .char_indices()
+        .find(|(i, c)| !(c.is_ascii_digit() || *c == '.' || ((*c == '-' || *c == '+') && *i == 0)))
+        .map(|(i, _)| i)
+        .unwrap_or(value.len());
+    value[..end].parse::<f32>().ok()
+}
+
+/// Parses "WxH" style pairs such as "0x0" or "2x2". A single number applies to both.
+fn parse_pair(value: &str) -> Option<(usize, usize)> {
+    match value.trim().split_once(['x', 'X']) {
+        Some((a, b)) => Some((a.trim().parse().ok()?, b.trim().parse().ok()?)),
+        None => value.trim().parse().ok().map(|v| (v, v)),
+    }
+}
+
+/// Parses FireCapture's shutter values ("1.500ms", "2s", "250µs") into milliseconds
+fn parse_shutter_ms(value: &str) -> Option<f32> {
+    let number = leading_number(value)?;
+    let value = value.trim();
+    if value.ends_with("ms") {
+        Some(number)
+    } else if value.ends_with("µs") || value.ends_with("us") {
+        Some(number / 1000.0)
+    } else if value.ends_with('s') {
+        Some(number * 1000.0)
+    } else {
+        Some(number)
+    }
+}
+
+/// Parses FireCapture's "LT=UT -7h" style offsets
+fn parse_utc_offset(value: &str) -> Option<f32> {
+    let offset = value.trim().strip_prefix("UT")?.trim();
+    if offset.is_empty() {
+        Some(0.0)
+    } else {
+        leading_number(&offset.replace(' ', ""))
+    }
+}
+
+/// Translates FireCapture's date/time format settings (ddMMyy, HHmmss...) into chrono formats
+fn chrono_format(firecapture_format: &str) -> String {
+    firecapture_format
+        .replace("yyyy", "%Y")
+        .replace("yy", "%y")
+        .replace("MM", "%m")
+        .replace("dd", "%d")
+        .replace("HH", "%H")
+        .replace("mm", "%M")
+        .replace("ss", "%S")
+}
+
+fn to_ticks(date_time: &NaiveDateTime) -> u64 {
+    timestamp::TimeStamp::from_naive_date_time(date_time).timestamp
+}
+
+impl CaptureMetadata {
+    /// Finds the settings file written alongside a capture, if there is one
+    pub fn find_for_capture(capture_path: &str) -> Option<String> {
+        let extension = path::get_extension(capture_path)?;
+        let stem = &capture_path[..capture_path.len() - extension.len() - 1];
+        [
+            format!("{}.txt", stem),
+            format!("{}.CameraSettings.txt", stem),
+        ]
+        .into_iter()
+        .find(|p| path::file_exists(p))
+    }
+
+    /// Loads the settings file written alongside a capture. Returns None if there isn't one or
+    /// if it can't be parsed.
+    pub fn load_for_capture(capture_path: &str) -> Option<CaptureMetadata> {
+        let metadata_path = CaptureMetadata::find_for_capture(capture_path)?;
+        match CaptureMetadata::load(&metadata_path) {
+            Ok(m) => {
+                info!("Loaded capture metadata from {}", metadata_path);
+                Some(m)
+            }
+            Err(why) => {
+                warn!(
+                    "Unable to parse capture metadata {}: {}",
+                    metadata_path, why
+                );
+                None
+            }
+        }
+    }
+
+    pub fn load(metadata_path: &str) -> Result<CaptureMetadata> {
+        let contents = fs::read(metadata_path)?;
+        CaptureMetadata::parse(&String::from_utf8_lossy(&contents))
+    }
+
+    pub fn parse(contents: &str) -> Result<CaptureMetadata> {
+        let first_line = contents.lines().next().unwrap_or_default().trim();
+        if first_line.starts_with("FireCapture") {
+            CaptureMetadata::parse_firecapture(contents)
+        } else if first_line.starts_with('[') && first_line.ends_with(']') {
+            CaptureMetadata::parse_sharpcap(contents)
+        } else {
+            Err(anyhow!("Unrecognized capture settings format"))
+        }
+    }
+
+    fn parse_firecapture(contents: &str) -> Result<CaptureMetadata> {
+        let mut settings = HashMap::new();
+        let mut frame_lines: Vec<(usize, String)> = vec![];
+
+        for line in contents.lines() {
+            let line = line.trim();
+            if let Some(frame) = line.strip_prefix("Frame ") {
+                // Frame N:<tab>date time
+                if let Some((num, ts)) = frame.split_once(':') {
+                    if let Ok(num) = num.trim().parse::<usize>() {
+                        frame_lines.push((num, ts.trim().to_string()));
+                        continue;
+                    }
+                }
+            }
+            if let Some((k, v)) = line.split_once('=') {
+                settings.insert(k.trim().to_string(), v.trim().to_string());
+            }
+        }
+
+        let get = |k: &str| settings.get(k).map(|v| v.as_str());
+
+        let date_format = chrono_format(get("Date_format").unwrap_or("ddMMyy"));
+        let time_format = chrono_format(get("Time_format").unwrap_or("HHmmss"));
+        let utc_offset_hours = get("LT").and_then(parse_utc_offset);
+        let offset = Duration::seconds((utc_offset_hours.unwrap_or(0.0) * 3600.0) as i64);
+
+        // Start/End are local times on the local Date. Converting those rather than reading the
+        // (UT) variants avoids guessing whether UT has rolled over to the next day.
+        let date = get("Date").and_then(|d| NaiveDate::parse_from_str(d, &date_format).ok());
+        let utc_time = |k: &str| -> Option<u64> {
+            let t = NaiveTime::parse_from_str(get(k)?, &format!("{}%.f", time_format)).ok()?;
+            Some(to_ticks(&(date?.and_time(t) - offset)))
+        };
+
+        // Frame timestamps are in local time, with the date and time in the configured formats
+        let frame_format = format!("{} {}%.f", date_format, time_format);
+        let mut frame_timestamps = vec![];
+        for (i, (num, ts)) in frame_lines.iter().enumerate() {
+            if *num != i + 1 {
+                return Err(anyhow!(
+                    "Frame timestamps are out of sequence at frame {}",
+                    num
+                ));
+            }
+            let local = NaiveDateTime::parse_from_str(ts, &frame_format)
+                .map_err(|_| anyhow!("Unable to parse timestamp of frame {}: {}", num, ts))?;
+            frame_timestamps.push(to_ticks(&(local - offset)));
+        }
+
+        Ok(CaptureMetadata {
+            software: CaptureSoftware::FireCapture,
+            camera: get("Camera").map(|v| v.to_string()),
+            filter: get("Filter").map(|v| v.to_string()),
+            gain: get("Gain").and_then(leading_number),
+            exposure_ms: get("Shutter").and_then(parse_shutter_ms),
+            sensor_temperature: get("Sensor temperature").and_then(leading_number),
+            roi_offset: get("ROI(Offset)").and_then(parse_pair),
+            binning: get("Binning").and_then(parse_pair),
+            utc_offset_hours,
+            frame_count: get("Frames captured").and_then(|v| v.parse::<usize>().ok()),
+            start_utc: utc_time("Start"),
+            end_utc: utc_time("End"),
+            frame_timestamps,
+            settings,
+        })
+    }
+
+    fn parse_sharpcap(contents: &str) -> Result<CaptureMetadata> {
+        let mut settings = HashMap::new();
+        let mut camera = None;
+
+        for line in contents.lines() {
+            let line = line.trim();
+            if line.starts_with('[') && line.ends_with(']') && camera.is_none() {
+                camera = Some(line[1..line.len() - 1].to_string());
+            } else if let Some((k, v)) = line.split_once('=') {
+                settings.insert(k.trim().to_string(), v.trim().to_string());
+            }
+        }
+
+        let get = |k: &str| settings.get(k).map(|v| v.as_str());
+
+        // SharpCap writes ISO 8601 times with an explicit offset (usually Z)
+        let utc_time = |k: &str| -> Option<u64> {
+            let dt = DateTime::parse_from_rfc3339(get(k)?).ok()?;
+            Some(to_ticks(&dt.naive_utc()))
+        };
+
+        Ok(CaptureMetadata {
+            software: CaptureSoftware::SharpCap,
+            camera,
+            filter: get("Filter").map(|v| v.to_string()),
+            gain: get("Gain").and_then(leading_number),
+            exposure_ms: get("Exposure").and_then(leading_number),
+            sensor_temperature: get("Temperature").and_then(leading_number),
+            roi_offset: None,
+            binning: get("Binning").and_then(parse_pair),
+            utc_offset_hours: None,
+            frame_count: get("FrameCount").and_then(|v| v.parse::<usize>().ok()),
+            start_utc: utc_time("StartCapture"),
+            end_utc: utc_time("EndCapture"),
+            frame_timestamps: vec![],
+            settings,
+        })
+    }
+
+    /// UTC SER ticks for a frame. Uses the per-frame list when there is one, otherwise spreads the
+    /// frames evenly between the capture start and end.
+    pub fn frame_timestamp(&self, frame_num: usize) -> Option<u64> {
+        if !self.frame_timestamps.is_empty() {
+            return self.frame_timestamps.get(frame_num).copied();
+        }
+
+        let (start, end, count) = (self.start_utc?, self.end_utc?, self.frame_count?);
+        if frame_num >= count {
+            None
+        } else if count == 1 || end < start {
+            Some(start)
+        } else {
+            Some(start + (end - start) * frame_num as u64 / (count as u64 - 1))
+        }
+    }
+}
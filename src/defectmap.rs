@@ -0,0 +1,331 @@
+// Hot and cold pixel maps. Defects are found in master darks (hot pixels) and flats (cold or
+// dead pixels) by comparing each pixel with its same-colored neighbors, then replaced in every
+// frame by the median of those neighbors. On color sensors this happens on the raw mosaic so a
+// defect isn't smeared into its neighbors by demosaicing first.
+
+use crate::framesource::{self, FrameSource};
+use crate::ser;
+use crate::util::median;
+use anyhow::{anyhow, Result};
+use rayon::prelude::*;
+use sciimg::{image, path};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs;
+
+pub const DEFAULT_WINDOW_SIZE: usize = 5;
+pub const DEFAULT_HOT_SIGMA: f32 = 6.0;
+pub const DEFAULT_COLD_SIGMA: f32 = 6.0;
+
+// Keeps near-noiseless darks from flagging every pixel a single DN off its neighbors
+const MIN_SIGMA: f32 = 1.0;
+
+pub struct DetectOptions {
+    // Width of the square neighborhood a pixel is compared with
+    pub window_size: usize,
+
+    // How far above or below the neighborhood median, in standard deviations of the residuals
+    // over the whole image, a pixel must be to be a defect
+    pub hot_sigma: f32,
+    pub cold_sigma: f32,
+}
+
+impl Default for DetectOptions {
+    fn default() -> Self {
+        DetectOptions {
+            window_size: DEFAULT_WINDOW_SIZE,
+            hot_sigma: DEFAULT_HOT_SIGMA,
+            cold_sigma: DEFAULT_COLD_SIGMA,
+        }
+    }
+}
+
+/// Sensor defect locations, saved as JSON so they can be reused across sessions
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DefectMap {
+    pub image_width: usize,
+    pub image_height: usize,
+
+    // Spacing of same-colored pixels on the sensor: 2 for Bayer and CMY mosaics, 1 otherwise
+    pub cfa_period: usize,
+    pub window_size: usize,
+    pub hot_pixels: Vec<(usize, usize)>,
+    pub cold_pixels: Vec<(usize, usize)>,
+}
+
+/// Spacing of same-colored pixels for a sensor color format
+pub fn cfa_period(color_id: ser::ColorFormatId) -> usize {
+    match color_id {
+        ser::ColorFormatId::Mono | ser::ColorFormatId::Rgb | ser::ColorFormatId::Bgr => 1,
+        _ => 2,
+    }
+}
+
+/// Pixels of the same color as (x, y) within the window, not including (x, y) itself
+fn neighbors(
+    x: usize,
+    y: usize,
+    width: usize,
+    height: usize,
+    period: usize,
+    window_size: usize,
+) -> impl Iterator<Item = (usize, usize)> {
+    let reach = (window_size / 2 / period * period) as isize;
+    (-reach..=reach)
+        .step_by(period)
+        .flat_map(move |dy| {
+            (-reach..=reach)
+                .step_by(period)
+                .map(move |dx| (x as isize + dx, y as isize + dy))
+        })
+        .filter(move |(nx, ny)| {
+            (*nx, *ny) != (x as isize, y as isize)
+                && *nx >= 0
+                && *ny >= 0
+                && *nx < width as isize
+                && *ny < height as isize
+        })
+        .map(|(nx, ny)| (nx as usize, ny as usize))
+}
+
+impl DefectMap {
+    /// Finds defects in each band of a master dark or flat. `cfa_period` is that of the sensor
+    /// if the image is a raw mosaic, or 1 otherwise.
+    pub fn detect(
+        image: &image::Image,
+        cfa_period: usize,
+        options: &DetectOptions,
+    ) -> Result<DefectMap> {
+        if options.window_size < 2 * cfa_period + 1 {
+            return Err(anyhow!(
+                "Window size {} is too small to hold same-colored neighbors (minimum {})",
+                options.window_size,
+                2 * cfa_period + 1
+            ));
+        }
+
+        let (width, height) = (image.width, image.height);
+        let mut hot = HashSet::new();
+        let mut cold = HashSet::new();
+
+        for band in 0..image.num_bands() {
+            let buffer = image.get_band(band);
+            let residuals: Vec<f32> = (0..height)
+                .into_par_iter()
+                .flat_map_iter(|y| {
+                    (0..width).map(move |x| {
+                        let mut values: Vec<f32> =
+                            neighbors(x, y, width, height, cfa_period, options.window_size)
+                                .map(|(nx, ny)| buffer.get(nx, ny))
+                                .collect();
+                        buffer.get(x, y) - median(&mut values)
+                    })
+                })
+                .collect();
+
+            let mut deviations = residuals.clone();
+            let center = median(&mut deviations);
+            deviations.iter_mut().for_each(|r| *r = (*r - center).abs());
+            let sigma = (median(&mut deviations) * 1.4826).max(MIN_SIGMA);
+
+            for (i, r) in residuals.iter().enumerate() {
+                if *r - center > options.hot_sigma * sigma {
+                    hot.insert((i % width, i / width));
+                } else if center - *r > options.cold_sigma * sigma {
+                    cold.insert((i % width, i / width));
+                }
+            }
+        }
+
+        let mut defect_map = DefectMap {
+            image_width: width,
+            image_height: height,
+            cfa_period,
+            window_size: options.window_size,
+            hot_pixels: hot.into_iter().collect(),
+            cold_pixels: cold.into_iter().collect(),
+        };
+        defect_map.sort();
+        Ok(defect_map)
+    }
+
+    /// Finds defects in a capture, averaging its raw frames first, or in a master image
+    pub fn from_input(input_file: &str, options: &DetectOptions) -> Result<DefectMap> {
+        let extension = path::get_extension(input_file)
+            .unwrap_or_default()
+            .to_uppercase();
+        if matches!(extension.as_str(), "PNG" | "TIF" | "TIFF" | "JPG" | "JPEG") {
+            // Master images are already demosaiced
+            return DefectMap::detect(&image::Image::open_str(input_file)?, 1, options);
+        }
+
+        let frame_source = framesource::open(input_file)?;
+        let mean = mean_mosaic(frame_source.as_ref())?;
+        DefectMap::detect(&mean, cfa_period(frame_source.color_format()), options)
+    }
+
+    fn sort(&mut self) {
+        self.hot_pixels.sort_by_key(|(x, y)| (*y, *x));
+        self.cold_pixels.sort_by_key(|(x, y)| (*y, *x));
+    }
+
+    /// Adds the defects of another map of the same sensor
+    pub fn merge(&mut self, other: &DefectMap) -> Result<()> {
+        if (other.image_width, other.image_height, other.cfa_period)
+            != (self.image_width, self.image_height, self.cfa_period)
+        {
+            return Err(anyhow!(
+                "Cannot merge defect maps of different sensors or modes"
+            ));
+        }
+        for (pixels, other_pixels) in [
+            (&mut self.hot_pixels, &other.hot_pixels),
+            (&mut self.cold_pixels, &other.cold_pixels),
+        ] {
+            for p in other_pixels {
+                if !pixels.contains(p) {
+                    pixels.push(*p);
+                }
+            }
+        }
+        self.window_size = self.window_size.max(other.window_size);
+        self.sort();
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.hot_pixels.len() + self.cold_pixels.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn open(file_path: &str) -> Result<DefectMap> {
+        if !path::file_exists(file_path) {
+            return Err(anyhow!("Defect map not found: {}", file_path));
+        }
+        Ok(serde_json::from_str(&fs::read_to_string(file_path)?)?)
+    }
+
+    pub fn save(&self, file_path: &str) -> Result<()> {
+        fs::write(file_path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// Replaces the defects with the median of their same-colored, non-defective neighbors.
+    /// `color_id` is the layout of the image: the sensor's mosaic format for raw frames, or Mono,
+    /// Rgb or Bgr for decoded ones. Images demosaiced from a mosaic sensor have each defect
+    /// spread over its immediate neighbors, so that whole footprint is replaced from the pixels
+    /// around it instead.
+    pub fn correct(&self, image: &mut image::Image, color_id: ser::ColorFormatId) -> Result<()> {
+        if image.width != self.image_width || image.height != self.image_height {
+            return Err(anyhow!(
+                "Defect map is for {}x{} frames, image is {}x{}",
+                self.image_width,
+                self.image_height,
+                image.width,
+                image.height
+            ));
+        }
+
+        // A map found in demosaiced frames doesn't know which color each defect was
+        let period = cfa_period(color_id);
+        if self.cfa_period < period {
+            return Err(anyhow!(
+                "Defect map was found in demosaiced frames and can't be applied to a raw mosaic"
+            ));
+        }
+        let footprint = if self.cfa_period > period { 1 } else { 0 };
+
+        let mut defects = HashSet::new();
+        for (x, y) in self.hot_pixels.iter().chain(self.cold_pixels.iter()) {
+            for dy in -footprint..=footprint {
+                for dx in -footprint..=footprint {
+                    let (fx, fy) = (*x as isize + dx, *y as isize + dy);
+                    if fx >= 0
+                        && fy >= 0
+                        && (fx as usize) < image.width
+                        && (fy as usize) < image.height
+                    {
+                        defects.insert((fx as usize, fy as usize));
+                    }
+                }
+            }
+        }
+
+        // The footprint is wider than the window would otherwise reach past it
+        let window_size = self.window_size + 2 * footprint as usize;
+        for band in 0..image.num_bands() {
+            let replacements: Vec<((usize, usize), f32)> = defects
+                .iter()
+                .filter_map(|(x, y)| {
+                    let buffer = image.get_band(band);
+                    let mut values: Vec<f32> =
+                        neighbors(*x, *y, image.width, image.height, period, window_size)
+                            .filter(|n| !defects.contains(n))
+                            .map(|(nx, ny)| buffer.get(nx, ny))
+                            .collect();
+                    if values.is_empty() {
+                        None
+                    } else {
+                        Some(((*x, *y), median(&mut values)))
+                    }
+                })
+                .collect();
+            for ((x, y), v) in replacements {
+                image.put(x, y, v, band);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Per-pixel mean of a source's frames, before demosaicing
+pub fn mean_mosaic(frame_source: &dyn FrameSource) -> Result<image::Image> {
+    let first = frame_source.read_frame_mosaic(0)?.frame.buffer;
+    let (width, height, num_bands) = (first.width, first.height, first.num_bands());
+    let plane_size = width * height;
+
+    let sums = (0..frame_source.frame_count())
+        .into_par_iter()
+        .map(|i| frame_source.read_frame_mosaic(i))
+        .try_fold(
+            || vec![0.0_f64; plane_size * num_bands],
+            |mut sums, frame| {
+                let buffer = frame?.frame.buffer;
+                for band in 0..num_bands {
+                    let plane = buffer.get_band(band);
+                    for y in 0..height {
+                        for x in 0..width {
+                            sums[band * plane_size + y * width + x] += plane.get(x, y) as f64;
+                        }
+                    }
+                }
+                Ok::<Vec<f64>, anyhow::Error>(sums)
+            },
+        )
+        .try_reduce(
+            || vec![0.0_f64; plane_size * num_bands],
+            |mut a, b| {
+                a.iter_mut().zip(b.iter()).for_each(|(a, b)| *a += b);
+                Ok(a)
+            },
+        )?;
+
+    let count = frame_source.frame_count() as f64;
+    let mut mean = image::Image::new_with_bands(width, height, num_bands, first.get_mode())?;
+    for band in 0..num_bands {
+        for y in 0..height {
+            for x in 0..width {
+                mean.put(
+                    x,
+                    y,
+                    (sums[band * plane_size + y * width + x] / count) as f32,
+                    band,
+                );
+            }
+        }
+    }
+    Ok(mean)
+}
@@ -0,0 +1,85 @@
+use anyhow::Result;
+use sciimg::{debayer, image, imagebuffer::ImageBuffer};
+
+/// Debayers a mosaic whose pattern is RGGB once shifted by `dx` columns and `dy` rows (i.e. GRBG
+/// is (1, 0), GBRG is (0, 1) and BGGR is (1, 1)). The mosaic is padded on the top and left with a
+/// mirrored row/column to get an RGGB layout, debayered, then cropped back to its original size.
+pub fn debayer_with_offset(buffer: &ImageBuffer, dx: usize, dy: usize) -> Result<image::Image> {
+    if dx == 0 && dy == 0 {
+        return debayer::debayer(buffer, debayer::DebayerMethod::AMaZE);
+    }
+
+    let padded_width = buffer.width + dx;
+    let padded_height = buffer.height + dy;
+    let mut padded =
+        ImageBuffer::new_with_fill_as_mode(padded_width, padded_height, 0.0, buffer.mode)?;
+
+    for y in 0..padded_height {
+        // Row -1 carries the same colors as row 1 in a two row repeating pattern
+        let src_y = if y < dy {
+            1.min(buffer.height - 1)
+        } else {
+            y - dy
+        };
+        for x in 0..padded_width {
+            let src_x = if x < dx {
+                1.min(buffer.width - 1)
+            } else {
+                x - dx
+            };
+            padded.put(x, y, buffer.get(src_x, src_y));
+        }
+    }
+
+    let mut debayered = debayer::debayer(&padded, debayer::DebayerMethod::AMaZE)?;
+    debayered.crop(dx, dy, buffer.width, buffer.height);
+    Ok(debayered)
+}
+
+/// Channels of a complementary (CMY) color mosaic
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum CmyChannel {
+    Cyan = 0,
+    Yellow = 1,
+    Magenta = 2,
+}
+
+/// Bilinear demosaic of a 2x2 repeating CMY mosaic into RGB. `pattern` lists the channel at each
+/// site of the 2x2 cell in row-major order. Each channel is interpolated as the mean of its samples
+/// in the surrounding 3x3 neighborhood, then converted using C = G + B, M = R + B, Y = R + G.
+pub fn demosaic_cmy(buffer: &ImageBuffer, pattern: [CmyChannel; 4]) -> Result<image::Image> {
+    let mut rgb = image::Image::new_with_bands(buffer.width, buffer.height, 3, buffer.mode)?;
+
+    for y in 0..buffer.height {
+        for x in 0..buffer.width {
+            let mut sums = [0.0_f32; 3];
+            let mut counts = [0_u32; 3];
+
+            for ny in y.saturating_sub(1)..(y + 2).min(buffer.height) {
+                for nx in x.saturating_sub(1)..(x + 2).min(buffer.width) {
+                    let channel = pattern[(ny % 2) * 2 + (nx % 2)] as usize;
+                    sums[channel] += buffer.get(nx, ny);
+                    counts[channel] += 1;
+                }
+            }
+
+            let value = |channel: CmyChannel| -> f32 {
+                let c = channel as usize;
+                if counts[c] > 0 {
+                    sums[c] / counts[c] as f32
+                } else {
+                    0.0
+                }
+            };
+            let c = value(CmyChannel::Cyan);
+            let m = value(CmyChannel::Magenta);
+            let yl = value(CmyChannel::Yellow);
+
+            rgb.put(x, y, ((yl + m - c) / 2.0).max(0.0), 0);
+            rgb.put(x, y, ((yl + c - m) / 2.0).max(0.0), 1);
+            rgb.put(x, y, ((c + m - yl) / 2.0).max(0.0), 2);
+        }
+    }
+
+    Ok(rgb)
+}
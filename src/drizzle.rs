@@ -1,3 +1,5 @@
+use crate::multipoint::WarpField;
+use crate::patchquality::PatchWeights;
 use crate::point::Point;
 use anyhow::{anyhow, Result};
 use sciimg::imagebuffer::Offset;
@@ -29,6 +31,58 @@ impl Scale {
     }
 }
 
+/// Maps a point in stacking coordinates to where it falls in a frame, by rotating about the
+/// frame center then removing the frame's offset
+struct FrameTransform {
+    mtx: Matrix,
+    center_x: f64,
+    center_y: f64,
+    offset: Offset,
+}
+
+impl FrameTransform {
+    fn new(width: usize, height: usize, offset: Offset, rotation: f64) -> FrameTransform {
+        FrameTransform {
+            mtx: Matrix::rotate(rotation, Axis::ZAxis),
+            center_x: (width / 2) as f64,
+            center_y: (height / 2) as f64,
+            offset,
+        }
+    }
+
+    fn apply(&self, x: f32, y: f32) -> Point {
+        let pt_vec = self.mtx.multiply_vector(&Vector::new(
+            x as f64 - self.center_x,
+            y as f64 - self.center_y,
+            0.0,
+        ));
+        Point {
+            x: (pt_vec.x + self.center_x) as f32 - self.offset.h,
+            y: (pt_vec.y + self.center_y) as f32 - self.offset.v,
+            valid: true,
+        }
+    }
+}
+
+/// Resamples a band into stacking coordinates at its own resolution, as the drizzle would see it
+/// with the same offset and rotation. Points falling outside the frame are zero.
+pub fn resample(other: &ImageBuffer, offset: Offset, rotation: f64) -> ImageBuffer {
+    let transform = FrameTransform::new(other.width, other.height, offset, rotation);
+    let mut out =
+        ImageBuffer::new(other.width, other.height).expect("Failed to allocate resampled buffer");
+    for y in 0..other.height {
+        for x in 0..other.width {
+            if let Some(v) = transform
+                .apply(x as f32, y as f32)
+                .get_interpolated_color(other)
+            {
+                out.put(x, y, v);
+            }
+        }
+    }
+    out
+}
+
 #[derive(Debug, Clone)]
 pub struct BilinearDrizzle {
     in_width: usize,
@@ -127,6 +181,31 @@ impl BilinearDrizzle {
         other: &Image,
         offset: Offset,
         rotation: f64,
+    ) -> Result<()> {
+        self.add_with_warp(other, offset, rotation, None)
+    }
+
+    /// Adds the image like `add_with_transform`, first displacing each point by the warp field's
+    /// local shift, for seeing distortion the global offset and rotation can't follow
+    pub fn add_with_warp(
+        &mut self,
+        other: &Image,
+        offset: Offset,
+        rotation: f64,
+        warp: Option<&WarpField>,
+    ) -> Result<()> {
+        self.add_weighted(other, offset, rotation, warp, None)
+    }
+
+    /// Adds the image like `add_with_warp`, each point counting toward the stack by the frame's
+    /// weight there, so regions are stacked only from the frames selected for them
+    pub fn add_weighted(
+        &mut self,
+        other: &Image,
+        offset: Offset,
+        rotation: f64,
+        warp: Option<&WarpField>,
+        weights: Option<&PatchWeights>,
     ) -> Result<()> {
         info!(
             "Adding drizzle frame of offset {:?} and rotation {}",
@@ -134,30 +213,31 @@ impl BilinearDrizzle {
             rotation.to_degrees()
         );
 
-        //let mut mtx = Matrix::identity();
-        let mtx = Matrix::rotate(rotation, Axis::ZAxis);
+        let transform = FrameTransform::new(other.width, other.height, offset, rotation);
 
         for y in 0..self.out_height {
             for x in 0..self.out_width {
                 let mut in_pt = self.buffer_point_to_input_point(x, y);
 
-                let mut pt_vec = Vector::new(
-                    in_pt.x as f64 - (other.width / 2) as f64,
-                    in_pt.y as f64 - (other.height / 2) as f64,
-                    0.0,
-                );
-
-                pt_vec = mtx.multiply_vector(&pt_vec);
-
-                in_pt.x = pt_vec.x as f32 + (other.width / 2) as f32;
-                in_pt.y = pt_vec.y as f32 + (other.height / 2) as f32;
+                let weight = match weights {
+                    Some(weights) => weights.at(in_pt.x, in_pt.y),
+                    None => 1.0,
+                };
+                if weight <= 0.0 {
+                    continue;
+                }
 
-                in_pt.x -= offset.h;
-                in_pt.y -= offset.v;
+                if let Some(warp) = warp {
+                    let (dx, dy) = warp.at(in_pt.x, in_pt.y);
+                    in_pt.x += dx;
+                    in_pt.y += dy;
+                }
+                let in_pt = transform.apply(in_pt.x, in_pt.y);
 
-                self.divisor.put(x, y, self.divisor.get(x, y) + 1.0);
+                self.divisor.put(x, y, self.divisor.get(x, y) + weight);
                 for band in 0..other.num_bands() {
                     if let Some(v) = in_pt.get_interpolated_color(other.get_band(band)) {
+                        let v = v * weight;
                         self.buffer
                             .put(x, y, v + self.buffer.get_band(band).get(x, y), band);
 
@@ -176,6 +256,11 @@ impl BilinearDrizzle {
         Ok(())
     }
 
+    /// Frames stacked at each output pixel, counted by their weights
+    pub fn get_weight_map(&self) -> &ImageBuffer {
+        &self.divisor
+    }
+
     pub fn get_finalized(&self) -> Result<Image> {
         if self.frame_add_count == 0 {
             Err(anyhow!(
@@ -1,3 +1,5 @@
+use serde::{Deserialize, Serialize};
+
 // Supported instruments
 #[derive(Debug, Copy, Clone, PartialEq)]
 pub enum Camera {
@@ -27,3 +29,69 @@ impl Target {
         }
     }
 }
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub enum CalibrationFrameType {
+    Bias,
+    Dark,
+    Flat,
+    DarkFlat,
+}
+
+impl CalibrationFrameType {
+    pub fn from(s: &str) -> Option<CalibrationFrameType> {
+        match s.to_uppercase().as_str() {
+            "BIAS" => Some(CalibrationFrameType::Bias),
+            "DARK" => Some(CalibrationFrameType::Dark),
+            "FLAT" => Some(CalibrationFrameType::Flat),
+            "DARKFLAT" | "DARK-FLAT" => Some(CalibrationFrameType::DarkFlat),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub enum AlignmentMethod {
+    #[default]
+    CenterOfMass,
+    PhaseCorrelation,
+    LimbFit,
+}
+
+impl AlignmentMethod {
+    pub fn from(s: &str) -> Option<AlignmentMethod> {
+        match s.to_uppercase().as_str() {
+            "COM" | "CENTER-OF-MASS" | "CENTEROFMASS" => Some(AlignmentMethod::CenterOfMass),
+            "PHASE" | "PHASE-CORRELATION" | "PHASECORRELATION" => {
+                Some(AlignmentMethod::PhaseCorrelation)
+            }
+            "LIMB" | "LIMB-FIT" | "LIMBFIT" => Some(AlignmentMethod::LimbFit),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub enum QualityMethod {
+    #[default]
+    Sigma,
+    Laplacian,
+    Tenengrad,
+    LocalContrast,
+    FftHighFrequency,
+}
+
+impl QualityMethod {
+    pub fn from(s: &str) -> Option<QualityMethod> {
+        match s.to_uppercase().as_str() {
+            "SIGMA" => Some(QualityMethod::Sigma),
+            "LAPLACIAN" => Some(QualityMethod::Laplacian),
+            "TENENGRAD" | "SOBEL" => Some(QualityMethod::Tenengrad),
+            "CONTRAST" | "LOCAL-CONTRAST" | "LOCALCONTRAST" => Some(QualityMethod::LocalContrast),
+            "FFT" | "FFT-HF" | "HIGH-FREQUENCY" | "FFTHIGHFREQUENCY" => {
+                Some(QualityMethod::FftHighFrequency)
+            }
+            _ => None,
+        }
+    }
+}
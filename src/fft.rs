@@ -0,0 +1,116 @@
+// Fast Fourier transforms, for phase correlation and frequency domain quality estimation
+
+use rayon::prelude::*;
+use sciimg::imagebuffer::ImageBuffer;
+
+// In place radix-2 FFT. The length must be a power of two.
+pub fn fft(re: &mut [f32], im: &mut [f32], inverse: bool) {
+    let n = re.len();
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            re.swap(i, j);
+            im.swap(i, j);
+        }
+    }
+
+    let sign = if inverse { 1.0 } else { -1.0 };
+    let mut len = 2;
+    while len <= n {
+        let half = len / 2;
+        let step = sign * std::f64::consts::TAU / len as f64;
+        for k in 0..half {
+            let (w_im, w_re) = (step * k as f64).sin_cos();
+            let (w_re, w_im) = (w_re as f32, w_im as f32);
+            for start in (0..n).step_by(len) {
+                let a = start + k;
+                let b = a + half;
+                let t_re = re[b] * w_re - im[b] * w_im;
+                let t_im = re[b] * w_im + im[b] * w_re;
+                re[b] = re[a] - t_re;
+                im[b] = im[a] - t_im;
+                re[a] += t_re;
+                im[a] += t_im;
+            }
+        }
+        len <<= 1;
+    }
+}
+
+fn transpose(data: &[f32], width: usize, height: usize) -> Vec<f32> {
+    let mut out = vec![0.0; data.len()];
+    for y in 0..height {
+        for x in 0..width {
+            out[x * height + y] = data[y * width + x];
+        }
+    }
+    out
+}
+
+fn fft_rows(re: &mut [f32], im: &mut [f32], width: usize, inverse: bool) {
+    re.par_chunks_mut(width)
+        .zip(im.par_chunks_mut(width))
+        .for_each(|(r, i)| fft(r, i, inverse));
+}
+
+// Two dimensional FFT over rows then columns. The inverse is scaled by 1/n.
+pub fn fft2(re: &mut Vec<f32>, im: &mut Vec<f32>, width: usize, height: usize, inverse: bool) {
+    fft_rows(re, im, width, inverse);
+    let mut re_t = transpose(re, width, height);
+    let mut im_t = transpose(im, width, height);
+    fft_rows(&mut re_t, &mut im_t, height, inverse);
+    *re = transpose(&re_t, height, width);
+    *im = transpose(&im_t, height, width);
+
+    if inverse {
+        let scale = 1.0 / (width * height) as f32;
+        re.iter_mut().chain(im.iter_mut()).for_each(|v| *v *= scale);
+    }
+}
+
+pub fn hann(i: usize, n: usize) -> f32 {
+    if n < 2 {
+        1.0
+    } else {
+        0.5 - 0.5 * (std::f32::consts::TAU * i as f32 / (n - 1) as f32).cos()
+    }
+}
+
+pub struct Spectrum {
+    pub re: Vec<f32>,
+    pub im: Vec<f32>,
+}
+
+// The buffer, less its mean and tapered to zero at the edges so the frame border doesn't
+// correlate with itself, zero padded to the transform size
+pub fn spectrum(buffer: &ImageBuffer, fft_width: usize, fft_height: usize) -> Spectrum {
+    let sum: f64 = (0..buffer.height)
+        .flat_map(|y| (0..buffer.width).map(move |x| buffer.get(x, y) as f64))
+        .sum();
+    let mean = (sum / (buffer.width * buffer.height) as f64) as f32;
+    let mut re = vec![0.0; fft_width * fft_height];
+    for y in 0..buffer.height {
+        let wy = hann(y, buffer.height);
+        for x in 0..buffer.width {
+            re[y * fft_width + x] = (buffer.get(x, y) - mean) * wy * hann(x, buffer.width);
+        }
+    }
+    let mut im = vec![0.0; fft_width * fft_height];
+    fft2(&mut re, &mut im, fft_width, fft_height, false);
+    Spectrum { re, im }
+}
+
+// Signed frequency of an FFT bin, in cycles per pixel
+pub fn frequency(k: usize, n: usize) -> f32 {
+    if k > n / 2 {
+        (k as f32 - n as f32) / n as f32
+    } else {
+        k as f32 / n as f32
+    }
+}
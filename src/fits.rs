@@ -0,0 +1,371 @@
+// Reader for FITS images and cubes (NAXIS3 = frames). Only the primary HDU is read.
+// Reference: https://fits.gsfc.nasa.gov/standard40/fits_standard40aa-le.pdf
+
+use crate::{ser, timestamp, util};
+use anyhow::{anyhow, Result};
+use chrono::{NaiveDate, NaiveDateTime};
+use memmap::Mmap;
+use sciimg::{enums::ImageMode, imagebuffer};
+use std::fs::{self, File};
+
+const BLOCK_SIZE_BYTES: usize = 2880;
+const CARD_SIZE_BYTES: usize = 80;
+
+pub struct FitsFile {
+    pub source_file: String,
+    pub bitpix: i32,
+    pub image_width: usize,
+    pub image_height: usize,
+    pub frame_count: usize,
+    pub bzero: f64,
+    pub bscale: f64,
+
+    // Factor physical values are scaled by to fit the image mode. Floating point data is usually
+    // normalized to 0..1 (or to DATAMAX), and is stretched to the 16 bit range. One for integer
+    // data and for floating point data already in sensor units.
+    pub value_scale: f64,
+
+    // Bayer pattern of the frames as they are returned (top row first), after accounting for
+    // XBAYROFF/YBAYROFF and row order. Mono if the file has no BAYERPAT.
+    pub color_id: ser::ColorFormatId,
+    pub date_obs: Option<timestamp::TimeStamp>,
+    pub headers: Vec<(String, String)>,
+    bottom_up: bool,
+    data_start: usize,
+    file_map: Mmap,
+}
+
+/// Splits an 80 character header card into its keyword and value. String values are unquoted,
+/// comments are dropped.
+fn parse_card(card: &str) -> Option<(String, String)> {
+    let keyword = card.get(0..8)?.trim().to_string();
+    if keyword.is_empty() || card.get(8..10) != Some("= ") {
+        return None;
+    }
+
+    let value_field = card.get(10..)?.trim_start();
+    let value = if let Some(quoted) = value_field.strip_prefix('\'') {
+        // Quotes inside strings are escaped by doubling them
+        let mut value = String::new();
+        let mut chars = quoted.chars().peekable();
+        while let Some(c) = chars.next() {
+            if c == '\'' {
+                if chars.peek() == Some(&'\'') {
+                    chars.next();
+                } else {
+                    break;
+                }
+            }
+            value.push(c);
+        }
+        value.trim_end().to_string()
+    } else {
+        value_field
+            .split('/')
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .to_string()
+    };
+
+    Some((keyword, value))
+}
+
+fn parse_date_obs(date_obs: &str, time_obs: Option<&String>) -> Option<timestamp::TimeStamp> {
+    let date_time = if let Ok(dt) = NaiveDateTime::parse_from_str(date_obs, "%Y-%m-%dT%H:%M:%S%.f")
+    {
+        dt
+    } else {
+        // Older files split the date and time between DATE-OBS and TIME-OBS
+        let date = NaiveDate::parse_from_str(date_obs, "%Y-%m-%d").ok()?;
+        match time_obs {
+            Some(t) => NaiveDateTime::parse_from_str(
+                &format!("{}T{}", date_obs, t),
+                "%Y-%m-%dT%H:%M:%S%.f",
+            )
+            .ok()?,
+            None => date.and_hms_opt(0, 0, 0)?,
+        }
+    };
+    Some(timestamp::TimeStamp::from_naive_date_time(&date_time))
+}
+
+/// Maps a BAYERPAT value and pattern offsets onto the equivalent SER color format
+fn bayer_color_id(pattern: &str, x_offset: usize, y_offset: usize) -> Result<ser::ColorFormatId> {
+    let (dx, dy) = match pattern.to_uppercase().as_str() {
+        "RGGB" => (0, 0),
+        "GRBG" => (1, 0),
+        "GBRG" => (0, 1),
+        "BGGR" => (1, 1),
+        _ => return Err(anyhow!("Unsupported Bayer pattern: {}", pattern)),
+    };
+
+    Ok(match ((dx + x_offset) % 2, (dy + y_offset) % 2) {
+        (0, 0) => ser::ColorFormatId::BayerRggb,
+        (1, 0) => ser::ColorFormatId::BayerGrbg,
+        (0, 1) => ser::ColorFormatId::BayerGbrg,
+        _ => ser::ColorFormatId::BayerBggr,
+    })
+}
+
+impl FitsFile {
+    pub fn open(file_path: &str) -> Result<FitsFile> {
+        let file_map = unsafe { Mmap::map(&File::open(file_path)?)? };
+
+        let mut headers: Vec<(String, String)> = vec![];
+        let mut data_start = 0;
+        let mut found_end = false;
+        while !found_end && data_start + BLOCK_SIZE_BYTES <= file_map.len() {
+            for card in file_map[data_start..data_start + BLOCK_SIZE_BYTES].chunks(CARD_SIZE_BYTES)
+            {
+                let card = String::from_utf8_lossy(card);
+                if card.trim_end() == "END" {
+                    found_end = true;
+                    break;
+                }
+                if let Some(kv) = parse_card(&card) {
+                    headers.push(kv);
+                }
+            }
+            data_start += BLOCK_SIZE_BYTES;
+        }
+
+        if headers.first().map(|(k, _)| k.as_str()) != Some("SIMPLE") || !found_end {
+            return Err(anyhow!("Not a FITS file: {}", file_path));
+        }
+
+        let get = |key: &str| headers.iter().find(|(k, _)| k == key).map(|(_, v)| v);
+        let get_num = |key: &str, default: f64| -> Result<f64> {
+            match get(key) {
+                Some(v) => v
+                    .replace('D', "E")
+                    .parse::<f64>()
+                    .map_err(|_| anyhow!("Invalid value for {}: {}", key, v)),
+                None => Ok(default),
+            }
+        };
+
+        let bitpix = get_num("BITPIX", 0.0)? as i32;
+        if ![8, 16, 32, 64, -32, -64].contains(&bitpix) {
+            return Err(anyhow!("Invalid BITPIX value: {}", bitpix));
+        }
+
+        let naxis = get_num("NAXIS", 0.0)? as usize;
+        if !(2..=3).contains(&naxis) {
+            return Err(anyhow!(
+                "FITS primary HDU has {} axes, expected an image or cube",
+                naxis
+            ));
+        }
+        let image_width = get_num("NAXIS1", 0.0)? as usize;
+        let image_height = get_num("NAXIS2", 0.0)? as usize;
+        let declared_frames = if naxis == 3 {
+            get_num("NAXIS3", 0.0)? as usize
+        } else {
+            1
+        };
+
+        // The FITS standard stores the bottom row first. Capture software writing the top row
+        // first says so with ROWORDER.
+        let bottom_up = get("ROWORDER").map(|r| r.as_str()) != Some("TOP-DOWN");
+
+        let color_id = match get("BAYERPAT") {
+            Some(pattern) => {
+                // BAYERPAT describes the data as stored. Reading bottom-up starts on stored row
+                // height - 1, which shifts the pattern by a row when the height is even.
+                let y_offset = get_num("YBAYROFF", 0.0)? as usize
+                    + if bottom_up { image_height + 1 } else { 0 };
+                bayer_color_id(pattern, get_num("XBAYROFF", 0.0)? as usize, y_offset)?
+            }
+            None => ser::ColorFormatId::Mono,
+        };
+
+        let date_obs = match get("DATE-OBS") {
+            Some(d) => match parse_date_obs(d, get("TIME-OBS")) {
+                Some(ts) => Some(ts),
+                None => {
+                    warn!("Unable to parse DATE-OBS value '{}' in {}", d, file_path);
+                    None
+                }
+            },
+            None => None,
+        };
+
+        let data_max = match get("DATAMAX") {
+            Some(_) => Some(get_num("DATAMAX", 0.0)?),
+            None => None,
+        };
+
+        let mut fits_file = FitsFile {
+            source_file: file_path.to_string(),
+            bitpix,
+            image_width,
+            image_height,
+            frame_count: declared_frames,
+            bzero: get_num("BZERO", 0.0)?,
+            bscale: get_num("BSCALE", 1.0)?,
+            value_scale: 1.0,
+            color_id,
+            date_obs,
+            headers,
+            bottom_up,
+            data_start,
+            file_map,
+        };
+
+        // Don't hand out frames the file doesn't actually contain
+        let available_frames =
+            (fits_file.file_map.len() - data_start) / fits_file.image_frame_size_bytes().max(1);
+        if available_frames < declared_frames {
+            warn!(
+                "FITS file {} declares {} frames but only contains {}",
+                file_path, declared_frames, available_frames
+            );
+            fits_file.frame_count = available_frames;
+        }
+
+        if bitpix < 0 {
+            let data_max = data_max.unwrap_or_else(|| fits_file.detect_normalized_max());
+            if data_max > 0.0 {
+                fits_file.value_scale =
+                    util::mode_max_value(fits_file.image_mode()) as f64 / data_max;
+            }
+        }
+
+        Ok(fits_file)
+    }
+
+    pub fn get_header(&self, key: &str) -> Option<&String> {
+        self.headers.iter().find(|(k, _)| k == key).map(|(_, v)| v)
+    }
+
+    pub fn bytes_per_value(&self) -> usize {
+        self.bitpix.unsigned_abs() as usize / 8
+    }
+
+    pub fn image_frame_size_bytes(&self) -> usize {
+        self.image_width * self.image_height * self.bytes_per_value()
+    }
+
+    pub fn image_mode(&self) -> ImageMode {
+        match self.bitpix {
+            8 => ImageMode::U8BIT,
+            _ => ImageMode::U16BIT,
+        }
+    }
+
+    /// Floating point data without a DATAMAX is taken as normalized to 0..1 if the first frame
+    /// stays within it. Returns 0.0 if it doesn't, leaving the values unscaled.
+    fn detect_normalized_max(&self) -> f64 {
+        if self.frame_count == 0 {
+            return 0.0;
+        }
+        match self.read_physical_values(0) {
+            Ok(values) if values.iter().all(|v| *v <= 1.0) => 1.0,
+            _ => 0.0,
+        }
+    }
+
+    /// FITS has no per-frame timestamps, so every frame of a cube is stamped with DATE-OBS.
+    /// Files without it fall back to the file modification time.
+    pub fn get_frame_timestamp(&self, frame_num: usize) -> Result<u64> {
+        if frame_num >= self.frame_count {
+            return Err(anyhow!("Frame number out of range"));
+        }
+
+        match self.date_obs {
+            Some(ts) => Ok(ts.timestamp),
+            None => Ok(timestamp::TimeStamp::from_system_time(
+                fs::metadata(&self.source_file)?.modified()?,
+            )
+            .timestamp),
+        }
+    }
+
+    pub fn get_frame_bytes(&self, frame_num: usize) -> Result<&[u8]> {
+        if frame_num >= self.frame_count {
+            return Err(anyhow!("Frame number out of range"));
+        }
+        let start = self.data_start + frame_num * self.image_frame_size_bytes();
+        Ok(&self.file_map[start..start + self.image_frame_size_bytes()])
+    }
+
+    /// Reads the values of a frame in the image mode's range, top row first
+    fn read_frame_values(&self, frame_num: usize) -> Result<Vec<f32>> {
+        info!(
+            "Extracting image frame #{} of {} from {}",
+            frame_num, self.frame_count, self.source_file
+        );
+
+        let mut values = self.read_physical_values(frame_num)?;
+        if self.value_scale != 1.0 {
+            values
+                .iter_mut()
+                .for_each(|v| *v = (*v as f64 * self.value_scale) as f32);
+        }
+        Ok(values)
+    }
+
+    /// Reads the physical (BZERO + BSCALE * stored) values of a frame, top row first
+    fn read_physical_values(&self, frame_num: usize) -> Result<Vec<f32>> {
+        let frame_bytes = self.get_frame_bytes(frame_num)?;
+
+        let raw: Vec<f64> = match self.bitpix {
+            8 => frame_bytes.iter().map(|v| *v as f64).collect(),
+            16 => frame_bytes
+                .chunks_exact(2)
+                .map(|v| i16::from_be_bytes([v[0], v[1]]) as f64)
+                .collect(),
+            32 => frame_bytes
+                .chunks_exact(4)
+                .map(|v| i32::from_be_bytes(v.try_into().unwrap()) as f64)
+                .collect(),
+            64 => frame_bytes
+                .chunks_exact(8)
+                .map(|v| i64::from_be_bytes(v.try_into().unwrap()) as f64)
+                .collect(),
+            -32 => frame_bytes
+                .chunks_exact(4)
+                .map(|v| f32::from_be_bytes(v.try_into().unwrap()) as f64)
+                .collect(),
+            _ => frame_bytes
+                .chunks_exact(8)
+                .map(|v| f64::from_be_bytes(v.try_into().unwrap()))
+                .collect(),
+        };
+
+        let mut values: Vec<f32> = Vec::with_capacity(raw.len());
+        for y in 0..self.image_height {
+            let row = if self.bottom_up {
+                self.image_height - 1 - y
+            } else {
+                y
+            };
+            values.extend(
+                raw[row * self.image_width..(row + 1) * self.image_width]
+                    .iter()
+                    .map(|v| (self.bzero + self.bscale * v) as f32),
+            );
+        }
+        Ok(values)
+    }
+
+    /// Returns a frame without debayering
+    pub fn get_frame_mosaic(&self, frame_num: usize) -> Result<ser::SerFrame> {
+        let frame_buffer = imagebuffer::ImageBuffer::from_vec_as_mode(
+            &self.read_frame_values(frame_num)?,
+            self.image_width,
+            self.image_height,
+            self.image_mode(),
+        )?;
+        Ok(ser::SerFrame::new(
+            &frame_buffer,
+            self.get_frame_timestamp(frame_num)?,
+        ))
+    }
+
+    /// Returns a frame, debayered if the file carries a BAYERPAT
+    pub fn get_frame(&self, frame_num: usize) -> Result<ser::SerFrame> {
+        self.get_frame_mosaic(frame_num)?.demosaic(self.color_id)
+    }
+}
@@ -1,11 +1,10 @@
-use crate::ser;
+use crate::framesource::{self, FrameSource};
 use anyhow::{anyhow, Result};
-use sciimg::path;
 use std::collections::HashMap;
 
 /** file pointer map */
 pub struct FpMap {
-    pub map: HashMap<String, ser::SerFile>,
+    pub map: HashMap<String, Box<dyn FrameSource>>,
 }
 
 impl Default for FpMap {
@@ -21,7 +20,7 @@ impl FpMap {
         }
     }
 
-    pub fn get_map(&self) -> &HashMap<String, ser::SerFile> {
+    pub fn get_map(&self) -> &HashMap<String, Box<dyn FrameSource>> {
         &self.map
     }
 
@@ -29,21 +28,19 @@ impl FpMap {
         self.map.contains_key(path)
     }
 
-    pub fn get_dont_open(&self, path: &String) -> Option<&ser::SerFile> {
-        self.map.get(path)
+    pub fn get_dont_open(&self, path: &String) -> Option<&dyn FrameSource> {
+        self.map.get(path).map(|s| s.as_ref())
     }
 
-    pub fn get(&mut self, path: &String) -> Option<&ser::SerFile> {
+    pub fn get(&mut self, path: &String) -> Option<&dyn FrameSource> {
         if !self.contains(path) {
-            match self.open(path) {
-                Ok(_) => {}
-                Err(e) => {
-                    panic!("Failed to open file: {}", e);
-                }
-            };
+            if let Err(e) = self.open(path) {
+                error!("Failed to open file {}: {}", path, e);
+                return None;
+            }
         }
 
-        self.map.get(path)
+        self.map.get(path).map(|s| s.as_ref())
     }
 
     pub fn open(&mut self, path: &String) -> Result<()> {
@@ -53,14 +50,13 @@ impl FpMap {
 
         info!("Opening file in fpmap: {}", path);
 
-        if !path::file_exists(path) {
-            panic!("File not found: {}", path);
+        if !framesource::exists(path) {
+            return Err(anyhow!("File not found: {}", path));
         }
 
-        match ser::SerFile::load_ser(path) {
-            Ok(ser_file) => {
-                ser_file.validate();
-                self.map.insert(path.clone(), ser_file);
+        match framesource::open(path) {
+            Ok(frame_source) => {
+                self.map.insert(path.clone(), frame_source);
                 Ok(())
             }
             Err(e) => Err(anyhow!(e)),
@@ -0,0 +1,436 @@
+use crate::{avi, fits, imageseq, ser};
+use anyhow::{anyhow, Result};
+use rayon::prelude::*;
+use sciimg::{enums::ImageMode, path};
+
+/// A decoded frame along with the file it came from and its index within that file
+pub struct SourcedFrame {
+    pub source_file: String,
+    pub frame_id: usize,
+    pub frame: ser::SerFrame,
+}
+
+/// Anything that can produce a sequence of same-sized frames (SER, AVI and FITS files, image
+/// directories, and sets of them)
+pub trait FrameSource: Sync {
+    fn frame_count(&self) -> usize;
+
+    fn image_width(&self) -> usize;
+
+    fn image_height(&self) -> usize;
+
+    /// Number of bands in the decoded frames
+    fn num_bands(&self) -> usize;
+
+    fn image_mode(&self) -> ImageMode;
+
+    fn read_frame(&self, frame_num: usize) -> Result<SourcedFrame>;
+
+    /// Layout of the sensor data. Sources that don't keep the mosaic report Mono.
+    fn color_format(&self) -> ser::ColorFormatId {
+        ser::ColorFormatId::Mono
+    }
+
+    /// Reads a frame as the sensor recorded it, before demosaicing. Sources that don't keep the
+    /// mosaic return the decoded frame.
+    fn read_frame_mosaic(&self, frame_num: usize) -> Result<SourcedFrame> {
+        self.read_frame(frame_num)
+    }
+
+    /// Whether the frames have already been rotated for field rotation, as pre-processed frames
+    /// have. Stacking doesn't rotate them again.
+    fn derotated(&self) -> bool {
+        false
+    }
+
+    /// Iterates the frames in order
+    fn frames(&self) -> Frames<'_>
+    where
+        Self: Sized,
+    {
+        Frames::new(self)
+    }
+
+    /// Iterates the frames in parallel. Being indexed, this can be limited with `take()`.
+    fn par_frames(&self) -> impl IndexedParallelIterator<Item = Result<SourcedFrame>> + '_
+    where
+        Self: Sized,
+    {
+        (0..self.frame_count())
+            .into_par_iter()
+            .map(move |i| self.read_frame(i))
+    }
+}
+
+pub struct Frames<'a> {
+    source: &'a dyn FrameSource,
+    next_frame: usize,
+}
+
+impl<'a> Frames<'a> {
+    pub fn new(source: &'a dyn FrameSource) -> Frames<'a> {
+        Frames {
+            source,
+            next_frame: 0,
+        }
+    }
+}
+
+impl Iterator for Frames<'_> {
+    type Item = Result<SourcedFrame>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.next_frame >= self.source.frame_count() {
+            None
+        } else {
+            self.next_frame += 1;
+            Some(self.source.read_frame(self.next_frame - 1))
+        }
+    }
+}
+
+impl FrameSource for ser::SerFile {
+    // Frames lost to a truncated capture are left out rather than failing to read
+    fn frame_count(&self) -> usize {
+        self.complete_frame_count()
+    }
+
+    fn image_width(&self) -> usize {
+        self.image_width
+    }
+
+    fn image_height(&self) -> usize {
+        self.image_height
+    }
+
+    fn num_bands(&self) -> usize {
+        match self.color_id {
+            ser::ColorFormatId::Mono => 1,
+            _ => 3,
+        }
+    }
+
+    fn image_mode(&self) -> ImageMode {
+        ser::SerFile::image_mode(self)
+    }
+
+    fn read_frame(&self, frame_num: usize) -> Result<SourcedFrame> {
+        Ok(SourcedFrame {
+            source_file: self.source_file.clone(),
+            frame_id: frame_num,
+            frame: self.get_frame(frame_num)?,
+        })
+    }
+
+    fn color_format(&self) -> ser::ColorFormatId {
+        self.color_id
+    }
+
+    fn read_frame_mosaic(&self, frame_num: usize) -> Result<SourcedFrame> {
+        Ok(SourcedFrame {
+            source_file: self.source_file.clone(),
+            frame_id: frame_num,
+            frame: self.get_frame_mosaic(frame_num)?,
+        })
+    }
+}
+
+impl FrameSource for avi::AviFile {
+    fn frame_count(&self) -> usize {
+        self.frame_count
+    }
+
+    fn image_width(&self) -> usize {
+        self.image_width
+    }
+
+    fn image_height(&self) -> usize {
+        self.image_height
+    }
+
+    fn num_bands(&self) -> usize {
+        match self.pixel_format {
+            avi::AviPixelFormat::Mono8 => 1,
+            avi::AviPixelFormat::Bgr24 => 3,
+        }
+    }
+
+    fn image_mode(&self) -> ImageMode {
+        ImageMode::U8BIT
+    }
+
+    fn read_frame(&self, frame_num: usize) -> Result<SourcedFrame> {
+        Ok(SourcedFrame {
+            source_file: self.source_file.clone(),
+            frame_id: frame_num,
+            frame: self.get_frame(frame_num)?,
+        })
+    }
+}
+
+impl FrameSource for fits::FitsFile {
+    fn frame_count(&self) -> usize {
+        self.frame_count
+    }
+
+    fn image_width(&self) -> usize {
+        self.image_width
+    }
+
+    fn image_height(&self) -> usize {
+        self.image_height
+    }
+
+    fn num_bands(&self) -> usize {
+        match self.color_id {
+            ser::ColorFormatId::Mono => 1,
+            _ => 3,
+        }
+    }
+
+    fn image_mode(&self) -> ImageMode {
+        fits::FitsFile::image_mode(self)
+    }
+
+    fn read_frame(&self, frame_num: usize) -> Result<SourcedFrame> {
+        Ok(SourcedFrame {
+            source_file: self.source_file.clone(),
+            frame_id: frame_num,
+            frame: self.get_frame(frame_num)?,
+        })
+    }
+
+    fn color_format(&self) -> ser::ColorFormatId {
+        self.color_id
+    }
+
+    fn read_frame_mosaic(&self, frame_num: usize) -> Result<SourcedFrame> {
+        Ok(SourcedFrame {
+            source_file: self.source_file.clone(),
+            frame_id: frame_num,
+            frame: self.get_frame_mosaic(frame_num)?,
+        })
+    }
+}
+
+impl FrameSource for imageseq::ImageSequence {
+    fn frame_count(&self) -> usize {
+        imageseq::ImageSequence::frame_count(self)
+    }
+
+    fn image_width(&self) -> usize {
+        self.image_width
+    }
+
+    fn image_height(&self) -> usize {
+        self.image_height
+    }
+
+    fn num_bands(&self) -> usize {
+        self.num_bands
+    }
+
+    fn image_mode(&self) -> ImageMode {
+        self.image_mode
+    }
+
+    fn read_frame(&self, frame_num: usize) -> Result<SourcedFrame> {
+        Ok(SourcedFrame {
+            source_file: self.source_file.clone(),
+            frame_id: frame_num,
+            frame: self.get_frame(frame_num)?,
+        })
+    }
+
+    fn derotated(&self) -> bool {
+        self.derotated
+    }
+}
+
+impl<T: FrameSource + ?Sized> FrameSource for Box<T> {
+    fn frame_count(&self) -> usize {
+        (**self).frame_count()
+    }
+
+    fn image_width(&self) -> usize {
+        (**self).image_width()
+    }
+
+    fn image_height(&self) -> usize {
+        (**self).image_height()
+    }
+
+    fn num_bands(&self) -> usize {
+        (**self).num_bands()
+    }
+
+    fn image_mode(&self) -> ImageMode {
+        (**self).image_mode()
+    }
+
+    fn read_frame(&self, frame_num: usize) -> Result<SourcedFrame> {
+        (**self).read_frame(frame_num)
+    }
+
+    fn color_format(&self) -> ser::ColorFormatId {
+        (**self).color_format()
+    }
+
+    fn read_frame_mosaic(&self, frame_num: usize) -> Result<SourcedFrame> {
+        (**self).read_frame_mosaic(frame_num)
+    }
+
+    fn derotated(&self) -> bool {
+        (**self).derotated()
+    }
+}
+
+/// Several frame sources of matching geometry, presented as one continuous sequence of frames.
+/// Frames still report the file they came from and their index within it.
+pub struct MultiFrameSource {
+    sources: Vec<Box<dyn FrameSource>>,
+}
+
+impl MultiFrameSource {
+    pub fn new(sources: Vec<Box<dyn FrameSource>>) -> Result<MultiFrameSource> {
+        if sources.is_empty() {
+            return Err(anyhow!("No frame sources specified"));
+        }
+
+        for source in sources.iter().skip(1) {
+            if source.image_width() != sources[0].image_width()
+                || source.image_height() != sources[0].image_height()
+                || source.num_bands() != sources[0].num_bands()
+            {
+                return Err(anyhow!(
+                    "Frame source dimensions {}x{}x{} do not match {}x{}x{}",
+                    source.image_width(),
+                    source.image_height(),
+                    source.num_bands(),
+                    sources[0].image_width(),
+                    sources[0].image_height(),
+                    sources[0].num_bands()
+                ));
+            }
+        }
+
+        if sources
+            .iter()
+            .any(|s| s.color_format() != sources[0].color_format())
+        {
+            return Err(anyhow!("Frame sources have different sensor color formats"));
+        }
+
+        Ok(MultiFrameSource { sources })
+    }
+
+    /// Opens each path with `open` and chains them. This lives here rather than on `FrameSource`,
+    /// which is used as a trait object: a constructor there couldn't name the type it returns,
+    /// and `frames` and `par_frames` need the sized set this gives.
+    pub fn from_paths(paths: &[&str]) -> Result<MultiFrameSource> {
+        let sources = paths
+            .iter()
+            .map(|p| open(p))
+            .collect::<Result<Vec<Box<dyn FrameSource>>>>()?;
+        MultiFrameSource::new(sources)
+    }
+
+    pub fn sources(&self) -> &[Box<dyn FrameSource>] {
+        &self.sources
+    }
+
+    // The source holding a frame, and the frame's index within it
+    fn locate(&self, frame_num: usize) -> Result<(&dyn FrameSource, usize)> {
+        let mut remaining = frame_num;
+        for source in self.sources.iter() {
+            if remaining < source.frame_count() {
+                return Ok((source.as_ref(), remaining));
+            }
+            remaining -= source.frame_count();
+        }
+        Err(anyhow!("Frame number out of range"))
+    }
+}
+
+impl FrameSource for MultiFrameSource {
+    fn frame_count(&self) -> usize {
+        self.sources.iter().map(|s| s.frame_count()).sum()
+    }
+
+    fn image_width(&self) -> usize {
+        self.sources[0].image_width()
+    }
+
+    fn image_height(&self) -> usize {
+        self.sources[0].image_height()
+    }
+
+    fn num_bands(&self) -> usize {
+        self.sources[0].num_bands()
+    }
+
+    fn image_mode(&self) -> ImageMode {
+        self.sources[0].image_mode()
+    }
+
+    fn read_frame(&self, frame_num: usize) -> Result<SourcedFrame> {
+        let (source, frame_num) = self.locate(frame_num)?;
+        source.read_frame(frame_num)
+    }
+
+    fn color_format(&self) -> ser::ColorFormatId {
+        self.sources[0].color_format()
+    }
+
+    fn read_frame_mosaic(&self, frame_num: usize) -> Result<SourcedFrame> {
+        let (source, frame_num) = self.locate(frame_num)?;
+        source.read_frame_mosaic(frame_num)
+    }
+
+    fn derotated(&self) -> bool {
+        self.sources.iter().all(|s| s.derotated())
+    }
+}
+
+/// Whether an input exists. Image sequence patterns only need their directory to exist.
+pub fn exists(file_path: &str) -> bool {
+    if imageseq::is_image_sequence(file_path) {
+        imageseq::sequence_exists(file_path)
+    } else {
+        path::file_exists(file_path)
+    }
+}
+
+/// Opens a single input as a frame source, picking the reader by file extension. Directories and
+/// wildcard patterns are read as image sequences.
+pub fn open(file_path: &str) -> Result<Box<dyn FrameSource>> {
+    if !exists(file_path) {
+        return Err(anyhow!("File not found: {}", file_path));
+    }
+
+    if imageseq::is_image_sequence(file_path) {
+        return Ok(Box::new(imageseq::ImageSequence::open(file_path)?));
+    }
+
+    match path::get_extension(file_path)
+        .unwrap_or_default()
+        .to_uppercase()
+        .as_str()
+    {
+        "SER" => {
+            let ser_file = ser::SerFile::load_ser(file_path)?;
+            let report = ser_file.validate();
+            report.warn_issues(file_path);
+            if report.complete_frames == 0 {
+                return Err(anyhow!(
+                    "SER file contains no complete frames: {}",
+                    file_path
+                ));
+            }
+            Ok(Box::new(ser_file))
+        }
+        "AVI" => Ok(Box::new(avi::AviFile::open(file_path)?)),
+        "FITS" | "FIT" | "FTS" => Ok(Box::new(fits::FitsFile::open(file_path)?)),
+        _ => Err(anyhow!("Unsupported input file type: {}", file_path)),
+    }
+}
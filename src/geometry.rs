@@ -0,0 +1,70 @@
+// Disk geometry, and the small linear solver behind the circle and limb profile fits
+
+use sciimg::image;
+
+/// Position and size of the disk in a frame
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Disk {
+    pub center_x: f32,
+    pub center_y: f32,
+    pub radius: f32,
+}
+
+impl Disk {
+    /// Finds the disk from the pixels of the first band over the threshold. The radius is that
+    /// of a circle of the same area.
+    pub fn find(image: &image::Image, threshold: f32) -> Option<Disk> {
+        let buffer = image.get_band(0);
+        let (mut sum_x, mut sum_y, mut sum_w, mut count) = (0.0_f64, 0.0_f64, 0.0_f64, 0);
+        for y in 0..image.height {
+            for x in 0..image.width {
+                let v = buffer.get(x, y);
+                if v > threshold {
+                    sum_x += x as f64 * v as f64;
+                    sum_y += y as f64 * v as f64;
+                    sum_w += v as f64;
+                    count += 1;
+                }
+            }
+        }
+        if count == 0 {
+            return None;
+        }
+        Some(Disk {
+            center_x: (sum_x / sum_w) as f32,
+            center_y: (sum_y / sum_w) as f32,
+            radius: (count as f32 / std::f32::consts::PI).sqrt(),
+        })
+    }
+
+    /// Distance of a pixel from the center
+    pub fn distance(&self, x: usize, y: usize) -> f32 {
+        ((x as f32 - self.center_x).powi(2) + (y as f32 - self.center_y).powi(2)).sqrt()
+    }
+}
+
+/// Solves a 3x3 linear system by Gaussian elimination with partial pivoting
+pub fn solve3(mut a: [[f64; 3]; 3], mut b: [f64; 3]) -> Option<[f64; 3]> {
+    for col in 0..3 {
+        let pivot = (col..3).max_by(|i, j| a[*i][col].abs().total_cmp(&a[*j][col].abs()))?;
+        if a[pivot][col].abs() < 1e-12 {
+            return None;
+        }
+        a.swap(col, pivot);
+        b.swap(col, pivot);
+        for row in col + 1..3 {
+            let f = a[row][col] / a[col][col];
+            let pivot_row = a[col];
+            for (v, p) in a[row].iter_mut().zip(pivot_row.iter()).skip(col) {
+                *v -= f * p;
+            }
+            b[row] -= f * b[col];
+        }
+    }
+
+    let mut x = [0.0; 3];
+    for row in (0..3).rev() {
+        x[row] = (b[row] - (row + 1..3).map(|k| a[row][k] * x[k]).sum::<f64>()) / a[row][row];
+    }
+    Some(x)
+}
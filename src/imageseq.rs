@@ -0,0 +1,255 @@
+// Reads a directory, or a wildcard pattern of files in a directory, of PNG/TIFF frames as a frame
+// source. This is what `solha pre-process` writes, so a pre-processed (and hand-pruned) set of
+// frames can be stacked without going back to the original capture.
+
+use crate::{ser, timestamp};
+use anyhow::{anyhow, Result};
+use chrono::NaiveDateTime;
+use sciimg::{enums::ImageMode, image, path};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+/// Name of the file, within the frame directory, that lists the capture time of each frame. Each
+/// line holds a frame file name followed by either SER ticks (100ns since 0001-01-01) or an
+/// ISO 8601 date/time. Frames it doesn't list are timestamped with their file modification time.
+pub const TIMESTAMPS_SIDECAR_FILE: &str = "timestamps.txt";
+
+/// Sidecar line marking frames that pre-process has already rotated to the initial field
+/// rotation, so stacking them doesn't derotate them a second time
+pub const DEROTATED_MARKER: &str = "# derotated";
+
+const SUPPORTED_EXTENSIONS: [&str; 3] = ["PNG", "TIF", "TIFF"];
+
+pub struct ImageSequence {
+    pub source_file: String,
+    pub frame_paths: Vec<String>,
+    pub image_width: usize,
+    pub image_height: usize,
+    pub num_bands: usize,
+    pub image_mode: ImageMode,
+
+    // The sidecar carries the derotated marker
+    pub derotated: bool,
+    timestamps: Vec<u64>,
+}
+
+fn has_wildcard(s: &str) -> bool {
+    s.contains('*') || s.contains('?')
+}
+
+/// Matches a file name against a pattern of literal characters, `*` and `?`
+fn wildcard_match(pattern: &[char], name: &[char]) -> bool {
+    match pattern.first() {
+        None => name.is_empty(),
+        Some('*') => (0..=name.len()).any(|i| wildcard_match(&pattern[1..], &name[i..])),
+        Some('?') => !name.is_empty() && wildcard_match(&pattern[1..], &name[1..]),
+        Some(c) => name.first() == Some(c) && wildcard_match(&pattern[1..], &name[1..]),
+    }
+}
+
+fn is_supported_image(file_path: &str) -> bool {
+    match path::get_extension(file_path) {
+        Some(e) => SUPPORTED_EXTENSIONS.contains(&e.to_uppercase().as_str()),
+        None => false,
+    }
+}
+
+/// Splits an input into the directory holding the frames and the file name pattern to match
+fn split_input(input: &str) -> (String, Option<String>) {
+    if has_wildcard(input) {
+        let p = Path::new(input);
+        let dir = match p.parent() {
+            Some(d) if !d.as_os_str().is_empty() => d.to_string_lossy().to_string(),
+            _ => String::from("."),
+        };
+        (dir, p.file_name().map(|f| f.to_string_lossy().to_string()))
+    } else {
+        (input.to_string(), None)
+    }
+}
+
+/// Whether an input refers to a sequence of image files rather than a single capture file
+pub fn is_image_sequence(input: &str) -> bool {
+    has_wildcard(input) || Path::new(input).is_dir()
+}
+
+/// Whether an image sequence input's directory exists
+pub fn sequence_exists(input: &str) -> bool {
+    Path::new(&split_input(input).0).is_dir()
+}
+
+fn parse_sidecar_timestamp(value: &str) -> Option<u64> {
+    if let Ok(ticks) = value.parse::<u64>() {
+        return Some(ticks);
+    }
+    NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S%.f")
+        .ok()
+        .map(|dt| timestamp::TimeStamp::from_naive_date_time(&dt).timestamp)
+}
+
+/// Reads the timestamps sidecar of a frame directory, keyed by frame file name. A missing
+/// sidecar yields an empty map.
+pub fn read_timestamps_sidecar(dir: &str) -> Result<HashMap<String, u64>> {
+    let sidecar_path = format!("{}/{}", dir, TIMESTAMPS_SIDECAR_FILE);
+    let mut timestamps = HashMap::new();
+    if !path::file_exists(&sidecar_path) {
+        return Ok(timestamps);
+    }
+
+    for (line_num, line) in fs::read_to_string(&sidecar_path)?.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        match line.rsplit_once(char::is_whitespace) {
+            Some((name, value)) => match parse_sidecar_timestamp(value.trim()) {
+                Some(ts) => {
+                    timestamps.insert(name.trim().to_string(), ts);
+                }
+                None => warn!(
+                    "Unable to parse timestamp on line {} of {}",
+                    line_num + 1,
+                    sidecar_path
+                ),
+            },
+            None => warn!(
+                "Malformed line {} in {}, expected a file name and timestamp",
+                line_num + 1,
+                sidecar_path
+            ),
+        }
+    }
+    Ok(timestamps)
+}
+
+/// Whether the timestamps sidecar of a frame directory carries the derotated marker
+pub fn sidecar_marks_derotated(dir: &str) -> Result<bool> {
+    let sidecar_path = format!("{}/{}", dir, TIMESTAMPS_SIDECAR_FILE);
+    if !path::file_exists(&sidecar_path) {
+        return Ok(false);
+    }
+    Ok(fs::read_to_string(&sidecar_path)?
+        .lines()
+        .any(|line| line.trim() == DEROTATED_MARKER))
+}
+
+/// Writes a timestamps sidecar into a frame directory listing the given (file name, SER ticks)
+/// pairs in file name order, marked if the frames were derotated
+pub fn write_timestamps_sidecar(
+    dir: &str,
+    timestamps: &[(String, u64)],
+    derotated: bool,
+) -> Result<()> {
+    let mut sorted = timestamps.to_vec();
+    sorted.sort();
+    let mut contents = if derotated {
+        format!("{}\n", DEROTATED_MARKER)
+    } else {
+        String::new()
+    };
+    contents += &sorted
+        .iter()
+        .map(|(name, ts)| format!("{} {}\n", name, ts))
+        .collect::<String>();
+    fs::write(format!("{}/{}", dir, TIMESTAMPS_SIDECAR_FILE), contents)?;
+    Ok(())
+}
+
+impl ImageSequence {
+    pub fn open(input: &str) -> Result<ImageSequence> {
+        let (dir, pattern) = split_input(input);
+        if !Path::new(&dir).is_dir() {
+            return Err(anyhow!("Directory not found: {}", dir));
+        }
+
+        let pattern: Option<Vec<char>> = pattern.map(|p| p.chars().collect());
+        let mut frame_paths: Vec<String> = fs::read_dir(&dir)?
+            .filter_map(|e| e.ok())
+            .filter(|e| e.path().is_file())
+            .map(|e| e.file_name().to_string_lossy().to_string())
+            .filter(|name| match &pattern {
+                Some(p) => wildcard_match(p, &name.chars().collect::<Vec<char>>()),
+                None => true,
+            })
+            .filter(|name| is_supported_image(name))
+            .map(|name| format!("{}/{}", dir, name))
+            .collect();
+        frame_paths.sort();
+
+        if frame_paths.is_empty() {
+            return Err(anyhow!("No PNG or TIFF frames found in {}", input));
+        }
+
+        let first_frame = image::Image::open_str(&frame_paths[0])?;
+
+        let sidecar = read_timestamps_sidecar(&dir)?;
+        let timestamps = frame_paths
+            .iter()
+            .map(|p| match sidecar.get(&path::basename(p)) {
+                Some(ts) => Ok(*ts),
+                None => Ok(
+                    timestamp::TimeStamp::from_system_time(fs::metadata(p)?.modified()?).timestamp,
+                ),
+            })
+            .collect::<Result<Vec<u64>>>()?;
+
+        Ok(ImageSequence {
+            source_file: input.to_string(),
+            frame_paths,
+            image_width: first_frame.width,
+            image_height: first_frame.height,
+            num_bands: first_frame.num_bands(),
+            image_mode: first_frame.get_mode(),
+            derotated: sidecar_marks_derotated(&dir)?,
+            timestamps,
+        })
+    }
+
+    pub fn frame_count(&self) -> usize {
+        self.frame_paths.len()
+    }
+
+    pub fn get_frame_timestamp(&self, frame_num: usize) -> Result<u64> {
+        match self.timestamps.get(frame_num) {
+            Some(ts) => Ok(*ts),
+            None => Err(anyhow!("Frame number out of range")),
+        }
+    }
+
+    pub fn get_frame(&self, frame_num: usize) -> Result<ser::SerFrame> {
+        let frame_path = match self.frame_paths.get(frame_num) {
+            Some(p) => p,
+            None => return Err(anyhow!("Frame number out of range")),
+        };
+
+        info!(
+            "Loading image frame #{} of {} from {}",
+            frame_num,
+            self.frame_count(),
+            frame_path
+        );
+
+        let buffer = image::Image::open_str(frame_path)?;
+        if buffer.width != self.image_width
+            || buffer.height != self.image_height
+            || buffer.num_bands() != self.num_bands
+        {
+            return Err(anyhow!(
+                "Frame {} is {}x{}x{}, expected {}x{}x{}",
+                frame_path,
+                buffer.width,
+                buffer.height,
+                buffer.num_bands(),
+                self.image_width,
+                self.image_height,
+                self.num_bands
+            ));
+        }
+
+        Ok(ser::SerFrame::new_rgb(
+            buffer,
+            self.get_frame_timestamp(frame_num)?,
+        ))
+    }
+}
@@ -3,19 +3,40 @@ extern crate clap;
 #[macro_use]
 extern crate stump;
 
+pub mod alignment;
+pub mod avi;
+pub mod calibration;
+pub mod callibrary;
+pub mod capturemeta;
 pub mod constants;
+pub mod defectmap;
+pub mod demosaic;
 pub mod drizzle;
 pub mod enums;
+pub mod fft;
+pub mod fits;
 pub mod fpmap;
+pub mod framesource;
+pub mod geometry;
+pub mod imageseq;
 pub mod ldcorrect;
+pub mod limbfit;
 pub mod lunar;
+pub mod master;
 pub mod mean;
+pub mod multipoint;
 pub mod parallacticangle;
 pub mod params;
+pub mod patchquality;
 pub mod point;
 pub mod processing;
+pub mod quality;
 pub mod ser;
+pub mod sercrop;
+pub mod sercut;
 pub mod solar;
+pub mod synthflat;
 pub mod threshtest;
 pub mod timestamp;
+pub mod timing;
 pub mod util;
@@ -0,0 +1,284 @@
+// Limb fitting for full disk work. The limb is the sharpest, most stable feature of the disk, so
+// a circle fitted to it anchors registration where the center of mass wanders with prominences,
+// clouds and clipping, and its radius saves measuring the disk by hand for limb darkening
+// correction and compositing.
+
+use crate::geometry::{solve3, Disk};
+use anyhow::{anyhow, Result};
+use sciimg::imagebuffer::ImageBuffer;
+use sciimg::{image, path};
+
+pub const DEFAULT_NUM_RAYS: usize = 360;
+pub const DEFAULT_RANSAC_ITERATIONS: usize = 200;
+pub const DEFAULT_INLIER_TOLERANCE: f32 = 1.5;
+pub const DEFAULT_MIN_INLIERS: usize = 16;
+
+// Step along each ray, in pixels
+const RAY_STEP: f32 = 0.5;
+
+// The limb is searched for between these fractions of the rough disk radius
+const SEARCH_INNER: f32 = 0.5;
+const SEARCH_OUTER: f32 = 1.6;
+
+// Edges weaker than this fraction of the typical edge strength are dropped before fitting
+const MIN_EDGE_STRENGTH: f32 = 0.25;
+
+#[derive(Debug, Clone, Copy)]
+pub struct LimbFitOptions {
+    // Rays cast out from the rough disk center, each giving at most one edge point
+    pub num_rays: usize,
+    pub ransac_iterations: usize,
+
+    // Distance from the circle, in pixels, within which an edge point counts as on the limb
+    pub inlier_tolerance: f32,
+    pub min_inliers: usize,
+
+    // Threshold for the rough disk the search starts from. Without one, a quarter of the way
+    // from the darkest to the brightest pixel.
+    pub obj_detect_threshold: Option<f32>,
+}
+
+impl Default for LimbFitOptions {
+    fn default() -> Self {
+        LimbFitOptions {
+            num_rays: DEFAULT_NUM_RAYS,
+            ransac_iterations: DEFAULT_RANSAC_ITERATIONS,
+            inlier_tolerance: DEFAULT_INLIER_TOLERANCE,
+            min_inliers: DEFAULT_MIN_INLIERS,
+            obj_detect_threshold: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct LimbFit {
+    pub center_x: f32,
+    pub center_y: f32,
+    pub radius: f32,
+    pub num_edge_points: usize,
+    pub num_inliers: usize,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Circle {
+    x: f64,
+    y: f64,
+    r: f64,
+}
+
+impl Circle {
+    // From x² + y² + dx + ey + f = 0
+    fn from_coefficients(c: [f64; 3]) -> Option<Circle> {
+        let x = -c[0] / 2.0;
+        let y = -c[1] / 2.0;
+        let r2 = x * x + y * y - c[2];
+        if r2 > 0.0 && r2.is_finite() {
+            Some(Circle { x, y, r: r2.sqrt() })
+        } else {
+            None
+        }
+    }
+
+    fn residual(&self, p: &(f32, f32)) -> f64 {
+        ((p.0 as f64 - self.x).hypot(p.1 as f64 - self.y) - self.r).abs()
+    }
+}
+
+// Algebraic least squares circle. Exact through three points.
+fn fit_circle(points: &[(f32, f32)]) -> Option<Circle> {
+    let mut normal = [[0.0_f64; 3]; 3];
+    let mut rhs = [0.0_f64; 3];
+    for (x, y) in points {
+        let (x, y) = (*x as f64, *y as f64);
+        let terms = [x, y, 1.0];
+        let z = -(x * x + y * y);
+        for j in 0..3 {
+            rhs[j] += terms[j] * z;
+            for k in 0..3 {
+                normal[j][k] += terms[j] * terms[k];
+            }
+        }
+    }
+    Circle::from_coefficients(solve3(normal, rhs)?)
+}
+
+// Small deterministic generator for RANSAC sampling, so fits are repeatable
+struct XorShift(u64);
+
+impl XorShift {
+    fn below(&mut self, n: usize) -> usize {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 % n as u64) as usize
+    }
+}
+
+fn sample_bilinear(buffer: &ImageBuffer, x: f32, y: f32) -> Option<f32> {
+    if x < 0.0 || y < 0.0 {
+        return None;
+    }
+    let (x0, y0) = (x.floor() as usize, y.floor() as usize);
+    if x0 + 1 >= buffer.width || y0 + 1 >= buffer.height {
+        return None;
+    }
+    let (fx, fy) = (x - x0 as f32, y - y0 as f32);
+    let top = buffer.get(x0, y0) * (1.0 - fx) + buffer.get(x0 + 1, y0) * fx;
+    let bottom = buffer.get(x0, y0 + 1) * (1.0 - fx) + buffer.get(x0 + 1, y0 + 1) * fx;
+    Some(top * (1.0 - fy) + bottom * fy)
+}
+
+/// Limb edge points, where the brightness falls off fastest along rays cast out from the rough
+/// disk center. Rays whose steepest fall is where they leave the frame are dropped, as the limb
+/// is off the frame there.
+fn find_edge_points(buffer: &ImageBuffer, disk: &Disk, num_rays: usize) -> Vec<(f32, f32)> {
+    let start = disk.radius * SEARCH_INNER;
+    let num_steps = ((disk.radius * (SEARCH_OUTER - SEARCH_INNER)) / RAY_STEP).ceil() as usize;
+
+    let mut edges: Vec<(f32, f32, f32)> = vec![];
+    for i in 0..num_rays {
+        let angle = std::f32::consts::TAU * i as f32 / num_rays as f32;
+        let (dx, dy) = (angle.cos(), angle.sin());
+        let samples: Vec<f32> = (0..num_steps)
+            .map_while(|s| {
+                let r = start + s as f32 * RAY_STEP;
+                sample_bilinear(buffer, disk.center_x + r * dx, disk.center_y + r * dy)
+            })
+            .collect();
+        if samples.len() < 5 {
+            continue;
+        }
+
+        // Brightness change over a pixel either side of each sample
+        let slopes: Vec<f32> = (2..samples.len() - 2)
+            .map(|k| samples[k + 2] - samples[k - 2])
+            .collect();
+        let mut k = 0;
+        for (i, v) in slopes.iter().enumerate() {
+            if *v < slopes[k] {
+                k = i;
+            }
+        }
+        let slope = slopes[k];
+        if slope >= 0.0 || k == 0 || k + 1 >= slopes.len() {
+            continue;
+        }
+        let clipped = samples.len() < num_steps;
+        if clipped && k + 4 >= slopes.len() {
+            continue;
+        }
+
+        let (l, c, r) = (slopes[k - 1], slopes[k], slopes[k + 1]);
+        let denom = l - 2.0 * c + r;
+        let sub = if denom.abs() > f32::EPSILON {
+            ((l - r) / (2.0 * denom)).clamp(-0.5, 0.5)
+        } else {
+            0.0
+        };
+        let radius = start + (k as f32 + 2.0 + sub) * RAY_STEP;
+        edges.push((
+            disk.center_x + radius * dx,
+            disk.center_y + radius * dy,
+            -slope,
+        ));
+    }
+
+    if edges.is_empty() {
+        return vec![];
+    }
+    let mut strengths: Vec<f32> = edges.iter().map(|e| e.2).collect();
+    strengths.sort_by(f32::total_cmp);
+    let typical = strengths[strengths.len() / 2];
+
+    edges
+        .into_iter()
+        .filter(|e| e.2 >= typical * MIN_EDGE_STRENGTH)
+        .map(|e| (e.0, e.1))
+        .collect()
+}
+
+fn auto_threshold(buffer: &ImageBuffer) -> f32 {
+    let (min, max) = (0..buffer.height)
+        .flat_map(|y| (0..buffer.width).map(move |x| buffer.get(x, y)))
+        .fold((f32::MAX, f32::MIN), |(mn, mx), v| (mn.min(v), mx.max(v)));
+    min + (max - min) * 0.25
+}
+
+impl LimbFit {
+    /// Fits a circle to the limb in the first band, robust to prominences, clouds and the
+    /// frame edge. None if there's no disk, or too few edge points agree on a circle.
+    pub fn find(image: &image::Image, options: &LimbFitOptions) -> Option<LimbFit> {
+        let buffer = image.get_band(0);
+        let threshold = options
+            .obj_detect_threshold
+            .unwrap_or_else(|| auto_threshold(buffer));
+        let disk = Disk::find(image, threshold)?;
+        if disk.radius < 2.0 {
+            return None;
+        }
+
+        let points = find_edge_points(buffer, &disk, options.num_rays);
+        if points.len() < options.min_inliers.max(3) {
+            return None;
+        }
+
+        let tolerance = options.inlier_tolerance as f64;
+        let inliers_of = |circle: &Circle| -> Vec<(f32, f32)> {
+            points
+                .iter()
+                .filter(|p| circle.residual(p) <= tolerance)
+                .copied()
+                .collect()
+        };
+
+        let mut rng = XorShift(0x9E37_79B9_7F4A_7C15);
+        let mut best: Vec<(f32, f32)> = vec![];
+        for _ in 0..options.ransac_iterations {
+            let (a, b, c) = (
+                rng.below(points.len()),
+                rng.below(points.len()),
+                rng.below(points.len()),
+            );
+            if a == b || b == c || a == c {
+                continue;
+            }
+            if let Some(circle) = fit_circle(&[points[a], points[b], points[c]]) {
+                let inliers = inliers_of(&circle);
+                if inliers.len() > best.len() {
+                    best = inliers;
+                }
+            }
+        }
+        if best.len() < options.min_inliers.max(3) {
+            return None;
+        }
+
+        // Refined over all the inliers, then once more over those of the refined circle
+        let circle = fit_circle(&best)?;
+        let inliers = inliers_of(&circle);
+        let circle = fit_circle(&inliers).unwrap_or(circle);
+
+        Some(LimbFit {
+            center_x: circle.x as f32,
+            center_y: circle.y as f32,
+            radius: circle.r as f32,
+            num_edge_points: points.len(),
+            num_inliers: inliers.len(),
+        })
+    }
+
+    /// Fits the limb of the disk in an image file, such as a finished stack
+    pub fn from_file(file_path: &str, options: &LimbFitOptions) -> Result<LimbFit> {
+        if !path::file_exists(file_path) {
+            return Err(anyhow!("File not found: {}", file_path));
+        }
+        let img = image::Image::open_str(file_path)?;
+        LimbFit::find(&img, options)
+            .ok_or_else(|| anyhow!("Unable to fit the limb in {}", file_path))
+    }
+
+    /// Fraction of the edge points on the fitted limb
+    pub fn inlier_fraction(&self) -> f32 {
+        self.num_inliers as f32 / self.num_edge_points as f32
+    }
+}
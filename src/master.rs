@@ -0,0 +1,374 @@
+// Builds master calibration frames (bias, dark, flat and dark flat) from one or more captures.
+// Rejecting outliers per pixel keeps cosmic ray hits, satellites and frames spoiled by a passing
+// cloud out of the master.
+
+use crate::capturemeta::CaptureSettings;
+use crate::enums::CalibrationFrameType;
+use crate::framesource::{FrameSource, MultiFrameSource};
+use crate::util::{median, mode_max_value};
+use anyhow::{anyhow, Result};
+use rayon::prelude::*;
+use sciimg::image;
+use serde::{Deserialize, Serialize};
+use std::fs;
+
+pub const DEFAULT_SIGMA: f32 = 3.0;
+pub const DEFAULT_MAX_MEMORY_MB: usize = 1024;
+const MAX_CLIP_ITERATIONS: usize = 5;
+
+// Normalized flats are scaled to a mean of this fraction of the pixel range when written, as
+// the output formats hold integers
+pub const NORMALIZED_FLAT_LEVEL: f32 = 0.5;
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub enum CombineMethod {
+    Mean,
+    Median,
+    SigmaClip,
+    Winsorized,
+}
+
+impl CombineMethod {
+    pub fn from(s: &str) -> Option<CombineMethod> {
+        match s.to_uppercase().as_str() {
+            "MEAN" => Some(CombineMethod::Mean),
+            "MEDIAN" => Some(CombineMethod::Median),
+            "SIGMA-CLIP" | "SIGMACLIP" => Some(CombineMethod::SigmaClip),
+            "WINSORIZED" => Some(CombineMethod::Winsorized),
+            _ => None,
+        }
+    }
+}
+
+pub struct MasterOptions {
+    pub method: CombineMethod,
+
+    // What the master is for, so a calibration library can match it up with captures
+    pub frame_type: Option<CalibrationFrameType>,
+
+    // Rejection bounds, in standard deviations below and above the median
+    pub sigma_low: f32,
+    pub sigma_high: f32,
+
+    // Master bias subtracted from every frame before combining (for flats)
+    pub bias: Option<image::Image>,
+
+    // Scale the master to a uniform mean (for flats)
+    pub normalize: bool,
+
+    // Upper bound on the frame data held at once while combining, in megabytes
+    pub max_memory_mb: usize,
+}
+
+impl Default for MasterOptions {
+    fn default() -> Self {
+        MasterOptions {
+            method: CombineMethod::SigmaClip,
+            frame_type: None,
+            sigma_low: DEFAULT_SIGMA,
+            sigma_high: DEFAULT_SIGMA,
+            bias: None,
+            normalize: false,
+            max_memory_mb: DEFAULT_MAX_MEMORY_MB,
+        }
+    }
+}
+
+/// Describes how a master was made. Written as JSON alongside the master image.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MasterMetadata {
+    pub method: CombineMethod,
+    #[serde(default)]
+    pub frame_type: Option<CalibrationFrameType>,
+    pub image_width: usize,
+    pub image_height: usize,
+    pub frame_count: usize,
+    pub source_files: Vec<String>,
+    pub settings: CaptureSettings,
+    pub bias_subtracted: bool,
+    pub normalized: bool,
+
+    // Share of pixel values rejected (sigma clipping) or clamped (winsorizing)
+    pub rejected_fraction: f32,
+}
+
+pub struct MasterFrame {
+    pub image: image::Image,
+    pub metadata: MasterMetadata,
+}
+
+/// Path of the metadata file written alongside a master image
+pub fn metadata_path(image_path: &str) -> String {
+    format!("{}.json", image_path)
+}
+
+fn mean(values: &[f32]) -> f32 {
+    values.iter().sum::<f32>() / values.len() as f32
+}
+
+fn stddev(values: &[f32], center: f32) -> f32 {
+    (values.iter().map(|v| (v - center).powi(2)).sum::<f32>() / values.len() as f32).sqrt()
+}
+
+/// Spread of the values from the median absolute deviation. Unlike the standard deviation, a
+/// single wild value can't inflate it enough to escape rejection in a small stack. Falls back
+/// to the standard deviation when most values are identical.
+fn robust_sigma(values: &[f32], center: f32) -> f32 {
+    let mut deviations: Vec<f32> = values.iter().map(|v| (v - center).abs()).collect();
+    let mad = median(&mut deviations);
+    if mad > 0.0 {
+        mad * 1.4826
+    } else {
+        stddev(values, center)
+    }
+}
+
+/// Combines one pixel's values across frames, returning the combined value and the number of
+/// values rejected or clamped. The values are reordered.
+pub fn combine_values(
+    values: &mut [f32],
+    method: CombineMethod,
+    low: f32,
+    high: f32,
+) -> (f32, usize) {
+    if values.is_empty() {
+        return (0.0, 0);
+    }
+
+    match method {
+        CombineMethod::Mean => (mean(values), 0),
+        CombineMethod::Median => (median(values), 0),
+        CombineMethod::SigmaClip => {
+            let mut kept = values.len();
+            for _ in 0..MAX_CLIP_ITERATIONS {
+                let center = median(&mut values[..kept]);
+                let sigma = robust_sigma(&values[..kept], center);
+                let (lower, upper) = (center - low * sigma, center + high * sigma);
+
+                // Values are sorted by median(), so the survivors are a contiguous run
+                let start = values[..kept].iter().take_while(|v| **v < lower).count();
+                let end = kept
+                    - values[..kept]
+                        .iter()
+                        .rev()
+                        .take_while(|v| **v > upper)
+                        .count();
+                if start == 0 && end == kept {
+                    break;
+                }
+                values.copy_within(start..end, 0);
+                kept = end - start;
+            }
+            (mean(&values[..kept]), values.len() - kept)
+        }
+        CombineMethod::Winsorized => {
+            let original: Vec<f32> = values.to_vec();
+            for _ in 0..MAX_CLIP_ITERATIONS {
+                let center = median(values);
+                let sigma = robust_sigma(values, center);
+                let (lower, upper) = (center - low * sigma, center + high * sigma);
+
+                let mut changed = false;
+                for v in values.iter_mut() {
+                    let clamped = v.clamp(lower, upper);
+                    changed |= clamped != *v;
+                    *v = clamped;
+                }
+                if !changed {
+                    break;
+                }
+            }
+
+            let mut sorted_original = original;
+            sorted_original.sort_by(f32::total_cmp);
+            let clamped = sorted_original
+                .iter()
+                .zip(values.iter())
+                .filter(|(a, b)| a != b)
+                .count();
+            (mean(values), clamped)
+        }
+    }
+}
+
+/// Combines every frame of the inputs into a master frame
+pub fn build_master(input_files: &[&str], options: &MasterOptions) -> Result<MasterFrame> {
+    let frame_source = MultiFrameSource::from_paths(input_files)?;
+
+    if let Some(bias) = &options.bias {
+        if bias.width != frame_source.image_width()
+            || bias.height != frame_source.image_height()
+            || bias.num_bands() != frame_source.num_bands()
+        {
+            return Err(anyhow!(
+                "Bias frame is {}x{}x{}, frames are {}x{}x{}",
+                bias.width,
+                bias.height,
+                bias.num_bands(),
+                frame_source.image_width(),
+                frame_source.image_height(),
+                frame_source.num_bands()
+            ));
+        }
+    }
+
+    let frame_count = frame_source.frame_count();
+    if frame_count == 0 {
+        return Err(anyhow!("No frames to combine"));
+    }
+    vprintln!("Combining {} frames with {:?}", frame_count, options.method);
+
+    let width = frame_source.image_width();
+    let height = frame_source.image_height();
+    let num_bands = frame_source.num_bands();
+
+    // Frames are combined a strip of rows at a time, holding only that strip of every frame, so
+    // long captures fit in memory. Each frame is read once per strip.
+    let row_bytes = frame_count * width * num_bands * std::mem::size_of::<f32>();
+    let strip_height = (options.max_memory_mb * 1024 * 1024 / row_bytes).clamp(1, height);
+    if strip_height < height {
+        vprintln!(
+            "Combining in strips of {} rows to stay within {} MB",
+            strip_height,
+            options.max_memory_mb
+        );
+    }
+
+    let mode = frame_source.image_mode();
+    let mut master = image::Image::new_with_bands(width, height, num_bands, mode)?;
+    let mut rejected = 0;
+    for y0 in (0..height).step_by(strip_height) {
+        let y1 = (y0 + strip_height).min(height);
+        let rows_in_strip = y1 - y0;
+
+        // Each frame's strip, band by band and row by row
+        let strips: Vec<Vec<f32>> = frame_source
+            .par_frames()
+            .map(|f| {
+                f.map(|sourced_frame| {
+                    let buffer = &sourced_frame.frame.buffer;
+                    (0..num_bands)
+                        .flat_map(|band| {
+                            (y0..y1).flat_map(move |y| {
+                                (0..width).map(move |x| buffer.get_band(band).get(x, y))
+                            })
+                        })
+                        .collect()
+                })
+            })
+            .collect::<Result<Vec<Vec<f32>>>>()?;
+
+        // Each row is combined independently. Rows come back as (band values, rejected count).
+        let rows: Vec<(Vec<Vec<f32>>, usize)> = (y0..y1)
+            .into_par_iter()
+            .map(|y| {
+                let mut values = vec![0.0; frame_count];
+                let mut rejected = 0;
+                let band_rows = (0..num_bands)
+                    .map(|band| {
+                        let row_start = (band * rows_in_strip + y - y0) * width;
+                        (0..width)
+                            .map(|x| {
+                                let bias_value = match &options.bias {
+                                    Some(bias) => bias.get_band(band).get(x, y),
+                                    None => 0.0,
+                                };
+                                for (i, strip) in strips.iter().enumerate() {
+                                    values[i] = strip[row_start + x] - bias_value;
+                                }
+                                let (v, r) = combine_values(
+                                    &mut values,
+                                    options.method,
+                                    options.sigma_low,
+                                    options.sigma_high,
+                                );
+                                rejected += r;
+                                v
+                            })
+                            .collect::<Vec<f32>>()
+                    })
+                    .collect::<Vec<Vec<f32>>>();
+                (band_rows, rejected)
+            })
+            .collect();
+
+        for (row, (band_rows, r)) in rows.iter().enumerate() {
+            rejected += r;
+            for (band, values) in band_rows.iter().enumerate() {
+                for (x, v) in values.iter().enumerate() {
+                    master.put(x, y0 + row, v.max(0.0), band);
+                }
+            }
+        }
+    }
+
+    if options.normalize {
+        // Each band is scaled on its own, so flats of color sensors also even out the color balance
+        let target = mode_max_value(mode) * NORMALIZED_FLAT_LEVEL;
+        for band in 0..num_bands {
+            let band_values: Vec<f32> = (0..height)
+                .flat_map(|y| (0..width).map(move |x| (x, y)))
+                .map(|(x, y)| master.get_band(band).get(x, y))
+                .collect();
+            let band_mean = mean(&band_values);
+            if band_mean <= 0.0 {
+                return Err(anyhow!("Cannot normalize a flat with a mean of zero"));
+            }
+            master.apply_weight_on_band(target / band_mean, band);
+        }
+    }
+
+    // Sensor temperature drifts over a session, so the master records the average
+    let all_settings: Vec<CaptureSettings> = input_files
+        .iter()
+        .map(|f| CaptureSettings::for_input(f))
+        .collect();
+    let settings = all_settings[0].clone();
+    for (f, s) in input_files.iter().zip(all_settings.iter()).skip(1) {
+        if s.exposure_ms != settings.exposure_ms || s.gain != settings.gain {
+            warn!(
+                "{} was captured with different exposure or gain than {}",
+                f, input_files[0]
+            );
+        }
+    }
+    let temperatures: Vec<f32> = all_settings
+        .iter()
+        .filter_map(|s| s.sensor_temperature)
+        .collect();
+
+    Ok(MasterFrame {
+        image: master,
+        metadata: MasterMetadata {
+            method: options.method,
+            frame_type: options.frame_type,
+            image_width: width,
+            image_height: height,
+            frame_count,
+            source_files: input_files.iter().map(|f| f.to_string()).collect(),
+            settings: CaptureSettings {
+                sensor_temperature: if temperatures.is_empty() {
+                    None
+                } else {
+                    Some(mean(&temperatures))
+                },
+                ..settings
+            },
+            bias_subtracted: options.bias.is_some(),
+            normalized: options.normalize,
+            rejected_fraction: rejected as f32 / (frame_count * width * height * num_bands) as f32,
+        },
+    })
+}
+
+impl MasterFrame {
+    /// Writes the master image, and its metadata to `<output>.json`
+    pub fn save(&self, output_file: &str) -> Result<()> {
+        self.image.save(output_file)?;
+        fs::write(
+            metadata_path(output_file),
+            serde_json::to_string_pretty(&self.metadata)?,
+        )?;
+        Ok(())
+    }
+}
@@ -0,0 +1,312 @@
+// Multi-point alignment. Seeing bends different parts of a frame different ways, so a single
+// offset per frame leaves the stack blurred away from wherever that offset was measured. A grid
+// of alignment patches is laid over a reference stacked from the best frames, each frame's local
+// shift is measured at every patch, and the shifts are smoothed into a warp field the drizzle
+// follows.
+
+use rayon::prelude::*;
+use sciimg::imagebuffer::ImageBuffer;
+
+pub const DEFAULT_PATCH_SIZE: usize = 48;
+pub const DEFAULT_SEARCH_RADIUS: usize = 4;
+pub const DEFAULT_REFERENCE_FRAMES: usize = 16;
+pub const DEFAULT_MIN_CONTRAST: f32 = 0.2;
+
+// Smaller patches hold too little detail to register reliably
+pub const MIN_PATCH_SIZE: usize = 16;
+
+// Weight of the no-shift prior against the measured patches, so the field fades to zero away
+// from them rather than extrapolating
+const PRIOR_WEIGHT: f32 = 0.25;
+
+#[derive(Debug, Clone, Copy)]
+pub struct MultiPointOptions {
+    // Patch width and height, in pixels. Patches are laid out on a grid at half this spacing.
+    pub patch_size: usize,
+
+    // Furthest a patch is searched for from where global alignment puts it, in pixels
+    pub search_radius: usize,
+
+    // Best frames, after global alignment, averaged into the reference
+    pub reference_frames: usize,
+
+    // Patches with less contrast than this fraction of the most contrasted patch are left out.
+    // Featureless patches (sky, the flat middle of the disk) give no shift to measure.
+    pub min_contrast: f32,
+}
+
+impl Default for MultiPointOptions {
+    fn default() -> Self {
+        MultiPointOptions {
+            patch_size: DEFAULT_PATCH_SIZE,
+            search_radius: DEFAULT_SEARCH_RADIUS,
+            reference_frames: DEFAULT_REFERENCE_FRAMES,
+            min_contrast: DEFAULT_MIN_CONTRAST,
+        }
+    }
+}
+
+/// Shift of a frame's content at one patch, relative to the reference
+#[derive(Debug, Clone, Copy)]
+pub struct LocalShift {
+    pub x: f32,
+    pub y: f32,
+    pub dx: f32,
+    pub dy: f32,
+}
+
+/// Smooth shift field sampled on a grid of nodes, bilinearly interpolated in between
+#[derive(Debug, Clone)]
+pub struct WarpField {
+    spacing: f32,
+    cols: usize,
+    rows: usize,
+    dx: Vec<f32>,
+    dy: Vec<f32>,
+}
+
+impl WarpField {
+    /// Spreads the measured shifts over the grid with a Gaussian the width of the node spacing
+    pub fn from_shifts(
+        width: usize,
+        height: usize,
+        spacing: usize,
+        shifts: &[LocalShift],
+    ) -> WarpField {
+        let spacing = spacing.max(1);
+        let cols = width / spacing + 2;
+        let rows = height / spacing + 2;
+        let sigma_sq = (spacing * spacing) as f32;
+
+        let mut dx = vec![0.0; cols * rows];
+        let mut dy = vec![0.0; cols * rows];
+        for row in 0..rows {
+            for col in 0..cols {
+                let (nx, ny) = ((col * spacing) as f32, (row * spacing) as f32);
+                let (mut sx, mut sy, mut sw) = (0.0, 0.0, PRIOR_WEIGHT);
+                for s in shifts {
+                    let d_sq = (s.x - nx).powi(2) + (s.y - ny).powi(2);
+                    let w = (-d_sq / (2.0 * sigma_sq)).exp();
+                    sx += w * s.dx;
+                    sy += w * s.dy;
+                    sw += w;
+                }
+                dx[row * cols + col] = sx / sw;
+                dy[row * cols + col] = sy / sw;
+            }
+        }
+
+        WarpField {
+            spacing: spacing as f32,
+            cols,
+            rows,
+            dx,
+            dy,
+        }
+    }
+
+    /// The shift at a point
+    pub fn at(&self, x: f32, y: f32) -> (f32, f32) {
+        let gx = (x / self.spacing).clamp(0.0, (self.cols - 1) as f32);
+        let gy = (y / self.spacing).clamp(0.0, (self.rows - 1) as f32);
+        let (c0, r0) = (gx.floor() as usize, gy.floor() as usize);
+        let (c1, r1) = ((c0 + 1).min(self.cols - 1), (r0 + 1).min(self.rows - 1));
+        let (fx, fy) = (gx - c0 as f32, gy - r0 as f32);
+
+        let lerp = |v: &[f32]| {
+            let top = v[r0 * self.cols + c0] * (1.0 - fx) + v[r0 * self.cols + c1] * fx;
+            let bottom = v[r1 * self.cols + c0] * (1.0 - fx) + v[r1 * self.cols + c1] * fx;
+            top * (1.0 - fy) + bottom * fy
+        };
+        (lerp(&self.dx), lerp(&self.dy))
+    }
+}
+
+// Summed-area table, for the mean of any window in constant time
+struct IntegralImage {
+    stride: usize,
+    sums: Vec<f64>,
+}
+
+impl IntegralImage {
+    fn new(buffer: &ImageBuffer) -> IntegralImage {
+        let stride = buffer.width + 1;
+        let mut sums = vec![0.0_f64; stride * (buffer.height + 1)];
+        for y in 0..buffer.height {
+            let mut row = 0.0;
+            for x in 0..buffer.width {
+                row += buffer.get(x, y) as f64;
+                sums[(y + 1) * stride + x + 1] = sums[y * stride + x + 1] + row;
+            }
+        }
+        IntegralImage { stride, sums }
+    }
+
+    fn mean(&self, x: usize, y: usize, size: usize) -> f32 {
+        let s = self.stride;
+        let total = self.sums[(y + size) * s + x + size]
+            - self.sums[y * s + x + size]
+            - self.sums[(y + size) * s + x]
+            + self.sums[y * s + x];
+        (total / (size * size) as f64) as f32
+    }
+}
+
+// Zero mean patch pixels, so brightness changes between frames don't read as mismatch
+fn patch_values(buffer: &ImageBuffer, x: usize, y: usize, size: usize) -> Vec<f32> {
+    let mut values = Vec::with_capacity(size * size);
+    for py in 0..size {
+        for px in 0..size {
+            values.push(buffer.get(x + px, y + py));
+        }
+    }
+    let mean = values.iter().map(|v| *v as f64).sum::<f64>() as f32 / values.len() as f32;
+    values.iter_mut().for_each(|v| *v -= mean);
+    values
+}
+
+fn stddev(values: &[f32]) -> f32 {
+    (values.iter().map(|v| (*v as f64).powi(2)).sum::<f64>() / values.len() as f64).sqrt() as f32
+}
+
+// Vertex of the parabola through three samples, relative to the middle one
+fn parabolic_min(left: f64, center: f64, right: f64) -> f32 {
+    let denom = left - 2.0 * center + right;
+    if denom <= 0.0 {
+        0.0
+    } else {
+        ((left - right) / (2.0 * denom)).clamp(-0.5, 0.5) as f32
+    }
+}
+
+struct Patch {
+    x: usize,
+    y: usize,
+    values: Vec<f32>,
+}
+
+/// Measures local shifts of globally aligned frames against a reference, at each of the
+/// patches with enough detail to register
+pub struct MultiPointAligner {
+    options: MultiPointOptions,
+    width: usize,
+    height: usize,
+    patches: Vec<Patch>,
+}
+
+impl MultiPointAligner {
+    /// The reference is in stacking coordinates at input resolution, as `drizzle::resample`
+    /// gives
+    pub fn new(reference: &ImageBuffer, options: &MultiPointOptions) -> MultiPointAligner {
+        let size = options.patch_size;
+        let step = (size / 2).max(1);
+        let margin = options.search_radius;
+
+        // Patches keep clear of the frame edge by the search radius, so every candidate shift
+        // stays on the frame
+        let mut candidates = vec![];
+        if reference.width >= size + 2 * margin && reference.height >= size + 2 * margin {
+            for y in (margin..=reference.height - size - margin).step_by(step) {
+                for x in (margin..=reference.width - size - margin).step_by(step) {
+                    let values = patch_values(reference, x, y, size);
+                    candidates.push((stddev(&values), Patch { x, y, values }));
+                }
+            }
+        }
+
+        let max_contrast = candidates.iter().fold(0.0_f32, |m, c| m.max(c.0));
+        let patches: Vec<Patch> = candidates
+            .into_iter()
+            .filter(|c| max_contrast > 0.0 && c.0 >= max_contrast * options.min_contrast)
+            .map(|c| c.1)
+            .collect();
+        info!("Using {} alignment patches", patches.len());
+
+        MultiPointAligner {
+            options: *options,
+            width: reference.width,
+            height: reference.height,
+            patches,
+        }
+    }
+
+    pub fn num_patches(&self) -> usize {
+        self.patches.len()
+    }
+
+    // Sum of squared differences between the patch and the frame window shifted by (sx, sy)
+    fn ssd(
+        &self,
+        patch: &Patch,
+        frame: &ImageBuffer,
+        means: &IntegralImage,
+        sx: isize,
+        sy: isize,
+    ) -> f64 {
+        let size = self.options.patch_size;
+        let x = (patch.x as isize + sx) as usize;
+        let y = (patch.y as isize + sy) as usize;
+        let mean = means.mean(x, y, size);
+        let mut sum = 0.0_f64;
+        for py in 0..size {
+            for px in 0..size {
+                let d = frame.get(x + px, y + py) - mean - patch.values[py * size + px];
+                sum += (d * d) as f64;
+            }
+        }
+        sum
+    }
+
+    /// Local shifts of a globally aligned frame, by block matching each patch within the
+    /// search radius. Patches whose best match is at the edge of the search are dropped, as the
+    /// true shift may lie beyond it.
+    pub fn measure(&self, frame: &ImageBuffer) -> Vec<LocalShift> {
+        if frame.width != self.width || frame.height != self.height {
+            return vec![];
+        }
+        let means = IntegralImage::new(frame);
+        let size = self.options.patch_size;
+        let r = self.options.search_radius as isize;
+
+        self.patches
+            .par_iter()
+            .filter_map(|patch| {
+                let side = (2 * r + 1) as usize;
+                let mut costs = vec![0.0_f64; side * side];
+                for sy in -r..=r {
+                    for sx in -r..=r {
+                        costs[((sy + r) as usize) * side + (sx + r) as usize] =
+                            self.ssd(patch, frame, &means, sx, sy);
+                    }
+                }
+                let best = (0..costs.len()).min_by(|a, b| costs[*a].total_cmp(&costs[*b]))?;
+                let (bx, by) = (best % side, best / side);
+                if bx == 0 || by == 0 || bx == side - 1 || by == side - 1 {
+                    return None;
+                }
+                let at = |x: usize, y: usize| costs[y * side + x];
+                let sub_x = parabolic_min(at(bx - 1, by), at(bx, by), at(bx + 1, by));
+                let sub_y = parabolic_min(at(bx, by - 1), at(bx, by), at(bx, by + 1));
+
+                Some(LocalShift {
+                    x: (patch.x + size / 2) as f32,
+                    y: (patch.y + size / 2) as f32,
+                    dx: bx as f32 - r as f32 + sub_x,
+                    dy: by as f32 - r as f32 + sub_y,
+                })
+            })
+            .collect()
+    }
+
+    /// The warp field of a globally aligned frame
+    pub fn warp_field(&self, frame: &ImageBuffer) -> (WarpField, Vec<LocalShift>) {
+        let shifts = self.measure(frame);
+        let field = WarpField::from_shifts(
+            self.width,
+            self.height,
+            (self.options.patch_size / 2).max(1),
+            &shifts,
+        );
+        (field, shifts)
+    }
+}
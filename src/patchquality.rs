@@ -0,0 +1,187 @@
+// Local quality selection. Seeing varies across the disk, so the frames sharpest in one region
+// are often not the sharpest in another. Every frame is scored at each patch of a grid over the
+// stacked field, and each patch is stacked only from the frames sharpest there.
+
+use crate::enums::QualityMethod;
+use crate::quality::{self, QualityMetric};
+use sciimg::imagebuffer::ImageBuffer;
+
+pub const DEFAULT_PATCH_SIZE: usize = 64;
+pub const DEFAULT_TOP_PERCENTAGE: u8 = 25;
+pub const DEFAULT_MIN_RELATIVE_QUALITY: f32 = 0.5;
+
+// Sigma shifts with the brightness of a patch, which haze and passing cloud change from frame to
+// frame, so patches are scored relative to their brightness unless asked otherwise
+pub const DEFAULT_QUALITY_METHOD: QualityMethod = QualityMethod::Laplacian;
+
+// Smaller patches are scored more by noise than by detail
+pub const MIN_PATCH_SIZE: usize = 8;
+
+#[derive(Debug, Clone, Copy)]
+pub struct PatchQualityOptions {
+    // Patch width and height, in pixels
+    pub patch_size: usize,
+
+    // Percentage (1 - 100) of the frames stacked at each patch, the sharpest there
+    pub top_percentage: u8,
+
+    // Frames scoring less than this fraction of the sharpest frame at a patch are left out of it
+    // even when within the top percentage, so regions that were only briefly steady are stacked
+    // from fewer frames
+    pub min_relative_quality: f32,
+
+    // Metric patches are scored with
+    pub quality_method: QualityMethod,
+}
+
+impl Default for PatchQualityOptions {
+    fn default() -> Self {
+        PatchQualityOptions {
+            patch_size: DEFAULT_PATCH_SIZE,
+            top_percentage: DEFAULT_TOP_PERCENTAGE,
+            min_relative_quality: DEFAULT_MIN_RELATIVE_QUALITY,
+            quality_method: DEFAULT_QUALITY_METHOD,
+        }
+    }
+}
+
+impl PatchQualityOptions {
+    pub fn metric(&self) -> Box<dyn QualityMetric> {
+        quality::metric(self.quality_method)
+    }
+}
+
+/// Square patches tiling the stacked field. Those on the right and bottom edges may be cut short.
+#[derive(Debug, Clone, Copy)]
+pub struct PatchGrid {
+    pub width: usize,
+    pub height: usize,
+    pub patch_size: usize,
+    pub cols: usize,
+    pub rows: usize,
+}
+
+impl PatchGrid {
+    pub fn new(width: usize, height: usize, patch_size: usize) -> PatchGrid {
+        let patch_size = patch_size.max(1);
+        PatchGrid {
+            width,
+            height,
+            patch_size,
+            cols: width.div_ceil(patch_size),
+            rows: height.div_ceil(patch_size),
+        }
+    }
+
+    pub fn num_patches(&self) -> usize {
+        self.cols * self.rows
+    }
+
+    /// Quality of a band at each patch, row by row. The band is in stacking coordinates, as
+    /// `drizzle::resample` gives.
+    pub fn score(&self, buffer: &ImageBuffer, metric: &dyn QualityMetric) -> Vec<f32> {
+        let mut scores = Vec::with_capacity(self.num_patches());
+        for row in 0..self.rows {
+            for col in 0..self.cols {
+                let (x0, y0) = (col * self.patch_size, row * self.patch_size);
+                scores.push(metric.estimate_region(
+                    buffer,
+                    x0,
+                    y0,
+                    (x0 + self.patch_size).min(self.width),
+                    (y0 + self.patch_size).min(self.height),
+                ));
+            }
+        }
+        scores
+    }
+}
+
+/// Which frames are stacked at each patch
+#[derive(Debug, Clone)]
+pub struct PatchSelection {
+    pub grid: PatchGrid,
+
+    // Per frame, 1.0 at the patches the frame is stacked at and 0.0 elsewhere
+    frame_weights: Vec<Vec<f32>>,
+}
+
+impl PatchSelection {
+    /// Selects from each frame's patch scores, as `PatchGrid::score` gives, in frame order.
+    /// Every patch keeps at least its sharpest frame.
+    pub fn new(grid: PatchGrid, scores: &[Vec<f32>], options: &PatchQualityOptions) -> Self {
+        let num_frames = scores.len();
+        let keep = ((options.top_percentage as f32 / 100.0) * num_frames as f32).round() as usize;
+        let keep = keep.clamp(1.min(num_frames), num_frames);
+
+        let mut frame_weights = vec![vec![0.0; grid.num_patches()]; num_frames];
+        for patch in 0..grid.num_patches() {
+            // A NaN score ranks as zero rather than breaking the sort
+            let score = |frame: usize| match scores[frame][patch] {
+                s if s.is_nan() => 0.0,
+                s => s,
+            };
+            let mut ranked: Vec<usize> = (0..num_frames).collect();
+            ranked.sort_by(|a, b| score(*b).total_cmp(&score(*a)));
+
+            let best = ranked.first().map(|f| score(*f)).unwrap_or(0.0);
+            for (rank, frame) in ranked.iter().take(keep).enumerate() {
+                if rank == 0 || score(*frame) >= best * options.min_relative_quality {
+                    frame_weights[*frame][patch] = 1.0;
+                }
+            }
+        }
+
+        PatchSelection {
+            grid,
+            frame_weights,
+        }
+    }
+
+    pub fn num_frames(&self) -> usize {
+        self.frame_weights.len()
+    }
+
+    /// Frames stacked at each patch, row by row
+    pub fn frames_per_patch(&self) -> Vec<usize> {
+        (0..self.grid.num_patches())
+            .map(|p| self.frame_weights.iter().filter(|w| w[p] > 0.0).count())
+            .collect()
+    }
+
+    /// The weights of one frame, by its index in the scores the selection was made from
+    pub fn weights(&self, frame: usize) -> PatchWeights {
+        PatchWeights {
+            grid: self.grid,
+            weights: self.frame_weights[frame].clone(),
+        }
+    }
+}
+
+/// One frame's patch weights, bilinearly interpolated between patch centers so the seams between
+/// patches stacked from different frames are blended
+#[derive(Debug, Clone)]
+pub struct PatchWeights {
+    grid: PatchGrid,
+    weights: Vec<f32>,
+}
+
+impl PatchWeights {
+    /// The weight at a point in stacking coordinates
+    pub fn at(&self, x: f32, y: f32) -> f32 {
+        let size = self.grid.patch_size as f32;
+        let gx = (x / size - 0.5).clamp(0.0, (self.grid.cols - 1) as f32);
+        let gy = (y / size - 0.5).clamp(0.0, (self.grid.rows - 1) as f32);
+        let (c0, r0) = (gx.floor() as usize, gy.floor() as usize);
+        let (c1, r1) = (
+            (c0 + 1).min(self.grid.cols - 1),
+            (r0 + 1).min(self.grid.rows - 1),
+        );
+        let (fx, fy) = (gx - c0 as f32, gy - r0 as f32);
+
+        let w = |c: usize, r: usize| self.weights[r * self.grid.cols + c];
+        let top = w(c0, r0) * (1.0 - fx) + w(c1, r0) * fx;
+        let bottom = w(c0, r1) * (1.0 - fx) + w(c1, r1) * fx;
+        top * (1.0 - fy) + bottom * fy
+    }
+}
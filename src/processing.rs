@@ -1,13 +1,26 @@
 use crate::{
+    alignment::{Aligner, AlignmentOptions, FrameOffset},
+    calibration::CalibrationSet,
+    callibrary::{CalibrationFiles, CalibrationLibrary, MatchCriteria},
+    defectmap::DefectMap,
     drizzle::{self, BilinearDrizzle},
-    enums::Target,
-    fpmap, lunar, mean, parallacticangle, ser, solar, timestamp,
+    enums::{AlignmentMethod, QualityMethod, Target},
+    fpmap,
+    framesource::{self, FrameSource},
+    lunar, mean,
+    multipoint::{self, LocalShift, MultiPointAligner, MultiPointOptions},
+    parallacticangle,
+    patchquality::{self, PatchGrid, PatchQualityOptions, PatchSelection, PatchWeights},
+    quality::{self, QualityMetric},
+    ser, solar,
+    synthflat::{self, SyntheticFlatOptions},
+    timestamp,
 };
 
 use anyhow::{anyhow, Result};
 use rayon::prelude::*;
-use sciimg::imagebuffer::Offset;
-use sciimg::{image, imagerot, max, min, path, quality};
+use sciimg::imagebuffer::{ImageBuffer, Offset};
+use sciimg::{enums::ImageMode, image, imagerot, max, min, path};
 use serde::Serialize;
 use std::{cmp::Ordering, ffi::OsStr, fs, path::Path};
 use std::{fmt, io::Write};
@@ -32,7 +45,51 @@ pub struct ProcessReport {
     pub num_frames_discarded_max_sigma: usize,
     pub num_frames_discarded_top_percentage: usize,
     pub initial_rotation: f32,
+    pub quality_method: QualityMethod,
     pub quality_values: Vec<f32>,
+    pub calibration_files: CalibrationFiles,
+    pub alignment_method: AlignmentMethod,
+    pub frame_offsets: Vec<FrameOffset>,
+
+    // Median fitted limb radius, in output pixels
+    pub limb_radius: Option<f32>,
+
+    // Alignment patches in multi-point alignment, if used
+    pub num_alignment_patches: Option<usize>,
+
+    // Patches scored in local quality selection, if used, and the fewest and most frames any
+    // one of them was stacked from
+    pub num_quality_patches: Option<usize>,
+    pub min_frames_per_patch: Option<usize>,
+    pub max_frames_per_patch: Option<usize>,
+
+    // Map of the frames stacked at each output pixel, written with local quality selection
+    pub weight_map: Option<String>,
+}
+
+// Weight map path next to the output, as stack.tif -> stack_weights.tif
+fn weight_map_path(out_path: &str) -> String {
+    let path = Path::new(out_path);
+    let stem = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let file_name = match path.extension() {
+        Some(ext) => format!("{}_weights.{}", stem, ext.to_string_lossy()),
+        None => format!("{}_weights", stem),
+    };
+    path.with_file_name(file_name)
+        .to_string_lossy()
+        .into_owned()
+}
+
+// Mean magnitude of a frame's local shifts
+fn mean_shift(shifts: &[LocalShift]) -> Option<f32> {
+    if shifts.is_empty() {
+        None
+    } else {
+        Some(shifts.iter().map(|s| s.dx.hypot(s.dy)).sum::<f32>() / shifts.len() as f32)
+    }
 }
 
 impl ProcessReport {
@@ -72,9 +129,55 @@ impl fmt::Display for ProcessReport {
             self.num_frames_discarded_top_percentage
         )
         .as_ref();
+        text += format!("Quality Metric: {:?}\n", self.quality_method).as_ref();
         text += format!("Maximum Sigma Encountered: {}\n", self.max_sigma).as_ref();
         text += format!("Minimum Sigma Encountered: {}\n", self.min_sigma).as_ref();
         text += format!("Initial Parallatic Rotation: {}\n", self.initial_rotation).as_ref();
+        text += "Calibration Frames:\n";
+        for (name, file) in [
+            ("Flat", &self.calibration_files.flat),
+            ("Dark", &self.calibration_files.dark),
+            ("Dark Flat", &self.calibration_files.dark_flat),
+            ("Bias", &self.calibration_files.bias),
+        ] {
+            let file = match file {
+                None if name == "Flat" && self.calibration_files.synthetic_flat => {
+                    String::from("Synthetic, from the input frames")
+                }
+                Some(f) if self.calibration_files.matched_from_library.contains(f) => {
+                    format!("{} (from library)", f)
+                }
+                Some(f) => f.to_owned(),
+                None => String::from("None"),
+            };
+            text += format!("\t{}: {}\n", name, file).as_ref();
+        }
+        text += format!("Alignment Method: {:?}\n", self.alignment_method).as_ref();
+        if self.alignment_method != AlignmentMethod::CenterOfMass {
+            text += format!(
+                "\tFrames falling back to center of mass: {}\n",
+                self.frame_offsets
+                    .iter()
+                    .filter(|o| o.method == AlignmentMethod::CenterOfMass)
+                    .count()
+            )
+            .as_ref();
+        }
+        if let Some(num_patches) = self.num_alignment_patches {
+            text += format!("Multi-Point Alignment Patches: {}\n", num_patches).as_ref();
+        }
+        if let Some(num_patches) = self.num_quality_patches {
+            text += format!("Local Quality Patches: {}\n", num_patches).as_ref();
+            if let (Some(min), Some(max)) = (self.min_frames_per_patch, self.max_frames_per_patch) {
+                text += format!("\tFrames stacked per patch: {} to {}\n", min, max).as_ref();
+            }
+        }
+        if let Some(weight_map) = &self.weight_map {
+            text += format!("Weight Map: {}\n", weight_map).as_ref();
+        }
+        if let Some(radius) = self.limb_radius {
+            text += format!("Limb Radius: {} pixels\n", radius).as_ref();
+        }
         write!(f, "{}", text)
     }
 }
@@ -136,21 +239,23 @@ impl CenterOfMass for image::Image {
 struct ProcessContext {
     pub frame_records: Vec<FrameRecord>,
     pub drizzle_buffer: BilinearDrizzle,
-    pub obj_detect_threshold: f32,
     pub obs_latitude: f32,
     pub obs_longitude: f32,
     pub target: Target,
-    pub flat_field: Option<image::Image>,
-    pub dark_field: Option<image::Image>,
-    pub dark_flat_field: Option<image::Image>,
-    pub bias_field: Option<image::Image>,
+    pub calibration: CalibrationSet,
+    pub defect_map: Option<DefectMap>,
+
+    // Local quality weights of each frame, in step with frame_records, if selecting by patch
+    pub patch_weights: Vec<PatchWeights>,
 }
 
 pub struct HaProcessing {
-    pub flat_field: Option<image::Image>,
-    pub dark_field: Option<image::Image>,
-    pub dark_flat_field: Option<image::Image>,
-    pub bias_field: Option<image::Image>,
+    pub calibration: CalibrationSet,
+    pub alignment: AlignmentOptions,
+    pub multipoint: Option<MultiPointOptions>,
+    pub patch_quality: Option<PatchQualityOptions>,
+    pub quality_metric: Box<dyn QualityMetric>,
+    pub defect_map: Option<DefectMap>,
     pub mask: image::Image,
     pub width: usize,
     pub height: usize,
@@ -209,6 +314,9 @@ impl HaProcessing {
         dark_path: &str,
         dark_flat_path: &str,
         bias_path: &str,
+        calibration_library: &str,
+        synthetic_flat: bool,
+        defect_map_path: &str,
         mask_file: &str,
         crop_width: usize,
         crop_height: usize,
@@ -224,90 +332,148 @@ impl HaProcessing {
         number_of_frames: usize,
         target: Target,
         drizzle_scale: drizzle::Scale,
+        alignment: AlignmentOptions,
+        multipoint: Option<MultiPointOptions>,
+        patch_quality: Option<PatchQualityOptions>,
+        quality_method: QualityMethod,
     ) -> Result<HaProcessing> {
-        let flat = match flat_path.len() {
-            0 => None,
-            _ => {
-                if !path::file_exists(flat_path) {
-                    panic!("File not found: {}", flat_path);
-                }
-
-                if HaProcessing::is_ser_file(flat_path) {
-                    Some(HaProcessing::create_mean_from_ser(flat_path).unwrap())
-                } else {
-                    Some(image::Image::open_str(flat_path).unwrap())
-                }
+        let source1 = framesource::open(input_files[0])?;
+
+        if let Some(options) = &multipoint {
+            if options.patch_size < multipoint::MIN_PATCH_SIZE {
+                return Err(anyhow!(
+                    "Multi-point patch size must be at least {} pixels",
+                    multipoint::MIN_PATCH_SIZE
+                ));
             }
-        };
-
-        let dark = match dark_path.len() {
-            0 => None,
-            _ => {
-                if !path::file_exists(dark_path) {
-                    panic!("File not found: {}", dark_path);
-                }
-
-                if HaProcessing::is_ser_file(dark_path) {
-                    Some(HaProcessing::create_mean_from_ser(dark_path).unwrap())
-                } else {
-                    Some(image::Image::open_str(dark_path).unwrap())
-                }
+            if options.search_radius < 2 {
+                return Err(anyhow!(
+                    "Multi-point search radius must be at least 2 pixels"
+                ));
             }
-        };
+        }
 
-        let darkflat = match dark_flat_path.len() {
-            0 => None,
-            _ => {
-                if !path::file_exists(dark_flat_path) {
-                    panic!("File not found: {}", dark_flat_path);
-                }
+        if let Some(options) = &patch_quality {
+            if options.patch_size < patchquality::MIN_PATCH_SIZE {
+                return Err(anyhow!(
+                    "Local quality patch size must be at least {} pixels",
+                    patchquality::MIN_PATCH_SIZE
+                ));
+            }
+            if options.top_percentage == 0 || options.top_percentage > 100 {
+                return Err(anyhow!(
+                    "Local quality percentage must be between 1 and 100: {}",
+                    options.top_percentage
+                ));
+            }
+        }
 
-                if HaProcessing::is_ser_file(dark_flat_path) {
-                    Some(HaProcessing::create_mean_from_ser(dark_flat_path).unwrap())
-                } else {
-                    Some(image::Image::open_str(dark_flat_path).unwrap())
-                }
+        // Explicitly given calibration frames take precedence over those from the library
+        let non_empty = |p: &str| {
+            if p.is_empty() {
+                None
+            } else {
+                Some(p.to_owned())
             }
         };
+        let mut calibration_files = CalibrationFiles {
+            flat: non_empty(flat_path),
+            dark: non_empty(dark_path),
+            dark_flat: non_empty(dark_flat_path),
+            bias: non_empty(bias_path),
+            synthetic_flat,
+            ..Default::default()
+        };
+        if synthetic_flat
+            && (calibration_files.flat.is_some() || calibration_files.dark_flat.is_some())
+        {
+            return Err(anyhow!(
+                "A synthetic flat replaces the flat and dark flat frames, which cannot be given too"
+            ));
+        }
+        if !calibration_library.is_empty() {
+            let library = CalibrationLibrary::open(calibration_library)?;
+            let criteria = MatchCriteria::for_input(
+                input_files[0],
+                source1.image_width(),
+                source1.image_height(),
+            );
+            library.fill_missing(&mut calibration_files, &criteria);
+        }
+        let mut calibration = CalibrationSet::load(&calibration_files)?;
+
+        if synthetic_flat {
+            let options = SyntheticFlatOptions {
+                obj_detect_threshold,
+                ..Default::default()
+            };
+            let synthetic =
+                synthflat::build_synthetic_flat(input_files, &calibration.dark, &options)?;
+            info!(
+                "Built synthetic flat from {} frames",
+                synthetic.metadata.frame_count
+            );
+            calibration.flat = Some(synthetic.image);
+        }
 
-        let bias = match bias_path.len() {
+        calibration.validate(
+            source1.image_width(),
+            source1.image_height(),
+            source1.num_bands(),
+        )?;
+
+        let defect_map = match defect_map_path.len() {
             0 => None,
             _ => {
-                if !path::file_exists(dark_flat_path) {
-                    panic!("File not found: {}", dark_flat_path);
-                }
-
-                if HaProcessing::is_ser_file(dark_flat_path) {
-                    Some(HaProcessing::create_mean_from_ser(dark_flat_path).unwrap())
-                } else {
-                    Some(image::Image::open_str(dark_flat_path).unwrap())
+                let defect_map = DefectMap::open(defect_map_path)?;
+                if defect_map.image_width != source1.image_width()
+                    || defect_map.image_height != source1.image_height()
+                {
+                    return Err(anyhow!(
+                        "Defect map is for {}x{} frames, inputs are {}x{}",
+                        defect_map.image_width,
+                        defect_map.image_height,
+                        source1.image_width(),
+                        source1.image_height()
+                    ));
                 }
+                info!("Correcting {} defective pixels", defect_map.len());
+                Some(defect_map)
             }
         };
 
+        // Masters are corrected too, otherwise calibration puts the defects back
+        if let Some(defect_map) = &defect_map {
+            calibration.correct_defects(defect_map)?;
+        }
+
         let mask = match mask_file.len() {
             0 => image::Image::new_empty().unwrap(),
             _ => {
                 if !path::file_exists(mask_file) {
-                    panic!("File not found: {}", mask_file);
+                    return Err(anyhow!("Mask file not found: {}", mask_file));
                 }
-                image::Image::open_str(mask_file).unwrap()
+                image::Image::open_str(mask_file)?
             }
         };
 
-        let ser1 = ser::SerFile::load_ser(input_files[0]).unwrap();
-
-        let drizzle_buffer =
-            drizzle::BilinearDrizzle::new(ser1.image_width, ser1.image_height, drizzle_scale, 3);
+        let drizzle_buffer = drizzle::BilinearDrizzle::new(
+            source1.image_width(),
+            source1.image_height(),
+            drizzle_scale,
+            3,
+        );
 
         Ok(HaProcessing {
-            flat_field: flat,
-            dark_field: dark,
-            dark_flat_field: darkflat,
-            bias_field: bias,
+            calibration,
+            alignment,
+            multipoint,
+            patch_quality,
+            quality_metric: quality::metric(quality_method),
+            defect_map,
             mask,
-            width: ser1.image_width,
-            height: ser1.image_height,
+            width: source1.image_width(),
+            height: source1.image_height(),
             crop_width,
             crop_height,
             buffer: drizzle_buffer,
@@ -325,7 +491,12 @@ impl HaProcessing {
             target,
             file_map: fpmap::FpMap::new(),
             drizzle_scale,
-            process_report: ProcessReport::default(),
+            process_report: ProcessReport {
+                calibration_files,
+                alignment_method: alignment.method,
+                quality_method,
+                ..Default::default()
+            },
         })
     }
 
@@ -362,12 +533,7 @@ impl HaProcessing {
     }
 
     pub fn process_frame(&self, buffer: &mut image::Image) {
-        buffer.calibrate2(
-            &self.flat_field,
-            &self.dark_field,
-            &self.dark_flat_field,
-            &self.bias_field,
-        );
+        self.calibration.apply(buffer);
     }
 
     pub fn finalize(&mut self, out_path: &str) -> Result<()> {
@@ -378,15 +544,7 @@ impl HaProcessing {
             //     self.buffer.apply_weight_on_band(1.0 / self.frame_count as f32, band);
             // }
 
-            if self.crop_height > 0 && self.crop_width > 0 {
-                let crop_width =
-                    (self.crop_width as f32 * self.drizzle_scale.value()).round() as usize;
-                let crop_height =
-                    (self.crop_height as f32 * self.drizzle_scale.value()).round() as usize;
-                let x = (final_buffer.width - crop_width) / 2;
-                let y = (final_buffer.height - crop_height) / 2;
-                final_buffer.crop(x, y, crop_width, crop_height);
-            }
+            self.crop_to_output(&mut final_buffer);
 
             let (stackmin, stackmax) = final_buffer.get_min_max_all_channel();
             info!(
@@ -415,12 +573,40 @@ impl HaProcessing {
             );
             final_buffer.save(out_path).expect("Failed to save image");
 
+            if self.patch_quality.is_some() {
+                let weight_map_path = weight_map_path(out_path);
+                self.save_weight_map(&weight_map_path)?;
+                self.process_report.weight_map = Some(weight_map_path);
+            }
+
             Ok(())
         } else {
             Err(anyhow!("No frames processed, not saving an empty buffer"))
         }
     }
 
+    fn crop_to_output(&self, image: &mut image::Image) {
+        if self.crop_height > 0 && self.crop_width > 0 {
+            let crop_width = (self.crop_width as f32 * self.drizzle_scale.value()).round() as usize;
+            let crop_height =
+                (self.crop_height as f32 * self.drizzle_scale.value()).round() as usize;
+            let x = (image.width - crop_width) / 2;
+            let y = (image.height - crop_height) / 2;
+            image.crop(x, y, crop_width, crop_height);
+        }
+    }
+
+    // Frames stacked at each pixel of the output, as a 16 bit image of the counts
+    fn save_weight_map(&self, path: &str) -> Result<()> {
+        let weights = self.buffer.get_weight_map();
+        let mut weight_map =
+            image::Image::new_with_bands(weights.width, weights.height, 1, ImageMode::U16BIT)?;
+        weight_map.set_band(weights, 0);
+        self.crop_to_output(&mut weight_map);
+        info!("Writing weight map to {}", path);
+        weight_map.save(path)
+    }
+
     fn get_rotation_of_single_frame(
         frame_records: &[FrameRecord],
         target: Target,
@@ -428,9 +614,12 @@ impl HaProcessing {
         obs_longitude: f32,
     ) -> f64 {
         let frame_record = &frame_records[0];
-        let ser_file = ser::SerFile::load_ser(frame_record.source_file.as_str())
-            .expect("Unable to load SER file");
-        let frame_buffer = ser_file.get_frame(frame_record.frame_id).unwrap();
+        let frame_source = framesource::open(frame_record.source_file.as_str())
+            .expect("Unable to open input file");
+        let frame_buffer = frame_source
+            .read_frame(frame_record.frame_id)
+            .unwrap()
+            .frame;
         let (rotation, _alt, _az) = HaProcessing::get_rotation_for_time(
             &frame_buffer.timestamp,
             target,
@@ -440,6 +629,154 @@ impl HaProcessing {
         rotation
     }
 
+    // Reads a frame and calibrates it. Defects are corrected on the raw mosaic, before
+    // demosaicing spreads them into their neighbors.
+    fn load_frame(
+        frame_source: &dyn FrameSource,
+        frame_id: usize,
+        calibration: &CalibrationSet,
+        defect_map: &Option<DefectMap>,
+    ) -> ser::SerFrame {
+        let mut frame = match defect_map {
+            Some(defect_map) => {
+                let mut mosaic = frame_source.read_frame_mosaic(frame_id).unwrap().frame;
+                if let Err(why) =
+                    defect_map.correct(&mut mosaic.buffer, frame_source.color_format())
+                {
+                    error!("Error correcting defective pixels: {}", why);
+                }
+                mosaic.demosaic(frame_source.color_format()).unwrap()
+            }
+            None => frame_source.read_frame(frame_id).unwrap().frame,
+        };
+        calibration.apply(&mut frame.buffer);
+        frame
+    }
+
+    // Field rotation of a frame relative to the initial rotation, in radians
+    fn effective_rotation(
+        timestamp: &timestamp::TimeStamp,
+        initial_rotation: f64,
+        target: Target,
+        obs_latitude: f32,
+        obs_longitude: f32,
+    ) -> f64 {
+        let (rotation, alt, az) =
+            HaProcessing::get_rotation_for_time(timestamp, target, obs_latitude, obs_longitude);
+        let start_rot = if initial_rotation == UNKNOWN_ROTATION {
+            rotation
+        } else {
+            initial_rotation
+        };
+        let do_rotation = initial_rotation - rotation;
+        info!(
+            "Rotation for frame is {} for az/alt {},{} at time {:?}",
+            rotation, az, alt, timestamp
+        );
+        info!(
+            "Initial rotation was {}, effective rotation is {}",
+            start_rot, do_rotation
+        );
+        do_rotation.to_radians()
+    }
+
+    // Inputs are opened once, up front, by init_ser_file_map
+    fn frame_source(&self, source_file: &String) -> &dyn FrameSource {
+        self.file_map
+            .get_dont_open(source_file)
+            .expect("Input file does not exist in file map")
+    }
+
+    // First band of a frame, calibrated and aligned, as it will be stacked
+    fn stacked_band(
+        &self,
+        frame_source: &dyn FrameSource,
+        frame_record: &FrameRecord,
+        aligner: &Aligner,
+        enable_rotation: bool,
+        initial_rotation: f64,
+    ) -> ImageBuffer {
+        let frame = HaProcessing::load_frame(
+            frame_source,
+            frame_record.frame_id,
+            &self.calibration,
+            &self.defect_map,
+        );
+        let rotation = if enable_rotation && !frame_source.derotated() {
+            HaProcessing::effective_rotation(
+                &frame.timestamp,
+                initial_rotation,
+                self.target,
+                self.obs_latitude,
+                self.obs_longitude,
+            )
+        } else {
+            0.0
+        };
+        let alignment = aligner.align(&frame.buffer);
+        drizzle::resample(frame.buffer.get_band(0), alignment.offset, rotation)
+    }
+
+    // Mean of the best frames as they will be stacked, for multi-point alignment to measure
+    // against
+    fn build_multipoint_reference(
+        &self,
+        frame_records: &[FrameRecord],
+        aligner: &Aligner,
+        options: &MultiPointOptions,
+        enable_rotation: bool,
+        initial_rotation: f64,
+    ) -> ImageBuffer {
+        let frame_records =
+            &frame_records[..options.reference_frames.clamp(1, frame_records.len())];
+        let stacked = frame_records
+            .par_iter()
+            .map(|frame_record| {
+                self.stacked_band(
+                    self.frame_source(&frame_record.source_file),
+                    frame_record,
+                    aligner,
+                    enable_rotation,
+                    initial_rotation,
+                )
+            })
+            .collect::<Vec<ImageBuffer>>();
+
+        let mut reference = stacked[0].clone();
+        for other in stacked.iter().skip(1) {
+            reference.add_mut(other);
+        }
+        reference.scale_mut(1.0 / stacked.len() as f32);
+        reference
+    }
+
+    // Scores every frame at each patch as it will be stacked, and picks the frames stacked there
+    fn select_patches(
+        &self,
+        frame_records: &[FrameRecord],
+        aligner: &Aligner,
+        options: &PatchQualityOptions,
+        enable_rotation: bool,
+        initial_rotation: f64,
+    ) -> PatchSelection {
+        let grid = PatchGrid::new(self.width, self.height, options.patch_size);
+        let metric = options.metric();
+        let scores = frame_records
+            .par_iter()
+            .map(|frame_record| {
+                let band = self.stacked_band(
+                    self.frame_source(&frame_record.source_file),
+                    frame_record,
+                    aligner,
+                    enable_rotation,
+                    initial_rotation,
+                );
+                grid.score(&band, metric.as_ref())
+            })
+            .collect::<Vec<Vec<f32>>>();
+        PatchSelection::new(grid, &scores, options)
+    }
+
     fn process_frame_records(
         &mut self,
         frame_records: &[FrameRecord],
@@ -458,21 +795,70 @@ impl HaProcessing {
         };
         self.process_report.initial_rotation = initial_rotation as f32;
 
-        let num_per_chunk = frame_records.len() / num_cpus::get();
+        // The best frame is the reference the others are registered against
+        let reference = HaProcessing::load_frame(
+            self.frame_source(&frame_records[0].source_file),
+            frame_records[0].frame_id,
+            &self.calibration,
+            &self.defect_map,
+        );
+        let aligner = Aligner::new(
+            &reference.buffer,
+            &self.alignment,
+            self.obj_detect_threshold,
+        );
+
+        let multipoint = self.multipoint.map(|options| {
+            info!("Building multi-point alignment reference");
+            let reference = self.build_multipoint_reference(
+                frame_records,
+                &aligner,
+                &options,
+                enable_rotation,
+                initial_rotation,
+            );
+            MultiPointAligner::new(&reference, &options)
+        });
+        self.process_report.num_alignment_patches = multipoint.as_ref().map(|mp| mp.num_patches());
+        let multipoint = multipoint.as_ref();
+
+        let selection = self.patch_quality.map(|options| {
+            info!("Scoring frames for local quality selection");
+            self.select_patches(
+                frame_records,
+                &aligner,
+                &options,
+                enable_rotation,
+                initial_rotation,
+            )
+        });
+        if let Some(selection) = &selection {
+            let frames_per_patch = selection.frames_per_patch();
+            self.process_report.num_quality_patches = Some(selection.grid.num_patches());
+            self.process_report.min_frames_per_patch = frames_per_patch.iter().min().copied();
+            self.process_report.max_frames_per_patch = frames_per_patch.iter().max().copied();
+        }
+
+        // At least one frame per chunk, as there may be fewer frames than cores
+        let num_per_chunk = frame_records.len().div_ceil(num_cpus::get()).max(1);
 
         let contexts = frame_records
             .chunks(num_per_chunk)
-            .map(|fr| ProcessContext {
+            .enumerate()
+            .map(|(chunk, fr)| ProcessContext {
                 frame_records: fr.to_vec(),
                 drizzle_buffer: self.buffer.clone(),
-                obj_detect_threshold: self.obj_detect_threshold,
                 obs_latitude: self.obs_latitude,
                 obs_longitude: self.obs_longitude,
                 target: self.target,
-                flat_field: self.flat_field.clone(),
-                dark_field: self.dark_field.clone(),
-                dark_flat_field: self.dark_flat_field.clone(),
-                bias_field: self.bias_field.clone(),
+                calibration: self.calibration.clone(),
+                defect_map: self.defect_map.clone(),
+                patch_weights: match &selection {
+                    Some(selection) => (0..fr.len())
+                        .map(|i| selection.weights(chunk * num_per_chunk + i))
+                        .collect(),
+                    None => vec![],
+                },
             })
             .collect::<Vec<ProcessContext>>();
 
@@ -480,57 +866,67 @@ impl HaProcessing {
             .into_par_iter()
             .map(|mut context| {
                 let mut file_map = fpmap::FpMap::new();
+                let mut frame_offsets = vec![];
 
-                for frame_record in context.frame_records {
+                for (i, frame_record) in context.frame_records.iter().enumerate() {
                     match file_map.get(&frame_record.source_file) {
                         None => panic!(
-                            "SER file does not exist in file map. Not good, Kevin. Not good."
+                            "Input file does not exist in file map. Not good, Kevin. Not good."
                         ),
-                        Some(ser_file) => {
-                            let mut frame_buffer =
-                                ser_file.get_frame(frame_record.frame_id).unwrap();
-
-                            frame_buffer.buffer.calibrate2(
-                                &context.flat_field,
-                                &context.dark_field,
-                                &context.dark_flat_field,
-                                &context.bias_field,
+                        Some(frame_source) => {
+                            let frame_buffer = HaProcessing::load_frame(
+                                frame_source,
+                                frame_record.frame_id,
+                                &context.calibration,
+                                &context.defect_map,
                             );
 
-                            let offset = frame_buffer
-                                .buffer
-                                .calc_center_of_mass_offset(context.obj_detect_threshold, 0);
-
-                            let rotation = if enable_rotation {
-                                let (rotation, alt, az) = HaProcessing::get_rotation_for_time(
+                            let alignment = aligner.align(&frame_buffer.buffer);
+                            frame_offsets.push(FrameOffset {
+                                source_file: frame_record.source_file.clone(),
+                                frame_id: frame_record.frame_id,
+                                offset_h: alignment.offset.h,
+                                offset_v: alignment.offset.v,
+                                method: alignment.method,
+                                confidence: alignment.confidence,
+                                radius: alignment.radius,
+                                num_local_shifts: None,
+                                mean_local_shift: None,
+                            });
+
+                            let rotation = if enable_rotation && !frame_source.derotated() {
+                                HaProcessing::effective_rotation(
                                     &frame_buffer.timestamp,
+                                    initial_rotation,
                                     context.target,
                                     context.obs_latitude,
                                     context.obs_longitude,
-                                );
-                                let start_rot = if initial_rotation == UNKNOWN_ROTATION {
-                                    rotation
-                                } else {
-                                    initial_rotation
-                                };
-                                let do_rotation = initial_rotation - rotation;
-                                info!(
-                                    "Rotation for frame is {} for az/alt {},{} at time {:?}",
-                                    rotation, az, alt, &frame_buffer.timestamp
-                                );
-                                info!(
-                                    "Initial rotation was {}, effective rotation is {}",
-                                    start_rot, do_rotation
-                                );
-                                do_rotation.to_radians()
+                                )
                             } else {
                                 0.0
                             };
 
-                            match context.drizzle_buffer.add_with_transform(
+                            // Local shifts are measured on the frame as it will be stacked
+                            let warp = multipoint.map(|mp| {
+                                let stacked = drizzle::resample(
+                                    frame_buffer.buffer.get_band(0),
+                                    alignment.offset,
+                                    rotation,
+                                );
+                                let (warp, shifts) = mp.warp_field(&stacked);
+                                if let Some(frame_offset) = frame_offsets.last_mut() {
+                                    frame_offset.num_local_shifts = Some(shifts.len());
+                                    frame_offset.mean_local_shift = mean_shift(&shifts);
+                                }
+                                warp
+                            });
+
+                            match context.drizzle_buffer.add_weighted(
                                 &frame_buffer.buffer,
-                                offset,
+                                alignment.offset,
                                 rotation,
+                                warp.as_ref(),
+                                context.patch_weights.get(i),
                             ) {
                                 Ok(_) => {}
                                 Err(why) => {
@@ -541,37 +937,46 @@ impl HaProcessing {
                     };
                 }
 
-                context.drizzle_buffer
+                (context.drizzle_buffer, frame_offsets)
             })
-            .collect::<Vec<BilinearDrizzle>>();
+            .collect::<Vec<(BilinearDrizzle, Vec<FrameOffset>)>>();
 
-        for drizzle_buffer in drizzles {
+        for (drizzle_buffer, frame_offsets) in drizzles {
             self.buffer.add_drizzle(&drizzle_buffer).unwrap();
+            self.process_report.frame_offsets.extend(frame_offsets);
+        }
+
+        let mut radii: Vec<f32> = self
+            .process_report
+            .frame_offsets
+            .iter()
+            .filter_map(|o| o.radius)
+            .collect();
+        if !radii.is_empty() {
+            radii.sort_by(f32::total_cmp);
+            let radius = radii[radii.len() / 2] * self.drizzle_scale.value();
+            info!("Fitted limb radius in the output: {} pixels", radius);
+            self.process_report.limb_radius = Some(radius);
         }
 
         self.frame_count += frame_records.len() as u32;
     }
 
-    fn determine_quality_in_ser(&self, ser_file: &ser::SerFile) -> Vec<FrameRecord> {
-        let frame_count = if ser_file.frame_count > self.number_of_frames {
-            self.number_of_frames
-        } else {
-            ser_file.frame_count
-        };
-
-        (0..frame_count)
-            .into_par_iter()
-            .map(|i| {
-                let frame_buffer = ser_file.get_frame(i).unwrap();
-                let qual = quality::get_quality_estimation(&frame_buffer.buffer);
+    fn determine_quality_in_source<S: FrameSource>(&self, frame_source: &S) -> Vec<FrameRecord> {
+        frame_source
+            .par_frames()
+            .take(self.number_of_frames)
+            .map(|f| {
+                let sourced_frame = f.unwrap();
+                let qual = self.quality_metric.estimate(&sourced_frame.frame.buffer);
                 info!(
                     "Quality value of frame {} is {}",
-                    ser_file.source_file, qual
+                    sourced_frame.source_file, qual
                 );
 
                 FrameRecord {
-                    source_file: ser_file.source_file.to_string(),
-                    frame_id: i,
+                    source_file: sourced_frame.source_file,
+                    frame_id: sourced_frame.frame_id,
                     quality_value: qual,
                 }
             })
@@ -585,7 +990,7 @@ impl HaProcessing {
             .par_iter()
             .map(|item| {
                 let (_pth, sf) = item;
-                self.determine_quality_in_ser(sf)
+                self.determine_quality_in_source(sf)
             })
             .collect::<Vec<Vec<FrameRecord>>>()
             .iter()
@@ -597,7 +1002,7 @@ impl HaProcessing {
 
     pub fn init_ser_file_map(&mut self, ser_files: &[&str]) {
         ser_files.iter().for_each(|sf| {
-            if !path::file_exists(sf) {
+            if !framesource::exists(sf) {
                 panic!("File not found: {}", sf);
             }
 
@@ -605,6 +1010,9 @@ impl HaProcessing {
         });
     }
 
+    /// Stacks the inputs, which may be any mix of files `framesource::open` supports (SER, AVI,
+    /// FITS cubes, single-frame FITS files or directories of PNG/TIFF frames) so long as their
+    /// dimensions match.
     pub fn process_ser_files<F: Fn(ProcessStep, usize), C: Fn(ProcessStep)>(
         &mut self,
         ser_files: &[&str],
@@ -623,8 +1031,22 @@ impl HaProcessing {
         self.init_ser_file_map(ser_files);
 
         self.file_map.map.iter().for_each(|(_, m)| {
-            self.process_report.total_frames += m.frame_count;
+            self.process_report.total_frames += m.frame_count();
         });
+
+        if enable_rotation {
+            let derotated = self.file_map.map.values().filter(|m| m.derotated()).count();
+            if derotated == self.file_map.map.len() {
+                info!("Inputs were derotated by pre-process, skipping field rotation");
+            } else if derotated > 0 {
+                warn!(
+                    "{} of {} inputs were derotated by pre-process and won't be rotated again. \
+                     Their orientation follows the pre-process initial rotation.",
+                    derotated,
+                    self.file_map.map.len()
+                );
+            }
+        }
         info!(
             "Total frames considered: {}",
             self.process_report.total_frames
@@ -0,0 +1,261 @@
+// Frame quality metrics, for ranking frames and patches by sharpness. Apart from the legacy
+// sigma, scores are taken relative to the mean brightness and given in percent, so they don't
+// shift with bit depth, gain or exposure and thresholds carry over between cameras.
+
+use crate::enums::QualityMethod;
+use crate::fft::{frequency, spectrum};
+use sciimg::enums::ImageMode;
+use sciimg::image::Image;
+use sciimg::imagebuffer::ImageBuffer;
+use sciimg::quality;
+
+// Block size local contrast is measured over, in pixels
+const CONTRAST_BLOCK_SIZE: usize = 8;
+
+// Spatial frequency, in cycles per pixel, above which spectral power counts as fine detail
+const HIGH_FREQUENCY_CUTOFF: f32 = 0.15;
+
+// Largest region transformed for the high frequency power. Larger regions are measured over
+// their middle, which bounds the cost on large sensors.
+const MAX_FFT_SIZE: usize = 1024;
+
+pub trait QualityMetric: Send + Sync {
+    fn method(&self) -> QualityMethod;
+
+    /// Quality of the region [x0, x1) x [y0, y1) of a band. Higher is sharper.
+    fn estimate_region(
+        &self,
+        buffer: &ImageBuffer,
+        x0: usize,
+        y0: usize,
+        x1: usize,
+        y1: usize,
+    ) -> f32;
+
+    /// Quality of a frame, from its first band
+    fn estimate(&self, image: &Image) -> f32 {
+        let buffer = image.get_band(0);
+        self.estimate_region(buffer, 0, 0, buffer.width, buffer.height)
+    }
+}
+
+/// The metric for a method
+pub fn metric(method: QualityMethod) -> Box<dyn QualityMetric> {
+    match method {
+        QualityMethod::Sigma => Box::new(Sigma),
+        QualityMethod::Laplacian => Box::new(Laplacian),
+        QualityMethod::Tenengrad => Box::new(Tenengrad),
+        QualityMethod::LocalContrast => Box::new(LocalContrast),
+        QualityMethod::FftHighFrequency => Box::new(FftHighFrequency),
+    }
+}
+
+fn crop(buffer: &ImageBuffer, x0: usize, y0: usize, x1: usize, y1: usize) -> ImageBuffer {
+    let mut out = ImageBuffer::new(x1 - x0, y1 - y0).expect("Failed to allocate region buffer");
+    for y in y0..y1 {
+        for x in x0..x1 {
+            out.put(x - x0, y - y0, buffer.get(x, y));
+        }
+    }
+    out
+}
+
+fn mean(buffer: &ImageBuffer, x0: usize, y0: usize, x1: usize, y1: usize) -> f64 {
+    let mut sum = 0.0;
+    for y in y0..y1 {
+        for x in x0..x1 {
+            sum += buffer.get(x, y) as f64;
+        }
+    }
+    sum / ((x1 - x0) * (y1 - y0)).max(1) as f64
+}
+
+// RMS of a response, as a percentage of the region's mean brightness
+fn relative(rms: f64, mean: f64) -> f32 {
+    if mean > 0.0 {
+        (rms / mean * 100.0) as f32
+    } else {
+        0.0
+    }
+}
+
+/// The sciimg quality estimation. Its scale shifts with bit depth and exposure, so thresholds
+/// for it are per camera.
+pub struct Sigma;
+
+impl QualityMetric for Sigma {
+    fn method(&self) -> QualityMethod {
+        QualityMethod::Sigma
+    }
+
+    fn estimate_region(
+        &self,
+        buffer: &ImageBuffer,
+        x0: usize,
+        y0: usize,
+        x1: usize,
+        y1: usize,
+    ) -> f32 {
+        let mut image = Image::new_with_bands(x1 - x0, y1 - y0, 1, ImageMode::U16BIT)
+            .expect("Failed to allocate region image");
+        image.set_band(&crop(buffer, x0, y0, x1, y1), 0);
+        quality::get_quality_estimation(&image)
+    }
+
+    fn estimate(&self, image: &Image) -> f32 {
+        quality::get_quality_estimation(image)
+    }
+}
+
+/// Standard deviation of the Laplacian, relative to the mean brightness
+pub struct Laplacian;
+
+impl QualityMetric for Laplacian {
+    fn method(&self) -> QualityMethod {
+        QualityMethod::Laplacian
+    }
+
+    fn estimate_region(
+        &self,
+        buffer: &ImageBuffer,
+        x0: usize,
+        y0: usize,
+        x1: usize,
+        y1: usize,
+    ) -> f32 {
+        let (mut sum, mut sum_sq, mut count) = (0.0_f64, 0.0_f64, 0);
+        for y in y0.max(1)..y1.min(buffer.height.saturating_sub(1)) {
+            for x in x0.max(1)..x1.min(buffer.width.saturating_sub(1)) {
+                let laplacian = (buffer.get(x - 1, y)
+                    + buffer.get(x + 1, y)
+                    + buffer.get(x, y - 1)
+                    + buffer.get(x, y + 1)
+                    - 4.0 * buffer.get(x, y)) as f64;
+                sum += laplacian;
+                sum_sq += laplacian * laplacian;
+                count += 1;
+            }
+        }
+        if count == 0 {
+            return 0.0;
+        }
+        let m = sum / count as f64;
+        let variance = (sum_sq / count as f64 - m * m).max(0.0);
+        relative(variance.sqrt(), mean(buffer, x0, y0, x1, y1))
+    }
+}
+
+/// RMS Sobel gradient magnitude, relative to the mean brightness
+pub struct Tenengrad;
+
+impl QualityMetric for Tenengrad {
+    fn method(&self) -> QualityMethod {
+        QualityMethod::Tenengrad
+    }
+
+    fn estimate_region(
+        &self,
+        buffer: &ImageBuffer,
+        x0: usize,
+        y0: usize,
+        x1: usize,
+        y1: usize,
+    ) -> f32 {
+        let (mut energy, mut count) = (0.0_f64, 0);
+        for y in y0.max(1)..y1.min(buffer.height.saturating_sub(1)) {
+            for x in x0.max(1)..x1.min(buffer.width.saturating_sub(1)) {
+                let p = |dx: usize, dy: usize| buffer.get(x + dx - 1, y + dy - 1);
+                let gx = (p(2, 0) + 2.0 * p(2, 1) + p(2, 2)) - (p(0, 0) + 2.0 * p(0, 1) + p(0, 2));
+                let gy = (p(0, 2) + 2.0 * p(1, 2) + p(2, 2)) - (p(0, 0) + 2.0 * p(1, 0) + p(2, 0));
+                energy += (gx * gx + gy * gy) as f64;
+                count += 1;
+            }
+        }
+        if count == 0 {
+            return 0.0;
+        }
+        relative((energy / count as f64).sqrt(), mean(buffer, x0, y0, x1, y1))
+    }
+}
+
+/// Standard deviation within small blocks, relative to their brightness. Brighter blocks count
+/// for more, so noise in the dark sky around the disk doesn't dominate.
+pub struct LocalContrast;
+
+impl QualityMetric for LocalContrast {
+    fn method(&self) -> QualityMethod {
+        QualityMethod::LocalContrast
+    }
+
+    fn estimate_region(
+        &self,
+        buffer: &ImageBuffer,
+        x0: usize,
+        y0: usize,
+        x1: usize,
+        y1: usize,
+    ) -> f32 {
+        let (mut total_stddev, mut total_mean) = (0.0_f64, 0.0_f64);
+        for by in (y0..y1).step_by(CONTRAST_BLOCK_SIZE) {
+            for bx in (x0..x1).step_by(CONTRAST_BLOCK_SIZE) {
+                let (ex, ey) = (
+                    (bx + CONTRAST_BLOCK_SIZE).min(x1),
+                    (by + CONTRAST_BLOCK_SIZE).min(y1),
+                );
+                let m = mean(buffer, bx, by, ex, ey);
+                let mut variance = 0.0;
+                for y in by..ey {
+                    for x in bx..ex {
+                        variance += (buffer.get(x, y) as f64 - m).powi(2);
+                    }
+                }
+                variance /= ((ex - bx) * (ey - by)) as f64;
+                total_stddev += variance.sqrt();
+                total_mean += m;
+            }
+        }
+        relative(total_stddev, total_mean)
+    }
+}
+
+/// Share of the spectral power above a spatial frequency cutoff. Blur moves power from fine to
+/// coarse detail, whatever the brightness.
+pub struct FftHighFrequency;
+
+impl QualityMetric for FftHighFrequency {
+    fn method(&self) -> QualityMethod {
+        QualityMethod::FftHighFrequency
+    }
+
+    fn estimate_region(
+        &self,
+        buffer: &ImageBuffer,
+        x0: usize,
+        y0: usize,
+        x1: usize,
+        y1: usize,
+    ) -> f32 {
+        let (w, h) = ((x1 - x0).min(MAX_FFT_SIZE), (y1 - y0).min(MAX_FFT_SIZE));
+        if w < 2 || h < 2 {
+            return 0.0;
+        }
+        let (cx, cy) = (x0 + (x1 - x0 - w) / 2, y0 + (y1 - y0 - h) / 2);
+        let region = crop(buffer, cx, cy, cx + w, cy + h);
+
+        let (fw, fh) = (w.next_power_of_two(), h.next_power_of_two());
+        let spectrum = spectrum(&region, fw, fh);
+        let (mut high, mut total) = (0.0_f64, 0.0_f64);
+        for i in 1..fw * fh {
+            let power = (spectrum.re[i] as f64).powi(2) + (spectrum.im[i] as f64).powi(2);
+            total += power;
+            if frequency(i % fw, fw).hypot(frequency(i / fw, fh)) >= HIGH_FREQUENCY_CUTOFF {
+                high += power;
+            }
+        }
+        if total > 0.0 {
+            (high / total * 100.0) as f32
+        } else {
+            0.0
+        }
+    }
+}
@@ -1,11 +1,29 @@
 // Technical specification: http://www.grischa-hahn.homepage.t-online.de/astro/ser/SER%20Doc%20V3b.pdf
 
+use crate::capturemeta;
+use crate::demosaic::{self, CmyChannel};
 use crate::timestamp;
+use crate::util;
 use anyhow::{anyhow, Result};
-use sciimg::{binfilereader::*, debayer, enums::ImageMode, image, imagebuffer};
+use memmap::Mmap;
+use sciimg::{binfilereader::*, enums::ImageMode, image, imagebuffer};
+use serde::Serialize;
+use std::fmt;
+use std::fs::File;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
 
 const HEADER_SIZE_BYTES: usize = 178;
 const TIMESTAMP_SIZE_BYTES: usize = 8;
+const FILE_ID_SIZE_BYTES: usize = 14;
+const HEADER_STRING_SIZE_BYTES: usize = 40;
+const OBSERVER_INDEX: usize = 42;
+const INSTRUMENT_INDEX: usize = 82;
+const TELESCOPE_INDEX: usize = 122;
+
+// Value written into the LittleEndian header field for little-endian pixel data. The spec says
+// otherwise, but this is what FireCapture, SharpCap and PIPP all write (and what they expect to read).
+const LITTLE_ENDIAN_FIELD_VALUE: i32 = 0;
+const BIG_ENDIAN_FIELD_VALUE: i32 = 1;
 
 #[derive(Debug, Copy, Clone, PartialEq)]
 pub enum ColorFormatId {
@@ -23,22 +41,37 @@ pub enum ColorFormatId {
 }
 
 impl ColorFormatId {
-    pub fn from_i32(v: i32) -> ColorFormatId {
+    pub fn from_i32(v: i32) -> Result<ColorFormatId> {
         match v {
-            0 => ColorFormatId::Mono,
-            8 => ColorFormatId::BayerRggb,
-            9 => ColorFormatId::BayerGrbg,
-            10 => ColorFormatId::BayerGbrg,
-            11 => ColorFormatId::BayerBggr,
-            16 => ColorFormatId::BayerCyym,
-            17 => ColorFormatId::BayerYcmy,
-            18 => ColorFormatId::BayerYmcy,
-            19 => ColorFormatId::BayerMyyc,
-            100 => ColorFormatId::Rgb,
-            101 => ColorFormatId::Bgr,
-            _ => panic!("Invalid color format enum value: {}", v),
+            0 => Ok(ColorFormatId::Mono),
+            8 => Ok(ColorFormatId::BayerRggb),
+            9 => Ok(ColorFormatId::BayerGrbg),
+            10 => Ok(ColorFormatId::BayerGbrg),
+            11 => Ok(ColorFormatId::BayerBggr),
+            16 => Ok(ColorFormatId::BayerCyym),
+            17 => Ok(ColorFormatId::BayerYcmy),
+            18 => Ok(ColorFormatId::BayerYmcy),
+            19 => Ok(ColorFormatId::BayerMyyc),
+            100 => Ok(ColorFormatId::Rgb),
+            101 => Ok(ColorFormatId::Bgr),
+            _ => Err(anyhow!("Invalid color format enum value: {}", v)),
+        }
+    }
+
+    /// Number of values stored per pixel. Rgb and Bgr are interleaved as three planes.
+    pub fn num_planes(&self) -> usize {
+        match *self {
+            ColorFormatId::Rgb | ColorFormatId::Bgr => 3,
+            _ => 1,
         }
     }
+
+    pub fn is_bayer(&self) -> bool {
+        !matches!(
+            *self,
+            ColorFormatId::Mono | ColorFormatId::Rgb | ColorFormatId::Bgr
+        )
+    }
 }
 
 // Variable size of pixel_depth * image_width * image_height
@@ -53,20 +86,25 @@ pub struct SerFrame {
 // Optional trailer starts at num_images * pixel_depth * image_width * image_height
 // Trailer size is 8 byte (i64) time stamps for each frame, size is 8 * num_images
 pub struct SerFile {
-    pub file_id: String,                     // 14 bytes
-    pub camera_series_id: i32,               // 4 bytes
-    pub color_id: ColorFormatId,             // 4 bytes
-    pub image_width: usize,                  // 4 bytes
-    pub image_height: usize,                 // 4 bytes
-    pub pixel_depth: usize,                  // 4 bytes
-    pub frame_count: usize,                  // 4 bytes
-    pub observer: String,                    // 40 bytes
-    pub instrument: String,                  // 40 bytes
-    pub telescope: String,                   // 40 bytes
-    pub date_time: timestamp::TimeStamp,     // 8 bytes,
-    pub date_time_utc: timestamp::TimeStamp, // 8 bytes,
-    pub total_size: usize,                   // Total file size (used for validation)
+    pub file_id: String,                                        // 14 bytes
+    pub camera_series_id: i32,                                  // 4 bytes
+    pub color_id: ColorFormatId,                                // 4 bytes
+    pub image_width: usize,                                     // 4 bytes
+    pub image_height: usize,                                    // 4 bytes
+    pub pixel_depth: usize,                                     // 4 bytes
+    pub frame_count: usize,                                     // 4 bytes
+    pub observer: String,                                       // 40 bytes
+    pub instrument: String,                                     // 40 bytes
+    pub telescope: String,                                      // 40 bytes
+    pub date_time: timestamp::TimeStamp,                        // 8 bytes,
+    pub date_time_utc: timestamp::TimeStamp,                    // 8 bytes,
+    pub total_size: usize, // Total file size (used for validation)
+    pub msb_aligned: bool, // Sub-16 bit data stored in the upper bits of its 16 bit container
+    pub header_metadata: HeaderMetadata, // Recorder values packed into the instrument and telescope strings
+    pub capture_metadata: Option<capturemeta::CaptureMetadata>, // From the capture software's sidecar file
+    big_endian: bool,
     file_reader: BinFileReader,
+    file_map: Mmap,
     pub source_file: String,
 }
 
@@ -107,11 +145,310 @@ impl SerFrame {
             timestamp: timestamp::TimeStamp::from_u64(timestamp),
         }
     }
+
+    /// Demosaics a single band mosaic frame recorded in the given color format. Frames of formats
+    /// that are not mosaics are returned unchanged.
+    pub fn demosaic(self, color_id: ColorFormatId) -> Result<SerFrame> {
+        let mosaic_band = self.buffer.get_band(0);
+
+        use CmyChannel::*;
+        let demosaiced = match color_id {
+            ColorFormatId::Mono | ColorFormatId::Rgb | ColorFormatId::Bgr => return Ok(self),
+            ColorFormatId::BayerRggb => demosaic::debayer_with_offset(mosaic_band, 0, 0)?,
+            ColorFormatId::BayerGrbg => demosaic::debayer_with_offset(mosaic_band, 1, 0)?,
+            ColorFormatId::BayerGbrg => demosaic::debayer_with_offset(mosaic_band, 0, 1)?,
+            ColorFormatId::BayerBggr => demosaic::debayer_with_offset(mosaic_band, 1, 1)?,
+            ColorFormatId::BayerCyym => {
+                demosaic::demosaic_cmy(mosaic_band, [Cyan, Yellow, Yellow, Magenta])?
+            }
+            ColorFormatId::BayerYcmy => {
+                demosaic::demosaic_cmy(mosaic_band, [Yellow, Cyan, Magenta, Yellow])?
+            }
+            ColorFormatId::BayerYmcy => {
+                demosaic::demosaic_cmy(mosaic_band, [Yellow, Magenta, Cyan, Yellow])?
+            }
+            ColorFormatId::BayerMyyc => {
+                demosaic::demosaic_cmy(mosaic_band, [Magenta, Yellow, Yellow, Cyan])?
+            }
+        };
+
+        Ok(SerFrame {
+            buffer: demosaiced,
+            timestamp: self.timestamp,
+        })
+    }
+}
+
+/// Values recorders pack into the instrument and telescope header strings as run-together
+/// key=value pairs, e.g. `ASI=ZWO ASI174MMtemp=45.8` and `fps=55.25gain=160exp=2.50`
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+pub struct HeaderMetadata {
+    pub camera_model: Option<String>,
+    pub sensor_temperature: Option<f32>,
+    pub fps: Option<f32>,
+    pub gain: Option<f32>,
+    pub exposure_ms: Option<f32>,
+}
+
+// Keys are matched case insensitively. The camera keys are vendor prefixes: `ASI=ZWO ASI174MM`.
+const CAMERA_KEYS: [&str; 4] = ["camera", "asi", "qhy", "player"];
+const TEMPERATURE_KEYS: [&str; 2] = ["temp", "temperature"];
+const FPS_KEYS: [&str; 1] = ["fps"];
+const GAIN_KEYS: [&str; 1] = ["gain"];
+const EXPOSURE_KEYS: [&str; 3] = ["exp", "exposure", "shutter"];
+
+/// Cuts a fixed size header string at its first NUL and trims it. Recorders don't clear the
+/// buffer, so whatever follows the NUL is leftover memory.
+fn trim_header_string(s: String) -> String {
+    s.split('\0').next().unwrap_or_default().trim().to_string()
+}
+
+/// Splits run-together `key=value` pairs, where a value ends where the next known key begins
+fn split_header_pairs(s: &str) -> Vec<(String, String)> {
+    // ASCII lowercasing keeps byte offsets valid in the original string
+    let lower = s.to_ascii_lowercase();
+    let all_keys = CAMERA_KEYS
+        .iter()
+        .chain(TEMPERATURE_KEYS.iter())
+        .chain(FPS_KEYS.iter())
+        .chain(GAIN_KEYS.iter())
+        .chain(EXPOSURE_KEYS.iter());
+
+    // (key start, value start) of every recognized key
+    let mut key_positions: Vec<(usize, usize)> = all_keys
+        .flat_map(|k| {
+            let pattern = format!("{}=", k);
+            lower
+                .match_indices(&pattern)
+                .map(|(i, _)| (i, i + pattern.len()))
+                .collect::<Vec<(usize, usize)>>()
+        })
+        .collect();
+    key_positions.sort();
+
+    key_positions
+        .iter()
+        .enumerate()
+        .map(|(i, (key_start, value_start))| {
+            let value_end = key_positions.get(i + 1).map(|p| p.0).unwrap_or(s.len());
+            (
+                lower[*key_start..*value_start - 1].to_string(),
+                s[*value_start..value_end].trim().to_string(),
+            )
+        })
+        .collect()
+}
+
+impl HeaderMetadata {
+    pub fn parse(instrument: &str, telescope: &str) -> HeaderMetadata {
+        let pairs: Vec<(String, String)> = split_header_pairs(instrument)
+            .into_iter()
+            .chain(split_header_pairs(telescope))
+            .collect();
+
+        let find = |keys: &[&str]| {
+            pairs
+                .iter()
+                .find(|(k, _)| keys.contains(&k.as_str()))
+                .map(|(_, v)| v.clone())
+        };
+        let find_number = |keys: &[&str]| find(keys).and_then(|v| v.parse::<f32>().ok());
+
+        // Recorders that don't pack key=value pairs put the bare camera name in the instrument
+        let camera_model = match find(&CAMERA_KEYS) {
+            Some(c) => Some(c),
+            None if !instrument.contains('=') && !instrument.is_empty() => {
+                Some(instrument.to_string())
+            }
+            None => None,
+        };
+
+        HeaderMetadata {
+            camera_model,
+            sensor_temperature: find_number(&TEMPERATURE_KEYS),
+            fps: find_number(&FPS_KEYS),
+            gain: find_number(&GAIN_KEYS),
+            exposure_ms: find_number(&EXPOSURE_KEYS),
+        }
+    }
+}
+
+/// A problem found by `SerFile::validate`
+#[derive(Debug, Clone, PartialEq)]
+pub enum SerIssue {
+    /// The file ends part way through the frame block
+    TruncatedFrames {
+        complete_frames: usize,
+        declared_frames: usize,
+    },
+
+    /// The file holds more frames than the header says it does
+    FrameCountMismatch {
+        declared_frames: usize,
+        frames_in_file: usize,
+    },
+
+    /// No timestamp trailer. Common and harmless for captures that never had one.
+    MissingTimestamps,
+
+    /// The timestamp trailer stops short of the last frame
+    PartialTimestamps {
+        timestamps_present: usize,
+        declared_frames: usize,
+    },
+
+    /// A frame is timestamped earlier than the one before it
+    NonMonotonicTimestamps { frame_num: usize },
+
+    /// Bytes beyond the end of the timestamp trailer
+    TrailingBytes { num_bytes: usize },
+}
+
+impl SerIssue {
+    /// Whether the issue means frames or timestamps were lost. Missing timestamps and out of
+    /// order timestamps are worth knowing about, but leave the file intact.
+    pub fn is_damage(&self) -> bool {
+        !matches!(
+            self,
+            SerIssue::MissingTimestamps | SerIssue::NonMonotonicTimestamps { .. }
+        )
+    }
+}
+
+impl fmt::Display for SerIssue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SerIssue::TruncatedFrames {
+                complete_frames,
+                declared_frames,
+            } => write!(
+                f,
+                "File is truncated: {} of {} frames are complete",
+                complete_frames, declared_frames
+            ),
+            SerIssue::FrameCountMismatch {
+                declared_frames,
+                frames_in_file,
+            } => write!(
+                f,
+                "Header declares {} frames but the file holds {}",
+                declared_frames, frames_in_file
+            ),
+            SerIssue::MissingTimestamps => write!(f, "No timestamp trailer"),
+            SerIssue::PartialTimestamps {
+                timestamps_present,
+                declared_frames,
+            } => write!(
+                f,
+                "Timestamp trailer is incomplete: {} of {} timestamps present",
+                timestamps_present, declared_frames
+            ),
+            SerIssue::NonMonotonicTimestamps { frame_num } => write!(
+                f,
+                "Frame {} is timestamped earlier than the frame before it",
+                frame_num
+            ),
+            SerIssue::TrailingBytes { num_bytes } => {
+                write!(
+                    f,
+                    "{} unexpected bytes after the timestamp trailer",
+                    num_bytes
+                )
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SerValidationReport {
+    pub declared_frames: usize,
+
+    // Frames wholly present in the file, which is what `ser-repair` keeps
+    pub complete_frames: usize,
+
+    // Timestamps present in the trailer, if the trailer is usable
+    pub timestamps_present: usize,
+    pub issues: Vec<SerIssue>,
+}
+
+impl SerValidationReport {
+    /// True if nothing was lost. A file without a timestamp trailer is still intact.
+    pub fn is_intact(&self) -> bool {
+        !self.issues.iter().any(|i| i.is_damage())
+    }
+
+    /// Logs each issue as a warning
+    pub fn warn_issues(&self, source_file: &str) {
+        self.issues
+            .iter()
+            .filter(|i| i.is_damage())
+            .for_each(|i| warn!("{}: {}", source_file, i));
+    }
+
+    pub fn print(&self) {
+        println!("Declared Frames: {}", self.declared_frames);
+        println!("Complete Frames: {}", self.complete_frames);
+        println!("Timestamps Present: {}", self.timestamps_present);
+        if self.issues.is_empty() {
+            println!("No issues found");
+        }
+        for issue in self.issues.iter() {
+            println!(
+                "{}: {}",
+                if issue.is_damage() { "Error" } else { "Note" },
+                issue
+            );
+        }
+    }
+}
+
+/// Header values in a form suited to indexing capture logs (`solha ser-info --json`)
+#[derive(Debug, Clone, Serialize)]
+pub struct SerHeaderSummary {
+    pub source_file: String,
+    pub file_id: String,
+    pub camera_series_id: i32,
+    pub color_id: String,
+    pub image_width: usize,
+    pub image_height: usize,
+    pub pixel_depth: usize,
+    pub msb_aligned: bool,
+    pub frame_count: usize,
+    pub observer: String,
+    pub instrument: String,
+    pub telescope: String,
+    pub date_time: String,
+    pub date_time_utc: String,
+    pub has_timestamps: bool,
+    pub total_size: usize,
+    pub metadata: HeaderMetadata,
 }
 
 // Full implementation of the SER specification is sorta impractical at this time
 // since I lack both the requisite test data and the motivation to actually do it.
 impl SerFile {
+    pub fn header_summary(&self) -> SerHeaderSummary {
+        SerHeaderSummary {
+            source_file: self.source_file.clone(),
+            file_id: self.file_id.clone(),
+            camera_series_id: self.camera_series_id,
+            color_id: format!("{:?}", self.color_id),
+            image_width: self.image_width,
+            image_height: self.image_height,
+            pixel_depth: self.pixel_depth,
+            msb_aligned: self.msb_aligned,
+            frame_count: self.frame_count,
+            observer: self.observer.clone(),
+            instrument: self.instrument.clone(),
+            telescope: self.telescope.clone(),
+            date_time: self.date_time.to_iso8601(),
+            date_time_utc: self.date_time_utc.to_iso8601(),
+            has_timestamps: self.has_timestamps(),
+            total_size: self.total_size,
+            metadata: self.header_metadata.clone(),
+        }
+    }
+
     pub fn print_header_details(&self) {
         println!("SER Header Values:");
         println!("File Id: {}", self.file_id);
@@ -120,46 +457,85 @@ impl SerFile {
         println!("Image Width: {}", self.image_width);
         println!("Image Height: {}", self.image_height);
         println!("Pixel Depth: {}", self.pixel_depth);
+        if self.msb_aligned {
+            println!("Pixel Alignment: MSB");
+        }
         println!("Frame Count: {}", self.frame_count);
         println!("Observer: {}", self.observer);
         println!("Instrument: {}", self.instrument);
         println!("Telescope: {}", self.telescope);
+        if let Some(camera_model) = &self.header_metadata.camera_model {
+            println!("Camera Model: {}", camera_model);
+        }
+        if let Some(temperature) = self.header_metadata.sensor_temperature {
+            println!("Sensor Temperature: {}", temperature);
+        }
+        if let Some(fps) = self.header_metadata.fps {
+            println!("FPS: {}", fps);
+        }
+        if let Some(gain) = self.header_metadata.gain {
+            println!("Gain: {}", gain);
+        }
+        if let Some(exposure) = self.header_metadata.exposure_ms {
+            println!("Exposure (ms): {}", exposure);
+        }
         println!("Date/Time: {:?}", self.date_time);
         println!("Date/Time UTC: {:?}", self.date_time_utc);
         println!("Total File Size: {}", self.total_size);
-        println!(
-            "Bytes per image: {}",
-            self.image_width * self.image_height * (self.pixel_depth / 8)
-        );
+        println!("Bytes per image: {}", self.image_frame_size_bytes());
     }
 
     pub fn load_ser(file_path: &str) -> Result<SerFile> {
+        let file_map = unsafe { Mmap::map(&File::open(file_path)?)? };
         let mut file_reader =
             BinFileReader::new_as_endiness(&file_path.to_string(), Endian::LittleEndian);
         let endiness = Endian::from_i32(file_reader.read_i32(22)?)?; // 4 bytes, start at 22
+        let big_endian = matches!(endiness, Endian::BigEndian);
         file_reader.set_endiness(endiness);
 
         // Some values are ok to default out, others need to propogate their errors
-        let ser = SerFile {
-            file_id: file_reader.read_string(0, 14).unwrap_or(String::default()), // 14 bytes
+        let mut ser = SerFile {
+            file_id: trim_header_string(
+                file_reader.read_string(0, 14).unwrap_or(String::default()),
+            ), // 14 bytes
             camera_series_id: file_reader.read_i32(14).unwrap_or(0), // 4 bytes, start at 14
-            color_id: ColorFormatId::from_i32(file_reader.read_i32(18).unwrap_or(0)), // 4 bytes, start at 18
+            color_id: ColorFormatId::from_i32(file_reader.read_i32(18).unwrap_or(0))?, // 4 bytes, start at 18
             image_width: file_reader.read_i32(26)? as usize, // 4 bytes, start at 26
             image_height: file_reader.read_i32(30)? as usize, // 4 bytes, start at 30
             pixel_depth: file_reader.read_i32(34)? as usize, // 4 bytes, start at 34
             frame_count: file_reader.read_i32(38)? as usize, // 4 bytes, start at 38
-            observer: file_reader.read_string(42, 40).unwrap_or(String::default()), // 40 bytes, start at 42
-            instrument: file_reader.read_string(82, 40).unwrap_or(String::default()), // 40 bytes, start at 82
-            telescope: file_reader
-                .read_string(122, 40)
-                .unwrap_or(String::default()), // 40 bytes, start at 122
+            observer: trim_header_string(
+                file_reader.read_string(42, 40).unwrap_or(String::default()),
+            ), // 40 bytes, start at 42
+            instrument: trim_header_string(
+                file_reader.read_string(82, 40).unwrap_or(String::default()),
+            ), // 40 bytes, start at 82
+            telescope: trim_header_string(
+                file_reader
+                    .read_string(122, 40)
+                    .unwrap_or(String::default()),
+            ), // 40 bytes, start at 122
             date_time: timestamp::TimeStamp::from_u64(file_reader.read_u64(162)?), // 8 bytes, start at 162
             date_time_utc: timestamp::TimeStamp::from_u64(file_reader.read_u64(170)?), // 8 bytes, start at 170
             total_size: file_reader.len(),
+            msb_aligned: false,
+            header_metadata: HeaderMetadata::default(),
+            capture_metadata: capturemeta::CaptureMetadata::load_for_capture(file_path),
+            big_endian,
             file_reader,
+            file_map,
             source_file: file_path.to_string(),
         };
 
+        if ser.pixel_depth == 0 || ser.pixel_depth > 16 {
+            return Err(anyhow!(
+                "Encountered unsupported pixel depth: {}",
+                ser.pixel_depth
+            ));
+        }
+        ser.msb_aligned = ser.detect_msb_alignment();
+        ser.header_metadata = HeaderMetadata::parse(&ser.instrument, &ser.telescope);
+
         if stump::is_verbose() {
             ser.print_header_details();
         }
@@ -167,16 +543,91 @@ impl SerFile {
         Ok(ser)
     }
 
+    /// Bytes used to store a single value of a single plane
+    pub fn bytes_per_value(&self) -> usize {
+        if self.pixel_depth > 8 {
+            2
+        } else {
+            1
+        }
+    }
+
+    /// Bytes per pixel, across all planes
+    pub fn bytes_per_pixel(&self) -> usize {
+        self.bytes_per_value() * self.color_id.num_planes()
+    }
+
+    /// The largest value representable at the file's pixel depth (i.e. the saturation point)
+    pub fn max_value(&self) -> f32 {
+        ((1_u32 << self.pixel_depth) - 1) as f32
+    }
+
+    /// Image mode that fits the file's pixel depth
+    pub fn image_mode(&self) -> ImageMode {
+        image_mode_for_depth(self.pixel_depth)
+    }
+
+    /// Factor decoded values are scaled by, so the file's saturation point is the image mode's
+    /// maximum. One for 8, 12 and 16 bit data.
+    pub fn value_scale(&self) -> f32 {
+        depth_scale(self.pixel_depth)
+    }
+
+    /// Some capture software writes 10, 12 or 14 bit data into the upper bits of a 16 bit value
+    /// while still recording the true depth in the header. If the first, middle and last complete
+    /// frames contain values that cannot exist at the header's pixel depth, all of which have the
+    /// unused low bits clear, the data is assumed to be shifted up.
+    fn detect_msb_alignment(&self) -> bool {
+        let complete_frames = self.complete_frame_count();
+        if self.pixel_depth <= 8 || self.pixel_depth >= 16 || complete_frames == 0 {
+            return false;
+        }
+
+        let mut sample_frames = vec![0, complete_frames / 2, complete_frames - 1];
+        sample_frames.dedup();
+
+        let low_bits_mask = (1_u16 << (16 - self.pixel_depth)) - 1;
+        let max_value = self.max_value() as u16;
+        let mut exceeds_max = false;
+
+        for frame_num in sample_frames {
+            let frame_bytes = match self.get_frame_bytes(frame_num) {
+                Ok(b) => b,
+                Err(_) => return false,
+            };
+
+            for value_bytes in frame_bytes.chunks_exact(2) {
+                let v = self.decode_u16(value_bytes);
+                if v & low_bits_mask != 0 {
+                    return false;
+                }
+                exceeds_max |= v > max_value;
+            }
+        }
+
+        exceeds_max
+    }
+
+    fn decode_u16(&self, value_bytes: &[u8]) -> u16 {
+        let bytes = [value_bytes[0], value_bytes[1]];
+        if self.big_endian {
+            u16::from_be_bytes(bytes)
+        } else {
+            u16::from_le_bytes(bytes)
+        }
+    }
+
     pub fn image_frame_size_bytes(&self) -> usize {
-        self.image_width * self.image_height * (self.pixel_depth / 8)
+        self.image_width * self.image_height * self.bytes_per_pixel()
     }
 
     pub fn image_frame_start_index(&self, frame_num: usize) -> usize {
         HEADER_SIZE_BYTES + (self.image_frame_size_bytes() * frame_num)
     }
 
+    /// Whether the file carries a complete timestamp trailer
     pub fn has_timestamps(&self) -> bool {
-        self.total_size > self.timestamp_block_start_index()
+        self.frame_count > 0 && self.total_size >= self.timestamp_start_index(self.frame_count)
     }
 
     pub fn timestamp_block_start_index(&self) -> usize {
@@ -197,9 +648,139 @@ impl SerFile {
             (8 * self.frame_count * has_ts) // Timestamps
     }
 
-    pub fn validate(&self) {
-        let expected_size = self.expected_size();
-        assert_eq!(self.total_size, expected_size);
+    /// Number of frames wholly present in the file. Less than the header's frame count if the
+    /// capture was cut short.
+    pub fn complete_frame_count(&self) -> usize {
+        self.frame_count.min(self.frames_in_file())
+    }
+
+    // Number of whole frames the file's size allows for, ignoring the header's frame count
+    fn frames_in_file(&self) -> usize {
+        let frame_size = self.image_frame_size_bytes();
+        if frame_size == 0 {
+            return 0;
+        }
+        self.total_size.saturating_sub(HEADER_SIZE_BYTES) / frame_size
+    }
+
+    /// Checks the file's size against its header and the timestamp trailer for consistency.
+    /// Problems are reported rather than raised, since most of them still leave usable frames.
+    pub fn validate(&self) -> SerValidationReport {
+        let mut report = SerValidationReport {
+            declared_frames: self.frame_count,
+            complete_frames: self.complete_frame_count(),
+            timestamps_present: 0,
+            issues: vec![],
+        };
+
+        let frames_end = self.timestamp_block_start_index();
+        if self.total_size < frames_end {
+            report.issues.push(SerIssue::TruncatedFrames {
+                complete_frames: report.complete_frames,
+                declared_frames: self.frame_count,
+            });
+            report.issues.push(SerIssue::MissingTimestamps);
+            return report;
+        }
+
+        let trailer_size = self.total_size - frames_end;
+        let expected_trailer_size = self.frame_count * TIMESTAMP_SIZE_BYTES;
+        if trailer_size == 0 {
+            report.issues.push(SerIssue::MissingTimestamps);
+        } else if trailer_size < expected_trailer_size {
+            report.timestamps_present = trailer_size / TIMESTAMP_SIZE_BYTES;
+            report.issues.push(SerIssue::PartialTimestamps {
+                timestamps_present: report.timestamps_present,
+                declared_frames: self.frame_count,
+            });
+        } else if trailer_size > expected_trailer_size
+            && trailer_size >= self.image_frame_size_bytes()
+        {
+            // Capture software that patches the frame count in when it's done can leave the
+            // header short if it doesn't get the chance to
+            report.issues.push(SerIssue::FrameCountMismatch {
+                declared_frames: self.frame_count,
+                frames_in_file: self.frames_in_file(),
+            });
+            report.complete_frames = self.frames_in_file();
+        } else {
+            report.timestamps_present = self.frame_count;
+            if trailer_size > expected_trailer_size {
+                report.issues.push(SerIssue::TrailingBytes {
+                    num_bytes: trailer_size - expected_trailer_size,
+                });
+            }
+        }
+
+        let mut previous: Option<u64> = None;
+        for frame_num in 0..report.timestamps_present {
+            let ts = match self
+                .file_reader
+                .read_u64_with_endiness(self.timestamp_start_index(frame_num), Endian::NativeEndian)
+            {
+                Ok(ts) => ts,
+                Err(_) => break,
+            };
+            if previous.is_some_and(|p| ts < p) {
+                report
+                    .issues
+                    .push(SerIssue::NonMonotonicTimestamps { frame_num });
+            }
+            previous = Some(ts);
+        }
+
+        report
+    }
+
+    /// Writes a copy of the file holding every complete frame, with the header's frame count set
+    /// to match. The timestamp trailer is carried over only if it covers all of those frames.
+    /// Returns the number of frames written.
+    pub fn write_repaired(&self, output_file: &str) -> Result<usize> {
+        let report = self.validate();
+        let keep_timestamps = report.timestamps_present >= report.complete_frames;
+
+        let mut writer = SerWriter::new_from_ser(output_file, self)?;
+        let frame_size = self.image_frame_size_bytes();
+        for frame_num in 0..report.complete_frames {
+            let start = self.image_frame_start_index(frame_num);
+            let timestamp = if keep_timestamps {
+                Some(self.file_reader.read_u64_with_endiness(
+                    self.timestamp_start_index(frame_num),
+                    Endian::NativeEndian,
+                )?)
+            } else {
+                None
+            };
+            writer.add_frame_bytes(&self.file_map[start..start + frame_size], timestamp)?;
+        }
+        writer.close()?;
+
+        Ok(report.complete_frames)
+    }
+
+    /// The raw 178 byte header block, as it exists in the file
+    pub fn get_header_bytes(&self) -> Result<&[u8]> {
+        if self.file_map.len() < HEADER_SIZE_BYTES {
+            return Err(anyhow!("File too short to contain a SER header"));
+        }
+        Ok(&self.file_map[0..HEADER_SIZE_BYTES])
+    }
+
+    /// The raw, undecoded bytes of a single frame
+    pub fn get_frame_bytes(&self, frame_num: usize) -> Result<&[u8]> {
+        if frame_num >= self.frame_count {
+            return Err(anyhow!("Frame number out of range"));
+        }
+
+        let start = self.image_frame_start_index(frame_num);
+        let end = start + self.image_frame_size_bytes();
+        if end > self.file_map.len() {
+            return Err(anyhow!(
+                "Frame {} extends past the end of the file",
+                frame_num
+            ));
+        }
+        Ok(&self.file_map[start..end])
     }
 
     pub fn get_frame_timestamp(&self, frame_num: usize) -> Result<u64> {
@@ -207,8 +788,13 @@ impl SerFile {
             return Err(anyhow!("Frame number out of range"));
         }
 
+        // Without a trailer, fall back to the times the capture software recorded alongside
         if !self.has_timestamps() {
-            return Ok(0);
+            return Ok(self
+                .capture_metadata
+                .as_ref()
+                .and_then(|m| m.frame_timestamp(frame_num))
+                .unwrap_or(0));
         }
 
         let timestamp_start_index = self.timestamp_start_index(frame_num);
@@ -216,74 +802,381 @@ impl SerFile {
             .read_u64_with_endiness(timestamp_start_index, Endian::NativeEndian)
     }
 
-    pub fn get_frame(&self, frame_num: usize) -> Result<SerFrame> {
-        if frame_num >= self.frame_count {
-            return Err(anyhow!("Frame number out of range"));
-        }
-
-        let image_frame_size_bytes = self.image_frame_size_bytes();
-        let image_frame_start_index = self.image_frame_start_index(frame_num);
+    /// Reads the pixel values of a frame. Values of multi-plane formats are left interleaved.
+    fn read_frame_values(&self, frame_num: usize) -> Result<Vec<f32>> {
+        let frame_bytes = self.get_frame_bytes(frame_num)?;
 
         info!(
             "Extracting image frame #{} of {} from {}. Size {} at byte index {}",
             frame_num,
             self.frame_count,
             self.source_file,
-            image_frame_size_bytes,
-            image_frame_start_index
+            frame_bytes.len(),
+            self.image_frame_start_index(frame_num)
         );
 
-        let mut values: Vec<f32> = Vec::with_capacity(self.image_width * self.image_height);
-        values.resize(self.image_width * self.image_height, 0.0);
+        // Byte order and alignment are constant for the whole file, so they're resolved once here
+        // rather than per value.
+        let shift = if self.msb_aligned {
+            16 - self.pixel_depth
+        } else {
+            0
+        };
+
+        let mut values: Vec<f32> = if self.bytes_per_value() == 1 {
+            frame_bytes.iter().map(|v| *v as f32).collect()
+        } else if self.big_endian {
+            frame_bytes
+                .chunks_exact(2)
+                .map(|v| (u16::from_be_bytes([v[0], v[1]]) >> shift) as f32)
+                .collect()
+        } else {
+            frame_bytes
+                .chunks_exact(2)
+                .map(|v| (u16::from_le_bytes([v[0], v[1]]) >> shift) as f32)
+                .collect()
+        };
 
-        let bytes_per_pixel = self.pixel_depth / 8;
-        for y in 0..self.image_height {
-            for x in 0..self.image_width {
-                let pixel_start =
-                    (x + (y * self.image_width)) * bytes_per_pixel + image_frame_start_index;
-                let pixel_value: f32;
-
-                if self.pixel_depth == 8 {
-                    pixel_value = self.file_reader.read_u8(pixel_start)? as f32;
-                } else if self.pixel_depth == 16 {
-                    pixel_value = self.file_reader.read_u16(pixel_start)? as f32;
+        // Multiplied before dividing, so the saturation point lands exactly on the mode maximum
+        if self.value_scale() != 1.0 {
+            let mode_max = util::mode_max_value(self.image_mode());
+            let max_value = self.max_value();
+            values
+                .iter_mut()
+                .for_each(|v| *v = *v * mode_max / max_value);
+        }
+
+        Ok(values)
+    }
+
+    /// Returns a frame without debayering. Mono and Bayer formats produce a single band frame
+    /// holding the sensor values as they were recorded, Rgb and Bgr are still split into bands.
+    pub fn get_frame_mosaic(&self, frame_num: usize) -> Result<SerFrame> {
+        let values = self.read_frame_values(frame_num)?;
+        let timestamp = self.get_frame_timestamp(frame_num)?;
+
+        match self.color_id {
+            ColorFormatId::Rgb | ColorFormatId::Bgr => {
+                let planes = (0..3)
+                    .map(|p| {
+                        imagebuffer::ImageBuffer::from_vec_as_mode(
+                            &values.iter().skip(p).step_by(3).copied().collect(),
+                            self.image_width,
+                            self.image_height,
+                            self.image_mode(),
+                        )
+                    })
+                    .collect::<Result<Vec<imagebuffer::ImageBuffer>>>()?;
+
+                if self.color_id == ColorFormatId::Rgb {
+                    Ok(SerFrame::new_three_channel(
+                        &planes[0], &planes[1], &planes[2], timestamp,
+                    ))
                 } else {
-                    panic!("Encountered unsupported pixel depth: {}", self.pixel_depth);
+                    Ok(SerFrame::new_three_channel(
+                        &planes[2], &planes[1], &planes[0], timestamp,
+                    ))
                 }
+            }
+            _ => {
+                let frame_buffer = imagebuffer::ImageBuffer::from_vec_as_mode(
+                    &values,
+                    self.image_width,
+                    self.image_height,
+                    self.image_mode(),
+                )?;
+                Ok(SerFrame::new(&frame_buffer, timestamp))
+            }
+        }
+    }
+
+    /// Returns a frame with Bayer and CMY mosaics demosaiced into RGB
+    pub fn get_frame(&self, frame_num: usize) -> Result<SerFrame> {
+        self.get_frame_mosaic(frame_num)?.demosaic(self.color_id)
+    }
+}
+
+/// Image mode that fits a pixel depth
+pub fn image_mode_for_depth(pixel_depth: usize) -> ImageMode {
+    match pixel_depth {
+        0..=8 => ImageMode::U8BIT,
+        9..=12 => ImageMode::U12BIT,
+        _ => ImageMode::U16BIT,
+    }
+}
+
+// Factor taking values at a pixel depth to the range of its image mode
+fn depth_scale(pixel_depth: usize) -> f32 {
+    util::mode_max_value(image_mode_for_depth(pixel_depth)) / ((1_u32 << pixel_depth) - 1) as f32
+}
 
-                values[x + (y * self.image_width)] = pixel_value;
+/// Writes a SER v3 file frame by frame. The header is written up front with a frame count of
+/// zero and is patched when the writer is closed, after which the timestamp trailer is appended.
+pub struct SerWriter {
+    pub file_id: String,
+    pub camera_series_id: i32,
+    pub color_id: ColorFormatId,
+    pub image_width: usize,
+    pub image_height: usize,
+    pub pixel_depth: usize,
+    pub observer: String,
+    pub instrument: String,
+    pub telescope: String,
+    pub date_time: u64,
+    pub date_time_utc: u64,
+    pub big_endian: bool,
+    pub output_file: String,
+    frame_count: usize,
+    timestamps: Vec<Option<u64>>,
+    writer: Option<BufWriter<File>>,
+    header_template: Option<Vec<u8>>,
+}
+
+fn put_fixed_string(buffer: &mut Vec<u8>, s: &str, size: usize) {
+    let mut bytes = s.as_bytes().to_vec();
+    bytes.resize(size, 0);
+    buffer.extend_from_slice(&bytes);
+}
+
+impl SerWriter {
+    pub fn new(
+        output_file: &str,
+        image_width: usize,
+        image_height: usize,
+        pixel_depth: usize,
+        color_id: ColorFormatId,
+    ) -> SerWriter {
+        SerWriter {
+            file_id: "LUCAM-RECORDER".to_string(),
+            camera_series_id: 0,
+            color_id,
+            image_width,
+            image_height,
+            pixel_depth,
+            observer: String::default(),
+            instrument: String::default(),
+            telescope: String::default(),
+            date_time: 0,
+            date_time_utc: 0,
+            big_endian: false,
+            output_file: output_file.to_string(),
+            frame_count: 0,
+            timestamps: vec![],
+            writer: None,
+            header_template: None,
+        }
+    }
+
+    /// Creates a writer carrying over the header of an existing SER file, including its byte order
+    /// so that raw frames can be copied across unchanged. Header strings that are left untouched
+    /// keep the source's bytes, including anything after the terminator.
+    pub fn new_from_ser(output_file: &str, ser_file: &SerFile) -> Result<SerWriter> {
+        let mut writer = SerWriter::new(
+            output_file,
+            ser_file.image_width,
+            ser_file.image_height,
+            ser_file.pixel_depth,
+            ser_file.color_id,
+        );
+        writer.file_id = ser_file.file_id.clone();
+        writer.camera_series_id = ser_file.camera_series_id;
+        writer.observer = ser_file.observer.clone();
+        writer.instrument = ser_file.instrument.clone();
+        writer.telescope = ser_file.telescope.clone();
+        writer.date_time = ser_file.date_time.timestamp;
+        writer.date_time_utc = ser_file.date_time_utc.timestamp;
+        writer.big_endian = ser_file.big_endian;
+        writer.header_template = Some(ser_file.get_header_bytes()?.to_vec());
+        Ok(writer)
+    }
+
+    pub fn bytes_per_pixel_value(&self) -> usize {
+        if self.pixel_depth > 8 {
+            2
+        } else {
+            1
+        }
+    }
+
+    pub fn image_frame_size_bytes(&self) -> usize {
+        self.image_width
+            * self.image_height
+            * self.bytes_per_pixel_value()
+            * self.color_id.num_planes()
+    }
+
+    pub fn frame_count(&self) -> usize {
+        self.frame_count
+    }
+
+    fn header_i32(&self, v: i32) -> [u8; 4] {
+        if self.big_endian {
+            v.to_be_bytes()
+        } else {
+            v.to_le_bytes()
+        }
+    }
+
+    fn header_u64(&self, v: u64) -> [u8; 8] {
+        if self.big_endian {
+            v.to_be_bytes()
+        } else {
+            v.to_le_bytes()
+        }
+    }
+
+    fn header_bytes(&self) -> Vec<u8> {
+        let endian_field = if self.big_endian {
+            BIG_ENDIAN_FIELD_VALUE
+        } else {
+            LITTLE_ENDIAN_FIELD_VALUE
+        };
+
+        let mut header: Vec<u8> = Vec::with_capacity(HEADER_SIZE_BYTES);
+        put_fixed_string(&mut header, &self.file_id, FILE_ID_SIZE_BYTES);
+        header.extend_from_slice(&self.header_i32(self.camera_series_id));
+        header.extend_from_slice(&self.header_i32(self.color_id as i32));
+        // The byte order field itself is always read as little-endian
+        header.extend_from_slice(&endian_field.to_le_bytes());
+        header.extend_from_slice(&self.header_i32(self.image_width as i32));
+        header.extend_from_slice(&self.header_i32(self.image_height as i32));
+        header.extend_from_slice(&self.header_i32(self.pixel_depth as i32));
+        header.extend_from_slice(&self.header_i32(self.frame_count as i32));
+        put_fixed_string(&mut header, &self.observer, HEADER_STRING_SIZE_BYTES);
+        put_fixed_string(&mut header, &self.instrument, HEADER_STRING_SIZE_BYTES);
+        put_fixed_string(&mut header, &self.telescope, HEADER_STRING_SIZE_BYTES);
+        header.extend_from_slice(&self.header_u64(self.date_time));
+        header.extend_from_slice(&self.header_u64(self.date_time_utc));
+
+        if let Some(template) = &self.header_template {
+            let strings = [
+                (0, FILE_ID_SIZE_BYTES, &self.file_id),
+                (OBSERVER_INDEX, HEADER_STRING_SIZE_BYTES, &self.observer),
+                (INSTRUMENT_INDEX, HEADER_STRING_SIZE_BYTES, &self.instrument),
+                (TELESCOPE_INDEX, HEADER_STRING_SIZE_BYTES, &self.telescope),
+            ];
+            for (start, size, value) in strings {
+                let source = &template[start..start + size];
+                if trim_header_string(String::from_utf8_lossy(source).to_string()) == *value {
+                    header[start..start + size].copy_from_slice(source);
+                }
             }
         }
+        header
+    }
 
-        let frame_buffer = imagebuffer::ImageBuffer::from_vec_as_mode(
-            &values,
-            self.image_width,
-            self.image_height,
-            match self.pixel_depth {
-                8 => ImageMode::U8BIT,
-                _ => ImageMode::U16BIT,
-            },
-        )
-        .expect("Failed to allocate image buffer");
+    fn get_writer(&mut self) -> Result<&mut BufWriter<File>> {
+        if self.writer.is_none() {
+            info!("Creating SER file {}", self.output_file);
+            let mut writer = BufWriter::new(File::create(&self.output_file)?);
+            writer.write_all(&self.header_bytes())?;
+            self.writer = Some(writer);
+        }
+        Ok(self.writer.as_mut().unwrap())
+    }
 
-        match self.color_id {
-            ColorFormatId::Mono => Ok(SerFrame::new(
-                &frame_buffer,
-                self.get_frame_timestamp(frame_num)
-                    .expect("Failed to extract frame timestamp"),
-            )),
-            ColorFormatId::BayerRggb => {
-                let debayered =
-                    debayer::debayer(&frame_buffer, debayer::DebayerMethod::AMaZE).unwrap();
-                Ok(SerFrame::new_rgb(
-                    debayered,
-                    self.get_frame_timestamp(frame_num)
-                        .expect("Failed to extract frame timestamp"),
+    /// Appends a frame of raw, already encoded pixel data. The data must be exactly one frame in size.
+    pub fn add_frame_bytes(&mut self, frame_bytes: &[u8], timestamp: Option<u64>) -> Result<()> {
+        if frame_bytes.len() != self.image_frame_size_bytes() {
+            return Err(anyhow!(
+                "Frame data is {} bytes, expected {}",
+                frame_bytes.len(),
+                self.image_frame_size_bytes()
+            ));
+        }
+
+        self.get_writer()?.write_all(frame_bytes)?;
+        self.timestamps.push(timestamp);
+        self.frame_count += 1;
+        Ok(())
+    }
+
+    /// Encodes and appends a decoded frame. Bayer formats cannot be re-encoded from a debayered
+    /// image, use `add_frame_bytes` with the raw mosaic for those.
+    pub fn add_frame(&mut self, frame: &SerFrame) -> Result<()> {
+        let timestamp = match frame.timestamp.timestamp {
+            0 => None,
+            ts => Some(ts),
+        };
+        let frame_bytes = self.encode_image(&frame.buffer)?;
+        self.add_frame_bytes(&frame_bytes, timestamp)
+    }
+
+    /// Converts an image into SER pixel data for this writer's geometry and color format
+    pub fn encode_image(&self, img: &image::Image) -> Result<Vec<u8>> {
+        if img.width != self.image_width || img.height != self.image_height {
+            return Err(anyhow!(
+                "Image dimensions {}x{} do not match SER dimensions {}x{}",
+                img.width,
+                img.height,
+                self.image_width,
+                self.image_height
+            ));
+        }
+
+        let band_order: Vec<usize> = match self.color_id {
+            ColorFormatId::Rgb => vec![0, 1, 2],
+            ColorFormatId::Bgr => vec![2, 1, 0],
+            ColorFormatId::Mono => vec![0],
+            _ => {
+                return Err(anyhow!(
+                    "Cannot encode a debayered image as {:?}",
+                    self.color_id
                 ))
             }
-            _ => {
-                panic!("Unsupported color mode: {:?}", self.color_id);
+        };
+
+        if band_order.len() > img.num_bands() {
+            return Err(anyhow!(
+                "Image has {} bands, {:?} requires {}",
+                img.num_bands(),
+                self.color_id,
+                band_order.len()
+            ));
+        }
+
+        // Decoded values are in the image mode's range, see SerFile::value_scale
+        let scale = depth_scale(self.pixel_depth);
+        let max_value = ((1_u32 << self.pixel_depth) - 1) as f32;
+        let mut frame_bytes: Vec<u8> = Vec::with_capacity(self.image_frame_size_bytes());
+        for y in 0..self.image_height {
+            for x in 0..self.image_width {
+                for band in band_order.iter() {
+                    let v = (img.get_band(*band).get(x, y) / scale)
+                        .round()
+                        .clamp(0.0, max_value);
+                    if self.bytes_per_pixel_value() == 1 {
+                        frame_bytes.push(v as u8);
+                    } else if self.big_endian {
+                        frame_bytes.extend_from_slice(&(v as u16).to_be_bytes());
+                    } else {
+                        frame_bytes.extend_from_slice(&(v as u16).to_le_bytes());
+                    }
+                }
             }
         }
+        Ok(frame_bytes)
+    }
+
+    /// Writes the timestamp trailer and final frame count, consuming the writer. The trailer is
+    /// only written if at least one frame was given a timestamp.
+    pub fn close(mut self) -> Result<()> {
+        let header = self.header_bytes();
+        let timestamps = self.timestamps.clone();
+        let writer = self.get_writer()?;
+
+        if timestamps.iter().any(|ts| ts.is_some()) {
+            if timestamps.iter().any(|ts| ts.is_none()) {
+                warn!("Some frames are missing timestamps, writing them as zero");
+            }
+            for ts in timestamps.iter() {
+                writer.write_all(&ts.unwrap_or(0).to_le_bytes())?;
+            }
+        }
+
+        writer.seek(SeekFrom::Start(0))?;
+        writer.write_all(&header)?;
+        writer.flush()?;
+        Ok(())
     }
 }
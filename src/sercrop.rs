@@ -0,0 +1,143 @@
+use crate::ser;
+use anyhow::{anyhow, Result};
+use rayon::prelude::*;
+use sciimg::path;
+
+/// Top left corner of a crop box of the given size, centered on the object's center of mass and
+/// clamped to the frame. Bayer mosaics get an even corner so the color pattern is preserved.
+pub fn crop_origin_for_frame(
+    ser_file: &ser::SerFile,
+    frame_num: usize,
+    crop_width: usize,
+    crop_height: usize,
+    obj_detect_threshold: f32,
+) -> Result<(usize, usize)> {
+    let frame = ser_file.get_frame(frame_num)?;
+    let offset = frame
+        .buffer
+        .calc_center_of_mass_offset(obj_detect_threshold, 0);
+
+    // The offset is the shift that would move the center of mass to the center of the frame
+    let center_x = ser_file.image_width as f32 / 2.0 - offset.h;
+    let center_y = ser_file.image_height as f32 / 2.0 - offset.v;
+
+    let max_x = (ser_file.image_width - crop_width) as f32;
+    let max_y = (ser_file.image_height - crop_height) as f32;
+    let mut x = (center_x - crop_width as f32 / 2.0)
+        .round()
+        .clamp(0.0, max_x) as usize;
+    let mut y = (center_y - crop_height as f32 / 2.0)
+        .round()
+        .clamp(0.0, max_y) as usize;
+
+    if ser_file.color_id.is_bayer() {
+        x -= x % 2;
+        y -= y % 2;
+    }
+
+    info!(
+        "Frame #{} center of mass at {}, {}. Crop origin {}, {}",
+        frame_num, center_x, center_y, x, y
+    );
+    Ok((x, y))
+}
+
+/// Copies a box out of a raw SER frame without decoding it
+pub fn crop_frame_bytes(
+    ser_file: &ser::SerFile,
+    frame_num: usize,
+    x: usize,
+    y: usize,
+    crop_width: usize,
+    crop_height: usize,
+) -> Result<Vec<u8>> {
+    let frame_bytes = ser_file.get_frame_bytes(frame_num)?;
+    let bytes_per_pixel = ser_file.bytes_per_pixel();
+    let row_bytes = crop_width * bytes_per_pixel;
+
+    let mut cropped: Vec<u8> = Vec::with_capacity(row_bytes * crop_height);
+    for row in y..(y + crop_height) {
+        let start = (row * ser_file.image_width + x) * bytes_per_pixel;
+        cropped.extend_from_slice(&frame_bytes[start..(start + row_bytes)]);
+    }
+    Ok(cropped)
+}
+
+/// Writes every frame of the input SER files into a new SER file, cropped to a fixed size box
+/// centered on the object in each frame. Returns the number of frames written.
+pub fn crop_ser_files(
+    input_files: &[&str],
+    output_file: &str,
+    crop_width: usize,
+    crop_height: usize,
+    obj_detect_threshold: f32,
+) -> Result<usize> {
+    if input_files.is_empty() {
+        return Err(anyhow!("No input files specified"));
+    }
+
+    let mut writer: Option<ser::SerWriter> = None;
+
+    for input_file in input_files {
+        if !path::file_exists(input_file) {
+            return Err(anyhow!("File not found: {}", input_file));
+        }
+
+        let ser_file = ser::SerFile::load_ser(input_file)?;
+
+        if crop_width > ser_file.image_width || crop_height > ser_file.image_height {
+            return Err(anyhow!(
+                "Crop size {}x{} exceeds frame size {}x{} of {}",
+                crop_width,
+                crop_height,
+                ser_file.image_width,
+                ser_file.image_height,
+                input_file
+            ));
+        }
+
+        if ser_file.color_id.is_bayer()
+            && (!crop_width.is_multiple_of(2) || !crop_height.is_multiple_of(2))
+        {
+            return Err(anyhow!("Crop size must be even for Bayer formatted files"));
+        }
+
+        if writer.is_none() {
+            let mut w = ser::SerWriter::new_from_ser(output_file, &ser_file)?;
+            w.image_width = crop_width;
+            w.image_height = crop_height;
+            writer = Some(w);
+        }
+        let w = writer.as_mut().unwrap();
+
+        if w.pixel_depth != ser_file.pixel_depth || w.color_id != ser_file.color_id {
+            return Err(anyhow!(
+                "SER file {} does not match the pixel format of the first input",
+                input_file
+            ));
+        }
+
+        // Centering is the expensive part, so do it in parallel and write the frames in order after
+        let origins = (0..ser_file.frame_count)
+            .into_par_iter()
+            .map(|i| {
+                crop_origin_for_frame(&ser_file, i, crop_width, crop_height, obj_detect_threshold)
+            })
+            .collect::<Result<Vec<(usize, usize)>>>()?;
+
+        for (i, (x, y)) in origins.iter().enumerate() {
+            let timestamp = if ser_file.has_timestamps() {
+                Some(ser_file.get_frame_timestamp(i)?)
+            } else {
+                None
+            };
+            let cropped = crop_frame_bytes(&ser_file, i, *x, *y, crop_width, crop_height)?;
+            w.add_frame_bytes(&cropped, timestamp)?;
+        }
+    }
+
+    let writer = writer.unwrap();
+    let frames_written = writer.frame_count();
+    writer.close()?;
+    Ok(frames_written)
+}
@@ -0,0 +1,112 @@
+use crate::ser;
+use anyhow::{anyhow, Result};
+use sciimg::path;
+
+/// Frame selection applied across the concatenation of all input files. Frame indices are zero
+/// based and count continuously from the first frame of the first file.
+#[derive(Debug, Clone, Default)]
+pub struct FrameSelection {
+    pub start_frame: usize,
+    pub end_frame: Option<usize>, // Inclusive
+    pub every_nth: usize,
+    pub drop_frames: Vec<usize>,
+}
+
+impl FrameSelection {
+    pub fn includes(&self, frame_num: usize) -> bool {
+        if frame_num < self.start_frame {
+            return false;
+        }
+
+        if let Some(end_frame) = self.end_frame {
+            if frame_num > end_frame {
+                return false;
+            }
+        }
+
+        if self.every_nth > 1 && !(frame_num - self.start_frame).is_multiple_of(self.every_nth) {
+            return false;
+        }
+
+        !self.drop_frames.contains(&frame_num)
+    }
+}
+
+fn check_matching_geometry(first: &ser::SerFile, other: &ser::SerFile) -> Result<()> {
+    if first.image_width != other.image_width
+        || first.image_height != other.image_height
+        || first.pixel_depth != other.pixel_depth
+        || first.color_id != other.color_id
+    {
+        Err(anyhow!(
+            "SER file {} ({}x{}, {} bit, {:?}) does not match {} ({}x{}, {} bit, {:?})",
+            other.source_file,
+            other.image_width,
+            other.image_height,
+            other.pixel_depth,
+            other.color_id,
+            first.source_file,
+            first.image_width,
+            first.image_height,
+            first.pixel_depth,
+            first.color_id
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+/// Writes the selected frames of one or more SER files into a single new SER file. The header of
+/// the first input is carried over and timestamps are copied from each source's trailer. Returns the
+/// number of frames written.
+pub fn cut_ser_files(
+    input_files: &[&str],
+    output_file: &str,
+    selection: &FrameSelection,
+) -> Result<usize> {
+    if input_files.is_empty() {
+        return Err(anyhow!("No input files specified"));
+    }
+
+    let ser_files = input_files
+        .iter()
+        .map(|f| {
+            if !path::file_exists(f) {
+                Err(anyhow!("File not found: {}", f))
+            } else {
+                ser::SerFile::load_ser(f)
+            }
+        })
+        .collect::<Result<Vec<ser::SerFile>>>()?;
+
+    for ser_file in ser_files.iter().skip(1) {
+        check_matching_geometry(&ser_files[0], ser_file)?;
+    }
+
+    let mut writer = ser::SerWriter::new_from_ser(output_file, &ser_files[0])?;
+
+    let mut frame_num = 0;
+    for ser_file in ser_files.iter() {
+        for i in 0..ser_file.frame_count {
+            if selection.includes(frame_num) {
+                let timestamp = if ser_file.has_timestamps() {
+                    Some(ser_file.get_frame_timestamp(i)?)
+                } else {
+                    None
+                };
+                info!(
+                    "Copying frame #{} of {} as output frame #{}",
+                    i,
+                    ser_file.source_file,
+                    writer.frame_count()
+                );
+                writer.add_frame_bytes(ser_file.get_frame_bytes(i)?, timestamp)?;
+            }
+            frame_num += 1;
+        }
+    }
+
+    let frames_written = writer.frame_count();
+    writer.close()?;
+    Ok(frames_written)
+}
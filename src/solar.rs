@@ -12,7 +12,7 @@ pub fn position_from_lat_lon_and_time(lat: f64, lon: f64, ts: &timestamp::TimeSt
     let unixtime = ts.to_unix_timestamp();
 
     info!("Time {:?} converted to unix timestamp {}", ts, unixtime);
-    let pos = sun::pos(unixtime * 1000, lat, lon);
+    let pos = sun::pos((unixtime * 1000.0) as i64, lat, lon);
 
     (pos.altitude.to_degrees(), pos.azimuth.to_degrees())
 }
@@ -0,0 +1,298 @@
+// Synthetic flats built from the session's own frames. Etalons put Newton rings and banding on
+// the sensor that stay put while the disk drifts and the seeing shifts solar detail around, so a
+// median of many frames in sensor coordinates keeps the pattern and washes out the Sun. Dividing
+// out the disk's radial limb profile, then anything broader than the rings, leaves the flat.
+
+use crate::capturemeta::CaptureSettings;
+use crate::enums::CalibrationFrameType;
+use crate::framesource::{FrameSource, MultiFrameSource};
+use crate::geometry::{solve3, Disk};
+use crate::master::{self, CombineMethod, MasterFrame, MasterMetadata};
+use crate::util::{self, median};
+use anyhow::{anyhow, Result};
+use rayon::prelude::*;
+use sciimg::image;
+
+pub const DEFAULT_MAX_FRAMES: usize = 64;
+pub const DEFAULT_LIMB_MARGIN: f32 = 0.95;
+pub const DEFAULT_HIGHPASS_RADIUS: usize = 48;
+
+// Frames whose disk is further than this fraction of the radius from the typical position are
+// left out, so the limb of an off-center frame doesn't print into the median
+const MAX_CENTER_DEVIATION: f32 = 0.1;
+
+pub struct SyntheticFlatOptions {
+    // Frames are sampled evenly across the inputs, up to this many
+    pub max_frames: usize,
+    pub obj_detect_threshold: f32,
+
+    // Fraction of the disk radius the flat covers. The limb itself is too steep to model.
+    pub limb_margin: f32,
+
+    // Half width of the window broad variations are removed over. 0 keeps them.
+    pub highpass_radius: usize,
+}
+
+impl Default for SyntheticFlatOptions {
+    fn default() -> Self {
+        SyntheticFlatOptions {
+            max_frames: DEFAULT_MAX_FRAMES,
+            obj_detect_threshold: 40.0,
+            limb_margin: DEFAULT_LIMB_MARGIN,
+            highpass_radius: DEFAULT_HIGHPASS_RADIUS,
+        }
+    }
+}
+
+/// Limb darkening model, a polynomial in (r / radius)^2 fitted to the median brightness at
+/// each whole-pixel distance from the disk center. Being smooth, it can't soak up rings or
+/// banding the way the binned medians would near the center, where bins hold few pixels.
+struct RadialProfile {
+    radius: f32,
+    coefficients: [f64; 3],
+}
+
+impl RadialProfile {
+    fn fit(values: &[f32], width: usize, disk: &Disk, max_radius: f32) -> RadialProfile {
+        let mut bins: Vec<Vec<f32>> = vec![vec![]; max_radius.ceil() as usize + 1];
+        for (i, v) in values.iter().enumerate() {
+            let r = disk.distance(i % width, i / width);
+            if r <= max_radius {
+                bins[r.round() as usize].push(*v);
+            }
+        }
+
+        // Weighted least squares over the bins, weighted by their pixel counts
+        let mut normal = [[0.0_f64; 3]; 3];
+        let mut rhs = [0.0_f64; 3];
+        for (r, bin) in bins.iter_mut().enumerate() {
+            if bin.is_empty() {
+                continue;
+            }
+            let weight = bin.len() as f64;
+            let u = (r as f64 / disk.radius as f64).powi(2);
+            let terms = [1.0, u, u * u];
+            let m = median(bin) as f64;
+            for j in 0..3 {
+                rhs[j] += weight * terms[j] * m;
+                for k in 0..3 {
+                    normal[j][k] += weight * terms[j] * terms[k];
+                }
+            }
+        }
+
+        RadialProfile {
+            radius: disk.radius,
+            coefficients: solve3(normal, rhs).unwrap_or([0.0; 3]),
+        }
+    }
+
+    fn at(&self, r: f32) -> f32 {
+        let u = (r as f64 / self.radius as f64).powi(2);
+        let c = self.coefficients;
+        (c[0] + c[1] * u + c[2] * u * u) as f32
+    }
+}
+
+/// Mean over a square window of the masked values, from summed-area tables
+fn masked_box_mean(
+    values: &[f32],
+    mask: &[bool],
+    width: usize,
+    height: usize,
+    radius: usize,
+) -> Vec<f32> {
+    let stride = width + 1;
+    let mut sums = vec![0.0_f64; stride * (height + 1)];
+    let mut counts = vec![0.0_f64; stride * (height + 1)];
+    for y in 0..height {
+        for x in 0..width {
+            let (v, c) = if mask[y * width + x] {
+                (values[y * width + x] as f64, 1.0)
+            } else {
+                (0.0, 0.0)
+            };
+            let i = (y + 1) * stride + x + 1;
+            sums[i] = v + sums[i - 1] + sums[i - stride] - sums[i - stride - 1];
+            counts[i] = c + counts[i - 1] + counts[i - stride] - counts[i - stride - 1];
+        }
+    }
+
+    (0..width * height)
+        .map(|i| {
+            let (x, y) = (i % width, i / width);
+            let (x0, y0) = (x.saturating_sub(radius), y.saturating_sub(radius));
+            let (x1, y1) = ((x + radius + 1).min(width), (y + radius + 1).min(height));
+            let window = |table: &[f64]| {
+                table[y1 * stride + x1] - table[y0 * stride + x1] - table[y1 * stride + x0]
+                    + table[y0 * stride + x0]
+            };
+            let count = window(&counts);
+            if count > 0.0 {
+                (window(&sums) / count) as f32
+            } else {
+                1.0
+            }
+        })
+        .collect()
+}
+
+/// Builds a flat from light frames of the disk. A dark, if given, is subtracted from each frame
+/// first.
+pub fn build_synthetic_flat(
+    input_files: &[&str],
+    dark: &Option<image::Image>,
+    options: &SyntheticFlatOptions,
+) -> Result<MasterFrame> {
+    let frame_source = MultiFrameSource::from_paths(input_files)?;
+    let (width, height, num_bands) = (
+        frame_source.image_width(),
+        frame_source.image_height(),
+        frame_source.num_bands(),
+    );
+    if let Some(dark) = dark {
+        if dark.width != width || dark.height != height || dark.num_bands() != num_bands {
+            return Err(anyhow!(
+                "Dark frame is {}x{}x{}, frames are {}x{}x{}",
+                dark.width,
+                dark.height,
+                dark.num_bands(),
+                width,
+                height,
+                num_bands
+            ));
+        }
+    }
+
+    let frame_count = frame_source.frame_count();
+    let step = (frame_count as f32 / options.max_frames.max(1) as f32).max(1.0);
+    let frame_nums: Vec<usize> = (0..options.max_frames.min(frame_count))
+        .map(|i| (i as f32 * step) as usize)
+        .collect();
+
+    let frames: Vec<(image::Image, Disk)> = frame_nums
+        .par_iter()
+        .map(|n| -> Result<Option<(image::Image, Disk)>> {
+            let mut frame = frame_source.read_frame(*n)?.frame.buffer;
+            if let Some(dark) = dark {
+                for band in 0..num_bands {
+                    for y in 0..height {
+                        for x in 0..width {
+                            let v = frame.get_band(band).get(x, y) - dark.get_band(band).get(x, y);
+                            frame.put(x, y, v.max(0.0), band);
+                        }
+                    }
+                }
+            }
+            Ok(Disk::find(&frame, options.obj_detect_threshold).map(|d| (frame, d)))
+        })
+        .collect::<Result<Vec<Option<(image::Image, Disk)>>>>()?
+        .into_iter()
+        .flatten()
+        .collect();
+    if frames.is_empty() {
+        return Err(anyhow!(
+            "No frames with a disk over the detection threshold"
+        ));
+    }
+
+    let typical = Disk {
+        center_x: median(&mut frames.iter().map(|(_, d)| d.center_x).collect::<Vec<f32>>()),
+        center_y: median(&mut frames.iter().map(|(_, d)| d.center_y).collect::<Vec<f32>>()),
+        radius: median(&mut frames.iter().map(|(_, d)| d.radius).collect::<Vec<f32>>()),
+    };
+    let frames: Vec<&image::Image> = frames
+        .iter()
+        .filter(|(_, d)| {
+            let dx = d.center_x - typical.center_x;
+            let dy = d.center_y - typical.center_y;
+            (dx * dx + dy * dy).sqrt() <= typical.radius * MAX_CENTER_DEVIATION
+        })
+        .map(|(f, _)| f)
+        .collect();
+    if frames.is_empty() {
+        return Err(anyhow!(
+            "No frames with the disk near its typical position, the disk may be moving too much"
+        ));
+    }
+    vprintln!(
+        "Building synthetic flat from {} frames, disk radius {:.1} px at ({:.1}, {:.1})",
+        frames.len(),
+        typical.radius,
+        typical.center_x,
+        typical.center_y
+    );
+
+    let mode = frame_source.image_mode();
+    let level = util::mode_max_value(mode) * master::NORMALIZED_FLAT_LEVEL;
+    let mut flat = image::Image::new_with_bands(width, height, num_bands, mode)?;
+
+    for band in 0..num_bands {
+        let stacked: Vec<f32> = (0..width * height)
+            .into_par_iter()
+            .map(|i| {
+                let mut values: Vec<f32> = frames
+                    .iter()
+                    .map(|f| f.get_band(band).get(i % width, i / width))
+                    .collect();
+                median(&mut values)
+            })
+            .collect();
+
+        // The median has a disk of its own, which is where the profile is centered
+        let disk = {
+            let mut stacked_image = image::Image::new_with_bands(width, height, 1, mode)?;
+            for (i, v) in stacked.iter().enumerate() {
+                stacked_image.put(i % width, i / width, *v, 0);
+            }
+            Disk::find(&stacked_image, options.obj_detect_threshold).unwrap_or(typical)
+        };
+        let max_radius = disk.radius * options.limb_margin;
+        let profile = RadialProfile::fit(&stacked, width, &disk, max_radius);
+
+        let mask: Vec<bool> = (0..width * height)
+            .map(|i| disk.distance(i % width, i / width) <= max_radius)
+            .collect();
+        let mut residual: Vec<f32> = stacked
+            .iter()
+            .enumerate()
+            .map(|(i, v)| {
+                let model = profile.at(disk.distance(i % width, i / width));
+                if mask[i] && model > 0.0 {
+                    v / model
+                } else {
+                    1.0
+                }
+            })
+            .collect();
+
+        if options.highpass_radius > 0 {
+            let broad = masked_box_mean(&residual, &mask, width, height, options.highpass_radius);
+            for (i, r) in residual.iter_mut().enumerate() {
+                if mask[i] && broad[i] > 0.0 {
+                    *r /= broad[i];
+                }
+            }
+        }
+
+        for (i, r) in residual.iter().enumerate() {
+            flat.put(i % width, i / width, r * level, band);
+        }
+    }
+
+    Ok(MasterFrame {
+        image: flat,
+        metadata: MasterMetadata {
+            method: CombineMethod::Median,
+            frame_type: Some(CalibrationFrameType::Flat),
+            image_width: width,
+            image_height: height,
+            frame_count: frames.len(),
+            source_files: input_files.iter().map(|f| f.to_string()).collect(),
+            settings: CaptureSettings::for_input(input_files[0]),
+            bias_subtracted: false,
+            normalized: true,
+            rejected_fraction: 0.0,
+        },
+    })
+}
@@ -1,17 +1,16 @@
 extern crate astro;
 use astro::*;
 
-use chrono::{NaiveDate, NaiveDateTime};
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime, Timelike};
+use std::time::{SystemTime, UNIX_EPOCH};
 
 const SEPTASECONDS_PER_SECOND: u64 = 10000000;
-const SEPTASECONDS_PER_MICROSECOND: u64 = 10;
-//const SEPTASECONDS_PER_PART_MINUTE : u64 = SEPTASECONDS_PER_DAY * 6;
-const SEPTASECONDS_PER_MINUTE: u64 = SEPTASECONDS_PER_SECOND * 60;
-const SEPTASECONDS_PER_HOUR: u64 = SEPTASECONDS_PER_SECOND * 60 * 60;
-const SEPTASECONDS_PER_DAY: u64 = SEPTASECONDS_PER_HOUR * 24;
-const DAYS_PER_400_YEARS: u64 = 303 * 365 + 97 * 366;
-//const SEPTASECONDS_PER_400_YEARS : u64 = DAYS_PER_400_YEARS * SEPTASECONDS_PER_DAY;
+const NANOSECONDS_PER_SEPTASECOND: u64 = 100;
+// 1970-01-01 00:00:00 expressed in SER ticks (100ns since 0001-01-01)
+const SEPTASECONDS_AT_UNIX_EPOCH: u64 = 621355968000000000;
 
+/// A SER timestamp: ticks of 100ns since 0001-01-01 00:00:00 on the proleptic Gregorian calendar.
+/// The calendar fields are derived from the ticks and are truncated to the microsecond.
 #[derive(Debug, Copy, Clone, PartialEq)]
 pub struct TimeStamp {
     pub timestamp: u64,
@@ -24,86 +23,84 @@ pub struct TimeStamp {
     pub microsecond: i32,
 }
 
-impl TimeStamp {
-    fn is_leap_year(year: u64) -> bool {
-        (year % 400 == 0 || year % 4 == 0) && year % 100 != 0
-    }
+/// The zero point of SER ticks
+fn ser_epoch() -> NaiveDateTime {
+    NaiveDate::from_ymd_opt(1, 1, 1)
+        .unwrap()
+        .and_hms_opt(0, 0, 0)
+        .unwrap()
+}
 
-    // This code is adapter from ser_viewer/pipp_timestmp.cpp, (c) 2015 by Chris Garry
+impl TimeStamp {
     pub fn from_u64(ts_u64: u64) -> TimeStamp {
-        let ts = ts_u64 % SEPTASECONDS_PER_DAY;
-
-        let hours = ts / SEPTASECONDS_PER_HOUR;
-        let minutes = (ts % SEPTASECONDS_PER_HOUR) / SEPTASECONDS_PER_MINUTE;
-        let seconds = (ts % SEPTASECONDS_PER_MINUTE) / SEPTASECONDS_PER_SECOND;
-        let microseconds = (ts % SEPTASECONDS_PER_SECOND) / SEPTASECONDS_PER_MICROSECOND;
-        let mut days_ts = ts_u64 / SEPTASECONDS_PER_DAY;
-        let mut year = 0;
-
-        for y in (1..9999).step_by(400) {
-            year = y;
-            if days_ts >= DAYS_PER_400_YEARS {
-                days_ts -= DAYS_PER_400_YEARS;
-            } else {
-                break;
-            }
+        let date_time = TimeStamp::ticks_to_naive_date_time(ts_u64);
+        TimeStamp {
+            timestamp: ts_u64,
+            year: date_time.year(),
+            month: date_time.month() as i32,
+            day: date_time.day() as i32,
+            hour: date_time.hour() as i32,
+            minute: date_time.minute() as i32,
+            second: date_time.second() as i32,
+            microsecond: (date_time.nanosecond() / 1000) as i32,
         }
+    }
 
-        for y in year..9999 {
-            let year = y;
-            let days_this_year = if TimeStamp::is_leap_year(year) {
-                366
-            } else {
-                365
-            };
+    fn ticks_to_naive_date_time(ts_u64: u64) -> NaiveDateTime {
+        ser_epoch()
+            + Duration::seconds((ts_u64 / SEPTASECONDS_PER_SECOND) as i64)
+            + Duration::nanoseconds(
+                ((ts_u64 % SEPTASECONDS_PER_SECOND) * NANOSECONDS_PER_SEPTASECOND) as i64,
+            )
+    }
 
-            if days_ts >= days_this_year {
-                days_ts -= days_this_year;
-            } else {
-                break;
-            }
+    /// Converts without loss beyond the 100ns tick resolution. Times before 0001-01-01 can't be
+    /// represented and are clamped to it.
+    pub fn from_naive_date_time(date_time: &NaiveDateTime) -> TimeStamp {
+        let since_epoch = date_time.signed_duration_since(ser_epoch());
+        let seconds = since_epoch.num_seconds();
+        let subsec_nanos = (since_epoch - Duration::seconds(seconds))
+            .num_nanoseconds()
+            .unwrap_or(0);
+
+        if seconds < 0 {
+            return TimeStamp::from_u64(0);
         }
+        TimeStamp::from_u64(
+            seconds as u64 * SEPTASECONDS_PER_SECOND
+                + subsec_nanos as u64 / NANOSECONDS_PER_SEPTASECOND,
+        )
+    }
 
-        let mut month = 0;
-        for m in 1..13 {
-            let days_this_month = match m {
-                4 | 6 | 9 | 11 => 30,
-                2 => {
-                    if TimeStamp::is_leap_year(year) {
-                        29
-                    } else {
-                        28
-                    }
-                }
-                _ => 31,
-            };
+    pub fn from_system_time(time: SystemTime) -> TimeStamp {
+        let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+        TimeStamp::from_u64(
+            SEPTASECONDS_AT_UNIX_EPOCH
+                + since_epoch.as_secs() * SEPTASECONDS_PER_SECOND
+                + since_epoch.subsec_nanos() as u64 / NANOSECONDS_PER_SEPTASECOND,
+        )
+    }
 
-            month = m;
-            if days_ts >= days_this_month {
-                days_ts -= days_this_month;
-            } else {
-                break;
-            }
-        }
+    /// The timestamp at full tick resolution
+    pub fn to_naive_date_time(&self) -> NaiveDateTime {
+        TimeStamp::ticks_to_naive_date_time(self.timestamp)
+    }
 
-        TimeStamp {
-            timestamp: ts_u64,
-            year: year as i32,
-            month,
-            day: days_ts as i32 + 1,
-            hour: hours as i32,
-            minute: minutes as i32,
-            second: seconds as i32,
-            microsecond: microseconds as i32,
-        }
+    pub fn to_iso8601(&self) -> String {
+        format!(
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:06}",
+            self.year, self.month, self.day, self.hour, self.minute, self.second, self.microsecond
+        )
     }
 
     pub fn to_julian_day(&self) -> f64 {
+        let seconds_into_day = (self.timestamp % (SEPTASECONDS_PER_SECOND * 86400)) as f64
+            / SEPTASECONDS_PER_SECOND as f64;
         let day_of_month = time::DayOfMonth {
             day: self.day as u8,
-            hr: self.hour as u8,
-            min: self.minute as u8,
-            sec: self.second as f64,
+            hr: 0,
+            min: 0,
+            sec: seconds_into_day,
             time_zone: 0.0,
         };
 
@@ -117,12 +114,9 @@ impl TimeStamp {
         time::julian_day(&date)
     }
 
-    pub fn to_unix_timestamp(&self) -> i64 {
-        let date_time: NaiveDateTime =
-            NaiveDate::from_ymd_opt(self.year, self.month as u32, self.day as u32)
-                .unwrap()
-                .and_hms_opt(self.hour as u32, self.minute as u32, self.second as u32)
-                .unwrap();
-        date_time.timestamp()
+    /// Seconds since 1970-01-01 00:00:00 UTC, including the fractional second
+    pub fn to_unix_timestamp(&self) -> f64 {
+        (self.timestamp as i64 - SEPTASECONDS_AT_UNIX_EPOCH as i64) as f64
+            / SEPTASECONDS_PER_SECOND as f64
     }
 }
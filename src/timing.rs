@@ -0,0 +1,270 @@
+// Frame timing diagnostics. Gaps between frames point to dropped frames or USB stalls, and
+// header times that disagree with the per-frame timestamps point to a misconfigured clock or
+// time zone in the capture software, which throws off derotation.
+
+use crate::util;
+use anyhow::{anyhow, Result};
+use std::fmt;
+
+const TICKS_PER_MILLISECOND: f64 = 10000.0;
+const TICKS_PER_SECOND: f64 = 10000000.0;
+
+// How far outside the span of frame timestamps the header's capture time may fall
+const HEADER_TOLERANCE_SECONDS: f64 = 2.0;
+
+// Local time offsets from UTC come in multiples of 15 minutes. Some slack is allowed since
+// capture software doesn't always read the two clocks at the same instant.
+const TIME_ZONE_STEP_SECONDS: f64 = 900.0;
+const TIME_ZONE_TOLERANCE_SECONDS: f64 = 60.0;
+
+pub const DEFAULT_GAP_FACTOR: f64 = 2.0;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TimingIssue {
+    /// The interval to `frame_num` from the frame before it is well over the typical interval
+    Gap {
+        frame_num: usize,
+        interval_ms: f64,
+        estimated_dropped_frames: usize,
+    },
+
+    /// `frame_num` has the same timestamp as the frame before it
+    Duplicate { frame_num: usize },
+
+    /// `frame_num` is timestamped earlier than the frame before it
+    OutOfOrder { frame_num: usize },
+
+    /// The header's UTC capture time is nowhere near the frame timestamps
+    HeaderUtcMismatch { seconds_from_first_frame: f64 },
+
+    /// The header's local and UTC times differ by something other than a time zone offset
+    IrregularUtcOffset { offset_hours: f64 },
+
+    /// The frame timestamps line up with the header's local time rather than UTC
+    TimestampsInLocalTime { offset_hours: f64 },
+}
+
+impl fmt::Display for TimingIssue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TimingIssue::Gap {
+                frame_num,
+                interval_ms,
+                estimated_dropped_frames,
+            } => write!(
+                f,
+                "Gap of {:.3} ms before frame {} (about {} frames dropped)",
+                interval_ms, frame_num, estimated_dropped_frames
+            ),
+            TimingIssue::Duplicate { frame_num } => write!(
+                f,
+                "Frame {} has the same timestamp as the frame before it",
+                frame_num
+            ),
+            TimingIssue::OutOfOrder { frame_num } => write!(
+                f,
+                "Frame {} is timestamped earlier than the frame before it",
+                frame_num
+            ),
+            TimingIssue::HeaderUtcMismatch {
+                seconds_from_first_frame,
+            } => write!(
+                f,
+                "Header UTC time is {:.3} seconds from the first frame timestamp",
+                seconds_from_first_frame
+            ),
+            TimingIssue::IrregularUtcOffset { offset_hours } => write!(
+                f,
+                "Header local and UTC times differ by {:.4} hours, which isn't a time zone offset",
+                offset_hours
+            ),
+            TimingIssue::TimestampsInLocalTime { offset_hours } => write!(
+                f,
+                "Frame timestamps match the header local time ({:+.2} hours from UTC) instead of UTC",
+                offset_hours
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TimingReport {
+    pub frame_count: usize,
+    pub first_timestamp: u64,
+    pub last_timestamp: u64,
+    pub duration_seconds: f64,
+    pub min_interval_ms: f64,
+    pub max_interval_ms: f64,
+    pub mean_interval_ms: f64,
+    pub median_interval_ms: f64,
+    pub stddev_interval_ms: f64,
+
+    // Frames per second over the whole capture, counting any gaps
+    pub effective_fps: f64,
+
+    // Frames per second the camera was running at, from the median interval
+    pub nominal_fps: f64,
+    pub issues: Vec<TimingIssue>,
+}
+
+impl TimingReport {
+    /// Analyzes per-frame timestamps (SER ticks, in frame order). An interval more than
+    /// `gap_factor` (greater than 1.0) times the median is reported as a gap.
+    pub fn from_timestamps(timestamps: &[u64], gap_factor: f64) -> Result<TimingReport> {
+        if gap_factor.is_nan() || gap_factor <= 1.0 {
+            return Err(anyhow!(
+                "Gap factor must be greater than 1.0, got {}",
+                gap_factor
+            ));
+        }
+        if timestamps.len() < 2 {
+            return Err(anyhow!(
+                "At least two frame timestamps are needed to analyze timing"
+            ));
+        }
+
+        let intervals_ms: Vec<f64> = timestamps
+            .windows(2)
+            .map(|w| (w[1] as i64 - w[0] as i64) as f64 / TICKS_PER_MILLISECOND)
+            .collect();
+
+        // Sorted by the median, which leaves the shortest and longest intervals at the ends
+        let mut sorted = intervals_ms.clone();
+        let median_interval_ms = util::median(&mut sorted);
+
+        let mean_interval_ms = intervals_ms.iter().sum::<f64>() / intervals_ms.len() as f64;
+        let stddev_interval_ms = (intervals_ms
+            .iter()
+            .map(|i| (i - mean_interval_ms).powi(2))
+            .sum::<f64>()
+            / intervals_ms.len() as f64)
+            .sqrt();
+
+        let mut issues = vec![];
+        for (i, interval_ms) in intervals_ms.iter().enumerate() {
+            let frame_num = i + 1;
+            if *interval_ms < 0.0 {
+                issues.push(TimingIssue::OutOfOrder { frame_num });
+            } else if *interval_ms == 0.0 {
+                issues.push(TimingIssue::Duplicate { frame_num });
+            } else if median_interval_ms > 0.0 && *interval_ms > median_interval_ms * gap_factor {
+                issues.push(TimingIssue::Gap {
+                    frame_num,
+                    interval_ms: *interval_ms,
+                    estimated_dropped_frames: (interval_ms / median_interval_ms).round() as usize
+                        - 1,
+                });
+            }
+        }
+
+        let first_timestamp = timestamps[0];
+        let last_timestamp = timestamps[timestamps.len() - 1];
+        let duration_seconds =
+            (last_timestamp as i64 - first_timestamp as i64) as f64 / TICKS_PER_SECOND;
+
+        Ok(TimingReport {
+            frame_count: timestamps.len(),
+            first_timestamp,
+            last_timestamp,
+            duration_seconds,
+            min_interval_ms: sorted[0],
+            max_interval_ms: sorted[sorted.len() - 1],
+            mean_interval_ms,
+            median_interval_ms,
+            stddev_interval_ms,
+            effective_fps: if duration_seconds > 0.0 {
+                (timestamps.len() - 1) as f64 / duration_seconds
+            } else {
+                0.0
+            },
+            nominal_fps: if median_interval_ms > 0.0 {
+                1000.0 / median_interval_ms
+            } else {
+                0.0
+            },
+            issues,
+        })
+    }
+
+    fn within_capture(&self, ts: u64) -> bool {
+        let tolerance = (HEADER_TOLERANCE_SECONDS * TICKS_PER_SECOND) as u64;
+        ts + tolerance >= self.first_timestamp
+            && ts <= self.last_timestamp.saturating_add(tolerance)
+    }
+
+    /// Compares the header's local and UTC capture times (SER ticks) against the frame
+    /// timestamps, which SER specifies as UTC
+    pub fn check_header_times(&mut self, date_time: u64, date_time_utc: u64) {
+        if date_time == 0 || date_time_utc == 0 {
+            return;
+        }
+
+        // Differences are taken in integer ticks, since f64 can't hold SER ticks exactly
+        let offset_seconds = (date_time as i64 - date_time_utc as i64) as f64 / TICKS_PER_SECOND;
+        let time_zone_seconds =
+            (offset_seconds / TIME_ZONE_STEP_SECONDS).round() * TIME_ZONE_STEP_SECONDS;
+        let regular_offset =
+            (offset_seconds - time_zone_seconds).abs() <= TIME_ZONE_TOLERANCE_SECONDS;
+        if !regular_offset {
+            self.issues.push(TimingIssue::IrregularUtcOffset {
+                offset_hours: offset_seconds / 3600.0,
+            });
+        }
+
+        if self.within_capture(date_time_utc) {
+            return;
+        }
+
+        if regular_offset && time_zone_seconds != 0.0 && self.within_capture(date_time) {
+            self.issues.push(TimingIssue::TimestampsInLocalTime {
+                offset_hours: time_zone_seconds / 3600.0,
+            });
+        } else {
+            self.issues.push(TimingIssue::HeaderUtcMismatch {
+                seconds_from_first_frame: (date_time_utc as i64 - self.first_timestamp as i64)
+                    as f64
+                    / TICKS_PER_SECOND,
+            });
+        }
+    }
+
+    pub fn estimated_dropped_frames(&self) -> usize {
+        self.issues
+            .iter()
+            .map(|i| match i {
+                TimingIssue::Gap {
+                    estimated_dropped_frames,
+                    ..
+                } => *estimated_dropped_frames,
+                _ => 0,
+            })
+            .sum()
+    }
+}
+
+impl fmt::Display for TimingReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut text = format!("Frames: {}\n", self.frame_count);
+        text += format!("Duration: {:.3} s\n", self.duration_seconds).as_ref();
+        text += format!("Effective FPS: {:.3}\n", self.effective_fps).as_ref();
+        text += format!("Nominal FPS: {:.3}\n", self.nominal_fps).as_ref();
+        text += format!(
+            "Frame Interval (ms): min {:.3}, max {:.3}, mean {:.3}, median {:.3}, std dev {:.3}\n",
+            self.min_interval_ms,
+            self.max_interval_ms,
+            self.mean_interval_ms,
+            self.median_interval_ms,
+            self.stddev_interval_ms
+        )
+        .as_ref();
+        text += format!(
+            "Estimated Dropped Frames: {}\n",
+            self.estimated_dropped_frames()
+        )
+        .as_ref();
+        for issue in self.issues.iter() {
+            text += format!("\t{}\n", issue).as_ref();
+        }
+        write!(f, "{}", text)
+    }
+}
@@ -1,5 +1,7 @@
-use sciimg::path;
+use sciimg::{enums::ImageMode, path};
 
+use std::cmp::Ordering;
+use std::ops::{Add, Div};
 use std::str::FromStr;
 
 #[macro_export]
@@ -60,6 +62,47 @@ pub fn append_file_name(input_file: &str, append: &str) -> String {
     // String::from(out_file)
 }
 
+/// Floating point types ordered with `total_cmp`, so that sorting never panics on a NaN
+pub trait TotalOrder: Copy {
+    fn total_order(&self, other: &Self) -> Ordering;
+}
+
+impl TotalOrder for f32 {
+    fn total_order(&self, other: &Self) -> Ordering {
+        self.total_cmp(other)
+    }
+}
+
+impl TotalOrder for f64 {
+    fn total_order(&self, other: &Self) -> Ordering {
+        self.total_cmp(other)
+    }
+}
+
+/// Median of the values, sorting them in place. The values must not be empty. NaNs sort to the
+/// end (or the start, if negative) rather than panicking.
+pub fn median<T>(values: &mut [T]) -> T
+where
+    T: TotalOrder + Add<Output = T> + Div<Output = T> + From<u8>,
+{
+    values.sort_by(T::total_order);
+    let mid = values.len() / 2;
+    if values.len().is_multiple_of(2) {
+        (values[mid - 1] + values[mid]) / T::from(2)
+    } else {
+        values[mid]
+    }
+}
+
+/// The largest value of an image mode, its saturation point
+pub fn mode_max_value(mode: ImageMode) -> f32 {
+    match mode {
+        ImageMode::U8BIT => 255.0,
+        ImageMode::U12BIT => 4095.0,
+        _ => 65535.0,
+    }
+}
+
 pub fn replace_image_extension(input_file: &str, append: &str) -> String {
     input_file
         .replace(".png", append)
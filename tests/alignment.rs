@@ -0,0 +1,88 @@
+mod common;
+
+use common::*;
+use sciimg::{enums::ImageMode, image};
+use solhat::alignment::{Aligner, AlignmentOptions, PhaseCorrelator};
+use solhat::enums::AlignmentMethod;
+
+const WIDTH: usize = 96;
+const HEIGHT: usize = 80;
+const RADIUS: f32 = 30.0;
+
+// A limb darkened disk with some surface detail, centered at (cx, cy)
+fn disk_image(cx: f32, cy: f32) -> image::Image {
+    mono_image(WIDTH, HEIGHT, |x, y| {
+        let disk = limb_darkened_disk(x, y, cx, cy, RADIUS, 0.0);
+        if disk > 0.0 {
+            let (dx, dy) = (x as f32 - cx, y as f32 - cy);
+            let detail = (dx * 0.7).sin() * (dy * 0.5).cos() + (dx * 0.23 + dy * 0.31).sin();
+            disk + 2000.0 * detail
+        } else {
+            500.0
+        }
+    })
+}
+
+#[test]
+fn test_phase_correlation_finds_subpixel_shift() {
+    let reference = disk_image(48.0, 40.0);
+    let correlator = PhaseCorrelator::new(reference.get_band(0));
+
+    let same = correlator.correlate(reference.get_band(0)).unwrap();
+    assert!(same.dx.abs() < 0.05 && same.dy.abs() < 0.05, "{:?}", same);
+
+    let shifted = disk_image(51.4, 37.3);
+    let c = correlator.correlate(shifted.get_band(0)).unwrap();
+    assert!((c.dx - 3.4).abs() < 0.15, "{:?}", c);
+    assert!((c.dy + 2.7).abs() < 0.15, "{:?}", c);
+    assert!(c.confidence > 10.0, "{:?}", c);
+
+    let wrong_size = image::Image::new_with_bands(8, 8, 1, ImageMode::U16BIT).unwrap();
+    assert!(correlator.correlate(wrong_size.get_band(0)).is_none());
+}
+
+#[test]
+fn test_phase_correlation_ignores_clipped_disk() {
+    // Most of the disk is off the frame edge, which drags the center of mass along with it
+    let reference = disk_image(20.0, 40.0);
+    let shifted = disk_image(14.0, 42.0);
+
+    let options = AlignmentOptions {
+        method: AlignmentMethod::PhaseCorrelation,
+        ..Default::default()
+    };
+    let aligner = Aligner::new(&reference, &options, 1000.0);
+    let reference_offset = reference.calc_center_of_mass_offset(1000.0, 0);
+
+    let alignment = aligner.align(&shifted);
+    assert_eq!(alignment.method, AlignmentMethod::PhaseCorrelation);
+    assert!((alignment.offset.h - (reference_offset.h + 6.0)).abs() < 0.15);
+    assert!((alignment.offset.v - (reference_offset.v - 2.0)).abs() < 0.15);
+}
+
+#[test]
+fn test_low_confidence_falls_back_to_center_of_mass() {
+    let reference = disk_image(48.0, 40.0);
+    let options = AlignmentOptions {
+        method: AlignmentMethod::PhaseCorrelation,
+        ..Default::default()
+    };
+    let aligner = Aligner::new(&reference, &options, 1000.0);
+
+    // Nothing in common with the reference
+    let mut unrelated = image::Image::new_with_bands(WIDTH, HEIGHT, 1, ImageMode::U16BIT).unwrap();
+    for y in 0..HEIGHT {
+        for x in 0..WIDTH {
+            let v = ((x * 7919 + y * 104729) % 251) as f32 * 10.0;
+            unrelated.put(x, y, v, 0);
+        }
+    }
+    let alignment = aligner.align(&unrelated);
+    assert_eq!(alignment.method, AlignmentMethod::CenterOfMass);
+    assert!(alignment.confidence.is_none());
+
+    let com = AlignmentOptions::default();
+    let aligner = Aligner::new(&reference, &com, 1000.0);
+    let alignment = aligner.align(&reference);
+    assert_eq!(alignment.method, AlignmentMethod::CenterOfMass);
+}
@@ -0,0 +1,166 @@
+mod common;
+
+use common::*;
+use solhat::avi;
+use solhat::framesource::{FrameSource, MultiFrameSource};
+use solhat::mean;
+use std::fs;
+
+fn chunk(fourcc: &[u8; 4], body: &[u8]) -> Vec<u8> {
+    let mut bytes = fourcc.to_vec();
+    bytes.extend((body.len() as u32).to_le_bytes());
+    bytes.extend(body);
+    if body.len() % 2 == 1 {
+        bytes.push(0);
+    }
+    bytes
+}
+
+fn list(list_type: &[u8; 4], children: &[Vec<u8>]) -> Vec<u8> {
+    let mut body = list_type.to_vec();
+    children.iter().for_each(|c| body.extend(c));
+    chunk(b"LIST", &body)
+}
+
+// Writes a minimal AVI with an audio stream ahead of the video stream, a JUNK chunk and a
+// dropped (empty) frame, since real captures contain all of those.
+fn write_synthetic_avi(
+    path: &str,
+    width: i32,
+    height: i32,
+    bit_count: u16,
+    compression: &[u8; 4],
+    frames: &[Vec<u8>],
+) {
+    let mut avih = vec![0; 56];
+    avih[0..4].copy_from_slice(&20000_u32.to_le_bytes());
+
+    let mut audio_strh = vec![0; 56];
+    audio_strh[0..4].copy_from_slice(b"auds");
+    let mut video_strh = vec![0; 56];
+    video_strh[0..4].copy_from_slice(b"vids");
+
+    let mut strf = vec![0; 40];
+    strf[0..4].copy_from_slice(&40_u32.to_le_bytes());
+    strf[4..8].copy_from_slice(&width.to_le_bytes());
+    strf[8..12].copy_from_slice(&height.to_le_bytes());
+    strf[12..14].copy_from_slice(&1_u16.to_le_bytes());
+    strf[14..16].copy_from_slice(&bit_count.to_le_bytes());
+    strf[16..20].copy_from_slice(compression);
+
+    let hdrl = list(
+        b"hdrl",
+        &[
+            chunk(b"avih", &avih),
+            list(
+                b"strl",
+                &[chunk(b"strh", &audio_strh), chunk(b"strf", &[0; 18])],
+            ),
+            list(
+                b"strl",
+                &[chunk(b"strh", &video_strh), chunk(b"strf", &strf)],
+            ),
+        ],
+    );
+
+    let mut movi_children = vec![chunk(b"00wb", &[1, 2, 3])];
+    for (i, f) in frames.iter().enumerate() {
+        movi_children.push(chunk(b"01db", f));
+        if i == 0 {
+            movi_children.push(chunk(b"01dc", &[]));
+        }
+    }
+
+    let mut body = b"AVI ".to_vec();
+    body.extend(hdrl);
+    body.extend(chunk(b"JUNK", &[0; 7]));
+    body.extend(list(b"movi", &movi_children));
+    fs::write(path, chunk(b"RIFF", &body)).unwrap();
+}
+
+fn temp_avi_path(name: &str) -> String {
+    temp_ser_path(name).replace(".ser", ".avi")
+}
+
+#[test]
+fn test_avi_y800() {
+    let avi_path = temp_avi_path("avi_y800");
+    let frames = (0..3)
+        .map(|f| (0..6 * 4).map(|i| (i * 10 + f) as u8).collect::<Vec<u8>>())
+        .collect::<Vec<Vec<u8>>>();
+    write_synthetic_avi(&avi_path, 6, 4, 8, b"Y800", &frames);
+
+    let avi_file = avi::AviFile::open(&avi_path).unwrap();
+    assert_eq!(avi_file.pixel_format, avi::AviPixelFormat::Mono8);
+    assert_eq!(avi_file.image_width, 6);
+    assert_eq!(avi_file.image_height, 4);
+    assert_eq!(avi_file.frame_count, 3);
+    assert_eq!(avi_file.num_bands(), 1);
+
+    let frame = avi_file.get_frame(2).unwrap();
+    assert_eq!(frame.buffer.get_band(0).get(0, 0), 2.0);
+    assert_eq!(frame.buffer.get_band(0).get(5, 0), 52.0);
+    assert_eq!(frame.buffer.get_band(0).get(1, 3), 192.0);
+
+    // Frames are spaced at the 20ms frame interval from the avih header
+    assert_eq!(
+        avi_file.get_frame_timestamp(1).unwrap() - avi_file.get_frame_timestamp(0).unwrap(),
+        200000
+    );
+
+    fs::remove_file(&avi_path).unwrap();
+}
+
+#[test]
+fn test_avi_rgb24_bottom_up() {
+    // 3 pixels of BGR make a 9 byte row, padded out to 12
+    let avi_path = temp_avi_path("avi_rgb24");
+    let mut frame = vec![];
+    for y in (0..2).rev() {
+        for x in 0..3 {
+            frame.extend([10 + x + y * 3, 100 + x + y * 3, 200 + x + y * 3]);
+        }
+        frame.extend([0; 3]);
+    }
+    write_synthetic_avi(&avi_path, 3, 2, 24, &[0; 4], &[frame]);
+
+    let avi_file = avi::AviFile::open(&avi_path).unwrap();
+    assert_eq!(avi_file.pixel_format, avi::AviPixelFormat::Bgr24);
+    assert_eq!(avi_file.num_bands(), 3);
+
+    let frame = avi_file.get_frame(0).unwrap();
+    assert_eq!(frame.buffer.get_band(0).get(0, 0), 200.0);
+    assert_eq!(frame.buffer.get_band(1).get(0, 0), 100.0);
+    assert_eq!(frame.buffer.get_band(2).get(0, 0), 10.0);
+    assert_eq!(frame.buffer.get_band(0).get(2, 1), 205.0);
+    assert_eq!(frame.buffer.get_band(2).get(2, 1), 15.0);
+
+    fs::remove_file(&avi_path).unwrap();
+}
+
+#[test]
+fn test_avi_mean() {
+    let avi_path = temp_avi_path("avi_mean");
+    let frames = (0..4)
+        .map(|f| vec![(f * 20) as u8; 8 * 4])
+        .collect::<Vec<Vec<u8>>>();
+    // Palettized 8 bit, rows already a multiple of 4 bytes
+    write_synthetic_avi(&avi_path, 8, 4, 8, &[0; 4], &frames);
+
+    let frame_source = MultiFrameSource::from_paths(&[&avi_path]).unwrap();
+    assert_eq!(frame_source.frame_count(), 4);
+
+    let mean_image = mean::compute_mean(&vec![avi_path.as_str()], true).unwrap();
+    assert_eq!(mean_image.num_bands(), 1);
+    assert_eq!(mean_image.get_band(0).get(3, 2), 30.0);
+
+    fs::remove_file(&avi_path).unwrap();
+}
+
+#[test]
+fn test_avi_unsupported_format() {
+    let avi_path = temp_avi_path("avi_mjpg");
+    write_synthetic_avi(&avi_path, 4, 4, 24, b"MJPG", &[vec![0; 48]]);
+    assert!(avi::AviFile::open(&avi_path).is_err());
+    fs::remove_file(&avi_path).unwrap();
+}
@@ -0,0 +1,83 @@
+mod common;
+
+use common::*;
+use sciimg::{enums::ImageMode, image};
+use solhat::calibration::{self, CalibrationSet};
+use solhat::ser;
+
+const WIDTH: usize = 16;
+const HEIGHT: usize = 12;
+
+fn write_flat_ser(name: &str, value: u16) -> String {
+    write_ser_from_fn(
+        name,
+        WIDTH,
+        HEIGHT,
+        ser::ColorFormatId::Mono,
+        3,
+        |_, _, _| value,
+    )
+}
+
+#[test]
+fn test_load_masters_from_captures_and_images() {
+    let ser_path = write_flat_ser("calibration_master", 500);
+    let from_capture = calibration::load_master(&ser_path).unwrap();
+    assert_eq!(from_capture.width, WIDTH);
+    assert_eq!(from_capture.height, HEIGHT);
+    assert_eq!(from_capture.get_band(0).get(3, 3), 500.0);
+
+    let mut img = image::Image::new_with_bands(WIDTH, HEIGHT, 1, ImageMode::U16BIT).unwrap();
+    img.put(2, 2, 1234.0, 0);
+    let png_path = std::env::temp_dir()
+        .join("solhat_calibration_master.png")
+        .to_string_lossy()
+        .to_string();
+    img.save(&png_path).unwrap();
+    let from_image = calibration::load_master(&png_path).unwrap();
+    assert_eq!(from_image.width, WIDTH);
+    assert_eq!(from_image.get_band(0).get(2, 2), 1234.0);
+
+    assert!(calibration::load_master("/nonexistent/solhat_dark.ser").is_err());
+    assert!(calibration::load_master("/nonexistent/solhat_dark.png").is_err());
+}
+
+#[test]
+fn test_bias_is_loaded_from_its_own_path() {
+    let dark_path = write_flat_ser("calibration_dark", 300);
+    let bias_path = write_flat_ser("calibration_bias", 100);
+
+    let calibration =
+        CalibrationSet::load_paths(&None, &Some(dark_path), &None, &Some(bias_path)).unwrap();
+    assert!(calibration.flat.is_none());
+    assert!(calibration.dark_flat.is_none());
+    assert_eq!(
+        calibration.dark.as_ref().unwrap().get_band(0).get(0, 0),
+        300.0
+    );
+    assert_eq!(
+        calibration.bias.as_ref().unwrap().get_band(0).get(0, 0),
+        100.0
+    );
+
+    assert!(CalibrationSet::load_paths(
+        &Some("/nonexistent/solhat_flat.ser".to_string()),
+        &None,
+        &None,
+        &None
+    )
+    .is_err());
+}
+
+#[test]
+fn test_validate_rejects_mismatched_frames() {
+    let dark_path = write_flat_ser("calibration_validate", 300);
+    let calibration = CalibrationSet::load_paths(&None, &Some(dark_path), &None, &None).unwrap();
+
+    assert!(calibration.validate(WIDTH, HEIGHT, 1).is_ok());
+    assert!(calibration.validate(WIDTH + 2, HEIGHT, 1).is_err());
+    assert!(calibration.validate(WIDTH, HEIGHT, 3).is_err());
+
+    assert!(CalibrationSet::default().is_empty());
+    assert!(CalibrationSet::default().validate(1, 1, 1).is_ok());
+}
@@ -0,0 +1,209 @@
+use solhat::callibrary::{CalibrationFiles, CalibrationLibrary, MatchCriteria};
+use solhat::capturemeta::CaptureSettings;
+use solhat::enums::CalibrationFrameType;
+use solhat::master::{self, CombineMethod, MasterMetadata};
+use std::fs;
+
+const WIDTH: usize = 1024;
+const HEIGHT: usize = 768;
+
+fn settings(exposure_ms: f32, gain: f32, sensor_temperature: f32) -> CaptureSettings {
+    CaptureSettings {
+        camera: Some("ZWO ASI174MM".to_string()),
+        exposure_ms: Some(exposure_ms),
+        gain: Some(gain),
+        sensor_temperature: Some(sensor_temperature),
+        pixel_depth: Some(16),
+        roi_offset: Some((0, 0)),
+        binning: Some((1, 1)),
+    }
+}
+
+// Only the metadata is read when indexing, so an empty file stands in for the master image
+fn add_master(
+    library_dir: &str,
+    name: &str,
+    frame_type: Option<CalibrationFrameType>,
+    width: usize,
+    settings: CaptureSettings,
+) -> String {
+    let image_path = format!("{}/{}.tif", library_dir, name);
+    let metadata = MasterMetadata {
+        method: CombineMethod::SigmaClip,
+        frame_type,
+        image_width: width,
+        image_height: HEIGHT,
+        frame_count: 50,
+        source_files: vec![],
+        settings,
+        bias_subtracted: false,
+        normalized: false,
+        rejected_fraction: 0.0,
+    };
+    fs::write(&image_path, []).unwrap();
+    fs::write(
+        master::metadata_path(&image_path),
+        serde_json::to_string_pretty(&metadata).unwrap(),
+    )
+    .unwrap();
+    image_path
+}
+
+fn temp_library(name: &str) -> String {
+    let dir = std::env::temp_dir().join(format!("solhat_callibrary_{}", name));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir.to_string_lossy().to_string()
+}
+
+fn criteria(settings: CaptureSettings) -> MatchCriteria {
+    MatchCriteria {
+        image_width: WIDTH,
+        image_height: HEIGHT,
+        settings,
+    }
+}
+
+#[test]
+fn test_closest_dark_is_chosen() {
+    let dir = temp_library("darks");
+    add_master(
+        &dir,
+        "dark_2ms_20c",
+        Some(CalibrationFrameType::Dark),
+        WIDTH,
+        settings(2.0, 160.0, 20.0),
+    );
+    let best = add_master(
+        &dir,
+        "dark_5ms_22c",
+        Some(CalibrationFrameType::Dark),
+        WIDTH,
+        settings(5.0, 160.0, 22.0),
+    );
+    add_master(
+        &dir,
+        "dark_5ms_35c",
+        Some(CalibrationFrameType::Dark),
+        WIDTH,
+        settings(5.0, 160.0, 35.0),
+    );
+    add_master(
+        &dir,
+        "dark_5ms_gain300",
+        Some(CalibrationFrameType::Dark),
+        WIDTH,
+        settings(5.0, 300.0, 20.0),
+    );
+
+    // Wrong ROI, otherwise a perfect match
+    add_master(
+        &dir,
+        "dark_roi",
+        Some(CalibrationFrameType::Dark),
+        640,
+        settings(5.0, 160.0, 20.0),
+    );
+
+    // No frame type, so not indexed
+    add_master(&dir, "unknown", None, WIDTH, settings(5.0, 160.0, 20.0));
+
+    let library = CalibrationLibrary::open(&dir).unwrap();
+    assert_eq!(library.entries.len(), 5);
+
+    let found = library
+        .find(
+            CalibrationFrameType::Dark,
+            &criteria(settings(5.0, 160.0, 20.0)),
+        )
+        .unwrap();
+    assert_eq!(found.image_path, best);
+
+    assert!(library
+        .find(
+            CalibrationFrameType::Flat,
+            &criteria(settings(5.0, 160.0, 20.0))
+        )
+        .is_none());
+
+    let mut other_camera = settings(5.0, 160.0, 20.0);
+    other_camera.camera = Some("QHY5III462C".to_string());
+    assert!(library
+        .find(CalibrationFrameType::Dark, &criteria(other_camera))
+        .is_none());
+
+    // Same frame size, but a different part of the sensor or binned
+    let mut moved_roi = settings(5.0, 160.0, 20.0);
+    moved_roi.roi_offset = Some((256, 128));
+    assert!(library
+        .find(CalibrationFrameType::Dark, &criteria(moved_roi))
+        .is_none());
+
+    let mut binned = settings(5.0, 160.0, 20.0);
+    binned.binning = Some((2, 2));
+    assert!(library
+        .find(CalibrationFrameType::Dark, &criteria(binned))
+        .is_none());
+
+    // Unrecorded on the capture side, so not held against the master
+    let mut unrecorded = settings(5.0, 160.0, 20.0);
+    unrecorded.roi_offset = None;
+    unrecorded.binning = None;
+    assert_eq!(
+        library
+            .find(CalibrationFrameType::Dark, &criteria(unrecorded))
+            .unwrap()
+            .image_path,
+        best
+    );
+}
+
+#[test]
+fn test_fill_missing_keeps_explicit_files() {
+    let dir = temp_library("fill");
+    let flat = add_master(
+        &dir,
+        "flat",
+        Some(CalibrationFrameType::Flat),
+        WIDTH,
+        settings(0.8, 160.0, 20.0),
+    );
+    add_master(
+        &dir,
+        "dark",
+        Some(CalibrationFrameType::Dark),
+        WIDTH,
+        settings(5.0, 160.0, 20.0),
+    );
+    add_master(
+        &dir,
+        "darkflat_5ms",
+        Some(CalibrationFrameType::DarkFlat),
+        WIDTH,
+        settings(5.0, 160.0, 20.0),
+    );
+    let dark_flat = add_master(
+        &dir,
+        "darkflat_08ms",
+        Some(CalibrationFrameType::DarkFlat),
+        WIDTH,
+        settings(0.8, 160.0, 20.0),
+    );
+
+    let library = CalibrationLibrary::open(&dir).unwrap();
+    let mut files = CalibrationFiles {
+        dark: Some("explicit_dark.tif".to_string()),
+        ..Default::default()
+    };
+    library.fill_missing(&mut files, &criteria(settings(5.0, 160.0, 20.0)));
+
+    assert_eq!(files.flat, Some(flat.clone()));
+    assert_eq!(files.dark, Some("explicit_dark.tif".to_string()));
+
+    // The dark flat follows the flat's exposure, not the lights'
+    assert_eq!(files.dark_flat, Some(dark_flat.clone()));
+    assert_eq!(files.bias, None);
+    assert_eq!(files.matched_from_library, vec![flat, dark_flat]);
+
+    assert!(CalibrationLibrary::open(&format!("{}/missing", dir)).is_err());
+}
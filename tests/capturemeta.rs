@@ -0,0 +1,103 @@
+mod common;
+
+use common::*;
+use solhat::capturemeta::{CaptureMetadata, CaptureSoftware};
+use solhat::ser;
+use std::fs;
+
+const SHARPCAP_SETTINGS: &str = "[ZWO ASI174MM]
+Debayer Preview=Off
+Output Format=SER file (*.ser)
+Binning=1
+Capture Area=1936x1216
+Colour Space=MONO16
+Gain=300
+Exposure=2.5
+Temperature=31.4
+StartCapture=2023-06-16T18:25:03.7360000Z
+EndCapture=2023-06-16T18:25:13.7360000Z
+FrameCount=11
+";
+
+#[test]
+fn test_firecapture_settings() {
+    let metadata = CaptureMetadata::load("testdata/Sun_150729.txt").unwrap();
+    assert_eq!(metadata.software, CaptureSoftware::FireCapture);
+    assert_eq!(metadata.camera.as_deref(), Some("ZWO ASI174MM"));
+    assert_eq!(metadata.filter.as_deref(), Some("L"));
+    assert_eq!(metadata.gain, Some(160.0));
+    assert_eq!(metadata.exposure_ms, Some(1.5));
+    assert_eq!(metadata.sensor_temperature, Some(46.5));
+    assert_eq!(metadata.utc_offset_hours, Some(-7.0));
+    assert_eq!(metadata.frame_count, Some(114));
+    assert_eq!(metadata.settings.get("Binning").unwrap(), "1x1");
+    assert_eq!(metadata.binning, Some((1, 1)));
+    assert_eq!(metadata.roi_offset, Some((0, 0)));
+
+    // Local times are converted to UT (LT=UT -7h), crossing into the next day
+    assert_eq!(metadata.start_utc, Some(637648348476390000)); // 2021-08-17 22:07:27.639
+    assert_eq!(metadata.frame_timestamps.len(), 114);
+    assert_eq!(metadata.frame_timestamp(0), Some(637648348476340000)); // 22:07:27.634
+    assert_eq!(metadata.frame_timestamp(113), Some(637648348506570000)); // 22:07:30.657
+    assert_eq!(metadata.frame_timestamp(114), None);
+}
+
+#[test]
+fn test_sharpcap_settings() {
+    let metadata = CaptureMetadata::parse(SHARPCAP_SETTINGS).unwrap();
+    assert_eq!(metadata.software, CaptureSoftware::SharpCap);
+    assert_eq!(metadata.camera.as_deref(), Some("ZWO ASI174MM"));
+    assert_eq!(metadata.gain, Some(300.0));
+    assert_eq!(metadata.exposure_ms, Some(2.5));
+    assert_eq!(metadata.sensor_temperature, Some(31.4));
+    assert_eq!(metadata.binning, Some((1, 1)));
+
+    // No per-frame times, so frames are spread evenly over the capture
+    assert_eq!(metadata.frame_timestamp(0), Some(638225367037360000));
+    assert_eq!(metadata.frame_timestamp(5), Some(638225367087360000));
+    assert_eq!(metadata.frame_timestamp(10), Some(638225367137360000));
+    assert_eq!(metadata.frame_timestamp(11), None);
+
+    assert!(CaptureMetadata::parse("not a settings file").is_err());
+}
+
+#[test]
+fn test_ser_timestamp_fallback() {
+    let ser_path = temp_ser_path("sidecar_fallback");
+    let sidecar_path = ser_path.replace(".ser", ".txt");
+    write_synthetic_ser(&ser_path, 16, 16, 8, ser::ColorFormatId::Mono, 3, false);
+    fs::copy("testdata/Sun_150729.txt", &sidecar_path).unwrap();
+
+    let ser_file = ser::SerFile::load_ser(&ser_path).unwrap();
+    assert!(!ser_file.has_timestamps());
+    assert!(ser_file.capture_metadata.is_some());
+    assert_eq!(
+        ser_file.get_frame(0).unwrap().timestamp.timestamp,
+        637648348476340000
+    );
+
+    // A trailer, when present, takes precedence over the sidecar
+    write_synthetic_ser(&ser_path, 16, 16, 8, ser::ColorFormatId::Mono, 3, true);
+    let ser_file = ser::SerFile::load_ser(&ser_path).unwrap();
+    assert_eq!(
+        ser_file.get_frame(2).unwrap().timestamp.timestamp,
+        synthetic_timestamp(2)
+    );
+
+    fs::remove_file(&ser_path).unwrap();
+    fs::remove_file(&sidecar_path).unwrap();
+
+    // SharpCap names its file differently
+    let ser_path = temp_ser_path("sidecar_sharpcap");
+    let sidecar_path = ser_path.replace(".ser", ".CameraSettings.txt");
+    write_synthetic_ser(&ser_path, 16, 16, 8, ser::ColorFormatId::Mono, 11, false);
+    fs::write(&sidecar_path, SHARPCAP_SETTINGS).unwrap();
+    let ser_file = ser::SerFile::load_ser(&ser_path).unwrap();
+    assert_eq!(
+        ser_file.get_frame_timestamp(10).unwrap(),
+        638225367137360000
+    );
+
+    fs::remove_file(&ser_path).unwrap();
+    fs::remove_file(&sidecar_path).unwrap();
+}
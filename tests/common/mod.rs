@@ -0,0 +1,155 @@
+// Helpers shared between integration tests. Not every test binary uses every helper.
+#![allow(dead_code)]
+
+use sciimg::{enums::ImageMode, image};
+use solhat::ser;
+
+pub fn temp_ser_path(name: &str) -> String {
+    std::env::temp_dir()
+        .join(format!("solhat_{}.ser", name))
+        .to_string_lossy()
+        .to_string()
+}
+
+// Deterministic, non-trivial pixel data so byte swaps and offsets show up in comparisons
+pub fn synthetic_frame_bytes(frame_size_bytes: usize, frame_num: usize) -> Vec<u8> {
+    (0..frame_size_bytes)
+        .map(|i| ((i * 7 + frame_num * 13) % 251) as u8)
+        .collect()
+}
+
+pub fn synthetic_timestamp(frame_num: usize) -> u64 {
+    638225427036930000 + frame_num as u64 * 100000
+}
+
+pub fn write_synthetic_ser(
+    output_file: &str,
+    width: usize,
+    height: usize,
+    pixel_depth: usize,
+    color_id: ser::ColorFormatId,
+    num_frames: usize,
+    with_timestamps: bool,
+) {
+    let mut writer = ser::SerWriter::new(output_file, width, height, pixel_depth, color_id);
+    writer.observer = "Observer".to_string();
+    writer.instrument = "Instrument".to_string();
+    writer.telescope = "Telescope".to_string();
+    writer.date_time = 638225427037360000;
+    writer.date_time_utc = 638225427036930000;
+
+    for i in 0..num_frames {
+        let frame_bytes = synthetic_frame_bytes(writer.image_frame_size_bytes(), i);
+        let timestamp = if with_timestamps {
+            Some(synthetic_timestamp(i))
+        } else {
+            None
+        };
+        writer
+            .add_frame_bytes(&frame_bytes, timestamp)
+            .expect("Failed to write frame");
+    }
+    writer.close().expect("Failed to close SER writer");
+}
+
+// A 16 bit SER of `value(frame, x, y)` in the temp directory, returning its path. The recorder
+// strings carry camera settings so capture metadata can be checked as well.
+pub fn write_ser_from_fn(
+    name: &str,
+    width: usize,
+    height: usize,
+    color_id: ser::ColorFormatId,
+    num_frames: usize,
+    value: impl Fn(usize, usize, usize) -> u16,
+) -> String {
+    let ser_path = temp_ser_path(name);
+    let mut writer = ser::SerWriter::new(&ser_path, width, height, 16, color_id);
+    writer.instrument = "ASI=ZWO ASI174MMtemp=20.5".to_string();
+    writer.telescope = "fps=55.25gain=160exp=2.50".to_string();
+    for i in 0..num_frames {
+        let frame_bytes: Vec<u8> = (0..width * height)
+            .flat_map(|p| value(i, p % width, p / width).to_le_bytes())
+            .collect();
+        writer
+            .add_frame_bytes(&frame_bytes, Some(synthetic_timestamp(i)))
+            .expect("Failed to write frame");
+    }
+    writer.close().expect("Failed to close SER writer");
+    ser_path
+}
+
+// A 16 bit mono frame containing a bright disk on a dark background
+pub fn disk_frame_bytes(
+    width: usize,
+    height: usize,
+    center_x: f32,
+    center_y: f32,
+    radius: f32,
+) -> Vec<u8> {
+    let mut frame_bytes: Vec<u8> = Vec::with_capacity(width * height * 2);
+    for y in 0..height {
+        for x in 0..width {
+            let dx = x as f32 - center_x;
+            let dy = y as f32 - center_y;
+            let v: u16 = if (dx * dx + dy * dy).sqrt() <= radius {
+                30000
+            } else {
+                10
+            };
+            frame_bytes.extend_from_slice(&v.to_le_bytes());
+        }
+    }
+    frame_bytes
+}
+
+// Brightness of a limb darkened disk at (x, y), zero off the disk. The limb is softened over
+// about `edge_softness` pixels, or sharp if that is zero.
+pub fn limb_darkened_disk(
+    x: usize,
+    y: usize,
+    center_x: f32,
+    center_y: f32,
+    radius: f32,
+    edge_softness: f32,
+) -> f32 {
+    let d = (x as f32 - center_x).hypot(y as f32 - center_y);
+    let u = (d / radius).min(1.0);
+    let edge = if edge_softness > 0.0 {
+        1.0 / (1.0 + ((d - radius) / edge_softness).exp())
+    } else if d < radius {
+        1.0
+    } else {
+        0.0
+    };
+    20000.0 * (1.0 - 0.6 * u * u) * edge
+}
+
+// A 16 bit mono image of `pixel(x, y)`
+pub fn mono_image(
+    width: usize,
+    height: usize,
+    pixel: impl Fn(usize, usize) -> f32,
+) -> image::Image {
+    let mut img = image::Image::new_with_bands(width, height, 1, ImageMode::U16BIT).unwrap();
+    for y in 0..height {
+        for x in 0..width {
+            img.put(x, y, pixel(x, y), 0);
+        }
+    }
+    img
+}
+
+// Center of the pixels above the threshold in a 16 bit mono frame
+pub fn bright_center_of_frame(frame_bytes: &[u8], width: usize, threshold: u16) -> (f32, f32) {
+    let mut sum_x = 0.0;
+    let mut sum_y = 0.0;
+    let mut count = 0.0;
+    for (i, px) in frame_bytes.chunks(2).enumerate() {
+        if u16::from_le_bytes([px[0], px[1]]) > threshold {
+            sum_x += (i % width) as f32;
+            sum_y += (i / width) as f32;
+            count += 1.0;
+        }
+    }
+    (sum_x / count, sum_y / count)
+}
@@ -0,0 +1,29 @@
+// The original per-pixel frame decoding, kept as a reference for both speed and correctness.
+// Shared by tests/serbench.rs and benches/serdecode.rs.
+
+use sciimg::binfilereader::*;
+use sciimg::{enums::ImageMode, imagebuffer::ImageBuffer};
+use solhat::ser;
+
+pub fn decode_per_pixel(
+    ser_file: &ser::SerFile,
+    file_reader: &BinFileReader,
+    frame_num: usize,
+) -> ser::SerFrame {
+    let start = ser_file.image_frame_start_index(frame_num);
+    let mut values = vec![0.0; ser_file.image_width * ser_file.image_height];
+    for y in 0..ser_file.image_height {
+        for x in 0..ser_file.image_width {
+            let i = x + y * ser_file.image_width;
+            values[i] = file_reader.read_u16(start + i * 2).unwrap() as f32;
+        }
+    }
+    let buffer = ImageBuffer::from_vec_as_mode(
+        &values,
+        ser_file.image_width,
+        ser_file.image_height,
+        ImageMode::U16BIT,
+    )
+    .unwrap();
+    ser::SerFrame::new(&buffer, 0)
+}
@@ -0,0 +1,142 @@
+mod common;
+
+use common::*;
+use sciimg::{enums::ImageMode, image};
+use solhat::defectmap::{DefectMap, DetectOptions};
+use solhat::framesource::{self, FrameSource};
+use solhat::ser;
+
+const WIDTH: usize = 24;
+const HEIGHT: usize = 20;
+
+// Small deterministic pixel-to-pixel variation, like read noise
+fn noise(x: usize, y: usize) -> u16 {
+    ((x * 7 + y * 13) % 5) as u16
+}
+
+#[test]
+fn test_mono_hot_and_cold_pixels() {
+    let ser_path = write_ser_from_fn(
+        "defects_mono",
+        WIDTH,
+        HEIGHT,
+        ser::ColorFormatId::Mono,
+        4,
+        |_, x, y| match (x, y) {
+            (5, 5) => 4000,
+            (10, 3) => 10,
+            _ => 1000 + noise(x, y),
+        },
+    );
+
+    let defect_map = DefectMap::from_input(&ser_path, &DetectOptions::default()).unwrap();
+    assert_eq!(defect_map.cfa_period, 1);
+    assert_eq!(defect_map.hot_pixels, vec![(5, 5)]);
+    assert_eq!(defect_map.cold_pixels, vec![(10, 3)]);
+
+    let frame_source = framesource::open(&ser_path).unwrap();
+    let mut frame = frame_source.read_frame(0).unwrap().frame.buffer;
+    defect_map
+        .correct(&mut frame, ser::ColorFormatId::Mono)
+        .unwrap();
+    for (x, y) in [(5, 5), (10, 3)] {
+        let v = frame.get_band(0).get(x, y);
+        assert!((1000.0..1005.0).contains(&v), "{} at ({}, {})", v, x, y);
+    }
+    assert_eq!(frame.get_band(0).get(6, 5), 1000.0 + noise(6, 5) as f32);
+
+    let mut wrong_size = image::Image::new_with_bands(8, 8, 1, ImageMode::U16BIT).unwrap();
+    assert!(defect_map
+        .correct(&mut wrong_size, ser::ColorFormatId::Mono)
+        .is_err());
+}
+
+#[test]
+fn test_bayer_defects_use_same_colored_neighbors() {
+    // RGGB with red far brighter than green and blue, which a plain neighborhood median would
+    // flag everywhere
+    let bayer_value = |x: usize, y: usize| match (x % 2, y % 2) {
+        (0, 0) => 3000,
+        (1, 1) => 200,
+        _ => 800,
+    };
+    let ser_path = write_ser_from_fn(
+        "defects_bayer",
+        WIDTH,
+        HEIGHT,
+        ser::ColorFormatId::BayerRggb,
+        4,
+        |_, x, y| {
+            if (x, y) == (8, 6) {
+                20000
+            } else {
+                bayer_value(x, y) + noise(x, y)
+            }
+        },
+    );
+
+    let defect_map = DefectMap::from_input(&ser_path, &DetectOptions::default()).unwrap();
+    assert_eq!(defect_map.cfa_period, 2);
+    assert_eq!(defect_map.hot_pixels, vec![(8, 6)]);
+    assert!(defect_map.cold_pixels.is_empty());
+
+    // Corrected from the other red pixels on the mosaic
+    let frame_source = framesource::open(&ser_path).unwrap();
+    let mut mosaic = frame_source.read_frame_mosaic(0).unwrap().frame;
+    assert_eq!(mosaic.buffer.num_bands(), 1);
+    defect_map
+        .correct(&mut mosaic.buffer, frame_source.color_format())
+        .unwrap();
+    let v = mosaic.buffer.get_band(0).get(8, 6);
+    assert!((3000.0..3005.0).contains(&v), "{}", v);
+
+    // A map found in demosaiced frames can't say which color a defect was
+    let demosaiced_map = DefectMap {
+        cfa_period: 1,
+        ..defect_map.clone()
+    };
+    let mut mosaic = frame_source.read_frame_mosaic(0).unwrap().frame;
+    assert!(demosaiced_map
+        .correct(&mut mosaic.buffer, frame_source.color_format())
+        .is_err());
+
+    let options = DetectOptions {
+        window_size: 3,
+        ..Default::default()
+    };
+    assert!(DefectMap::from_input(&ser_path, &options).is_err());
+}
+
+#[test]
+fn test_save_open_and_merge() {
+    let mut dark_map = DefectMap {
+        image_width: WIDTH,
+        image_height: HEIGHT,
+        cfa_period: 1,
+        window_size: 5,
+        hot_pixels: vec![(3, 4)],
+        cold_pixels: vec![],
+    };
+    let flat_map = DefectMap {
+        cold_pixels: vec![(9, 9)],
+        hot_pixels: vec![(3, 4)],
+        ..dark_map.clone()
+    };
+    dark_map.merge(&flat_map).unwrap();
+    assert_eq!(dark_map.hot_pixels, vec![(3, 4)]);
+    assert_eq!(dark_map.cold_pixels, vec![(9, 9)]);
+    assert_eq!(dark_map.len(), 2);
+
+    let map_path = std::env::temp_dir()
+        .join("solhat_defect_map.json")
+        .to_string_lossy()
+        .to_string();
+    dark_map.save(&map_path).unwrap();
+    assert_eq!(DefectMap::open(&map_path).unwrap(), dark_map);
+
+    let other_sensor = DefectMap {
+        cfa_period: 2,
+        ..dark_map.clone()
+    };
+    assert!(dark_map.merge(&other_sensor).is_err());
+}
@@ -0,0 +1,226 @@
+mod common;
+
+use common::*;
+use sciimg::enums::ImageMode;
+use solhat::fits;
+use solhat::framesource::{FrameSource, MultiFrameSource};
+use solhat::ser;
+use std::fs;
+
+fn write_synthetic_fits(path: &str, cards: &[(&str, &str)], data: &[u8]) {
+    let mut header = String::new();
+    for (k, v) in cards {
+        header.push_str(&format!("{:<8}= {:<70}", k, v));
+    }
+    header.push_str(&format!("{:<80}", "END"));
+    while !header.len().is_multiple_of(2880) {
+        header.push(' ');
+    }
+
+    let mut bytes = header.into_bytes();
+    bytes.extend(data);
+    while !bytes.len().is_multiple_of(2880) {
+        bytes.push(0);
+    }
+    fs::write(path, bytes).unwrap();
+}
+
+fn temp_fits_path(name: &str) -> String {
+    temp_ser_path(name).replace(".ser", ".fits")
+}
+
+#[test]
+fn test_fits_cube_unsigned_16bit() {
+    let fits_path = temp_fits_path("fits_cube");
+
+    // Unsigned 16 bit data is stored signed with BZERO = 32768, bottom row first
+    let (width, height, frames) = (5, 3, 4);
+    let mut data = vec![];
+    for f in 0..frames {
+        for y in (0..height).rev() {
+            for x in 0..width {
+                let value = 1000 * f + 100 * y + x - 32768;
+                data.extend((value as i16).to_be_bytes());
+            }
+        }
+    }
+    write_synthetic_fits(
+        &fits_path,
+        &[
+            ("SIMPLE", "T"),
+            ("BITPIX", "16"),
+            ("NAXIS", "3"),
+            ("NAXIS1", "5"),
+            ("NAXIS2", "3"),
+            ("NAXIS3", "4"),
+            ("BZERO", "32768 / offset for unsigned data"),
+            ("BSCALE", "1.0D0"),
+            ("DATE-OBS", "'2023-06-16T18:25:03.736'"),
+            ("OBJECT", "'Sun''s limb'"),
+        ],
+        &data,
+    );
+
+    let fits_file = fits::FitsFile::open(&fits_path).unwrap();
+    assert_eq!(fits_file.image_width, 5);
+    assert_eq!(fits_file.image_height, 3);
+    assert_eq!(fits_file.frame_count, 4);
+    assert_eq!(fits_file.color_id, ser::ColorFormatId::Mono);
+    assert_eq!(fits_file.num_bands(), 1);
+    assert_eq!(fits_file.get_header("OBJECT").unwrap(), "Sun's limb");
+
+    let frame = fits_file.get_frame(2).unwrap();
+    assert_eq!(frame.buffer.get_band(0).get(0, 0), 2000.0);
+    assert_eq!(frame.buffer.get_band(0).get(4, 2), 2204.0);
+
+    // 2023-06-16T18:25:03.736 in 100ns ticks since 0001-01-01
+    assert_eq!(
+        fits_file.get_frame_timestamp(3).unwrap(),
+        638225367037360000
+    );
+
+    fs::remove_file(&fits_path).unwrap();
+}
+
+#[test]
+fn test_fits_bayer_offsets() {
+    let fits_path = temp_fits_path("fits_bayer");
+    write_synthetic_fits(
+        &fits_path,
+        &[
+            ("SIMPLE", "T"),
+            ("BITPIX", "8"),
+            ("NAXIS", "2"),
+            ("NAXIS1", "4"),
+            ("NAXIS2", "4"),
+            ("ROWORDER", "'TOP-DOWN'"),
+            ("BAYERPAT", "'RGGB'"),
+            ("XBAYROFF", "1"),
+        ],
+        &[50; 16],
+    );
+    let fits_file = fits::FitsFile::open(&fits_path).unwrap();
+    assert_eq!(fits_file.color_id, ser::ColorFormatId::BayerGrbg);
+    assert_eq!(fits_file.num_bands(), 3);
+    assert_eq!(fits_file.get_frame(0).unwrap().buffer.num_bands(), 3);
+    fs::remove_file(&fits_path).unwrap();
+
+    // Bottom-up with an even height, so the first row returned is an odd stored row
+    write_synthetic_fits(
+        &fits_path,
+        &[
+            ("SIMPLE", "T"),
+            ("BITPIX", "8"),
+            ("NAXIS", "2"),
+            ("NAXIS1", "4"),
+            ("NAXIS2", "4"),
+            ("BAYERPAT", "'RGGB'"),
+        ],
+        &[50; 16],
+    );
+    let fits_file = fits::FitsFile::open(&fits_path).unwrap();
+    assert_eq!(fits_file.color_id, ser::ColorFormatId::BayerGbrg);
+    fs::remove_file(&fits_path).unwrap();
+}
+
+#[test]
+fn test_fits_frame_sequence() {
+    let paths = (0..3)
+        .map(|i| temp_fits_path(&format!("fits_seq_{}", i)))
+        .collect::<Vec<String>>();
+    for (i, p) in paths.iter().enumerate() {
+        let data = (0..8 * 6)
+            .flat_map(|_| ((i * 10) as f32).to_be_bytes())
+            .collect::<Vec<u8>>();
+        write_synthetic_fits(
+            p,
+            &[
+                ("SIMPLE", "T"),
+                ("BITPIX", "-32"),
+                ("NAXIS", "2"),
+                ("NAXIS1", "8"),
+                ("NAXIS2", "6"),
+                ("DATE-OBS", &format!("'2023-06-16T18:25:0{}'", i)),
+            ],
+            &data,
+        );
+    }
+
+    let path_refs = paths.iter().map(|p| p.as_str()).collect::<Vec<&str>>();
+    let frame_source = MultiFrameSource::from_paths(&path_refs).unwrap();
+    assert_eq!(frame_source.frame_count(), 3);
+
+    for (i, f) in frame_source.frames().enumerate() {
+        let sourced_frame = f.unwrap();
+        assert_eq!(sourced_frame.source_file, paths[i]);
+        assert_eq!(sourced_frame.frame_id, 0);
+        assert_eq!(sourced_frame.frame.timestamp.second, i as i32);
+        assert_eq!(
+            sourced_frame.frame.buffer.get_band(0).get(7, 5),
+            (i * 10) as f32
+        );
+    }
+
+    paths.iter().for_each(|p| fs::remove_file(p).unwrap());
+}
+
+#[test]
+fn test_fits_float_scaled_to_mode() {
+    let fits_path = temp_fits_path("fits_float");
+    let values = [0.0_f32, 0.25, 0.5, 1.0];
+    let data = values
+        .iter()
+        .flat_map(|v| v.to_be_bytes())
+        .collect::<Vec<u8>>();
+    let cards = [
+        ("SIMPLE", "T"),
+        ("BITPIX", "-32"),
+        ("NAXIS", "2"),
+        ("NAXIS1", "4"),
+        ("NAXIS2", "1"),
+    ];
+
+    // Normalized to 0..1, stretched to the 16 bit range
+    write_synthetic_fits(&fits_path, &cards, &data);
+    let fits_file = fits::FitsFile::open(&fits_path).unwrap();
+    assert!(matches!(fits_file.image_mode(), ImageMode::U16BIT));
+    let band = fits_file.get_frame(0).unwrap().buffer.get_band(0).clone();
+    assert_eq!(band.get(0, 0), 0.0);
+    assert_eq!(band.get(2, 0), 32767.5);
+    assert_eq!(band.get(3, 0), 65535.0);
+
+    // DATAMAX gives the saturation point
+    let mut with_max = cards.to_vec();
+    with_max.push(("DATAMAX", "2.0"));
+    write_synthetic_fits(&fits_path, &with_max, &data);
+    let band = fits::FitsFile::open(&fits_path)
+        .unwrap()
+        .get_frame(0)
+        .unwrap()
+        .buffer
+        .get_band(0)
+        .clone();
+    assert_eq!(band.get(3, 0), 32767.5);
+
+    fs::remove_file(&fits_path).unwrap();
+}
+
+#[test]
+fn test_fits_truncated_cube() {
+    let fits_path = temp_fits_path("fits_truncated");
+    write_synthetic_fits(
+        &fits_path,
+        &[
+            ("SIMPLE", "T"),
+            ("BITPIX", "8"),
+            ("NAXIS", "3"),
+            ("NAXIS1", "2000"),
+            ("NAXIS2", "2"),
+            ("NAXIS3", "10"),
+        ],
+        &[0; 4000 * 2],
+    );
+    let fits_file = fits::FitsFile::open(&fits_path).unwrap();
+    assert_eq!(fits_file.frame_count, 2);
+    fs::remove_file(&fits_path).unwrap();
+}
@@ -0,0 +1,94 @@
+mod common;
+
+use common::*;
+use rayon::prelude::*;
+use solhat::framesource::{FrameSource, MultiFrameSource};
+use solhat::ser;
+use std::fs;
+
+#[test]
+fn test_ser_frames() {
+    let input_file = temp_ser_path("frames_single");
+    write_synthetic_ser(&input_file, 32, 24, 16, ser::ColorFormatId::Mono, 5, true);
+
+    let ser_file = ser::SerFile::load_ser(&input_file).unwrap();
+    assert_eq!(ser_file.num_bands(), 1);
+
+    let mut count = 0;
+    for (i, f) in ser_file.frames().enumerate() {
+        let sourced_frame = f.unwrap();
+        assert_eq!(sourced_frame.source_file, input_file);
+        assert_eq!(sourced_frame.frame_id, i);
+        assert_eq!(
+            sourced_frame.frame.buffer.get_band(0).get(3, 2),
+            ser_file.get_frame(i).unwrap().buffer.get_band(0).get(3, 2)
+        );
+        assert_eq!(
+            sourced_frame.frame.timestamp,
+            ser_file.get_frame(i).unwrap().timestamp
+        );
+        count += 1;
+    }
+    assert_eq!(count, 5);
+
+    let mut ids = ser_file
+        .par_frames()
+        .take(3)
+        .map(|f| f.unwrap().frame_id)
+        .collect::<Vec<usize>>();
+    ids.sort();
+    assert_eq!(ids, vec![0, 1, 2]);
+
+    fs::remove_file(&input_file).unwrap();
+}
+
+#[test]
+fn test_multiple_sources() {
+    let first_file = temp_ser_path("frames_first");
+    let second_file = temp_ser_path("frames_second");
+    write_synthetic_ser(&first_file, 32, 24, 8, ser::ColorFormatId::Mono, 3, true);
+    write_synthetic_ser(&second_file, 32, 24, 8, ser::ColorFormatId::Mono, 4, false);
+
+    let frame_source = MultiFrameSource::from_paths(&[&first_file, &second_file]).unwrap();
+    assert_eq!(frame_source.frame_count(), 7);
+    assert_eq!(frame_source.image_width(), 32);
+    assert_eq!(frame_source.image_height(), 24);
+
+    let origins = frame_source
+        .frames()
+        .map(|f| {
+            let sourced_frame = f.unwrap();
+            (sourced_frame.source_file, sourced_frame.frame_id)
+        })
+        .collect::<Vec<(String, usize)>>();
+    assert_eq!(
+        origins,
+        vec![
+            (first_file.clone(), 0),
+            (first_file.clone(), 1),
+            (first_file.clone(), 2),
+            (second_file.clone(), 0),
+            (second_file.clone(), 1),
+            (second_file.clone(), 2),
+            (second_file.clone(), 3),
+        ]
+    );
+    assert!(frame_source.read_frame(7).is_err());
+
+    fs::remove_file(&first_file).unwrap();
+    fs::remove_file(&second_file).unwrap();
+}
+
+#[test]
+fn test_multiple_sources_mismatched_geometry() {
+    let first_file = temp_ser_path("frames_mismatch_a");
+    let second_file = temp_ser_path("frames_mismatch_b");
+    write_synthetic_ser(&first_file, 32, 24, 8, ser::ColorFormatId::Mono, 2, true);
+    write_synthetic_ser(&second_file, 24, 32, 8, ser::ColorFormatId::Mono, 2, true);
+
+    assert!(MultiFrameSource::from_paths(&[&first_file, &second_file]).is_err());
+    assert!(MultiFrameSource::from_paths(&[]).is_err());
+
+    fs::remove_file(&first_file).unwrap();
+    fs::remove_file(&second_file).unwrap();
+}
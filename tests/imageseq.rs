@@ -0,0 +1,113 @@
+mod common;
+
+use common::*;
+use sciimg::{enums::ImageMode, image::Image};
+use solhat::framesource::{self, FrameSource};
+use solhat::imageseq;
+use std::fs;
+
+fn write_frame_directory(name: &str, num_frames: usize) -> String {
+    let dir = temp_ser_path(name).replace(".ser", "");
+    if fs::metadata(&dir).is_ok() {
+        fs::remove_dir_all(&dir).unwrap();
+    }
+    fs::create_dir(&dir).unwrap();
+
+    for i in 0..num_frames {
+        let mut frame = Image::new_with_bands(12, 8, 1, ImageMode::U16BIT).unwrap();
+        frame.put(3, 4, (i * 1000 + 500) as f32, 0);
+        frame.save(&format!("{}/frame_{:05}.png", dir, i)).unwrap();
+    }
+    fs::write(format!("{}/notes.md", dir), "not a frame").unwrap();
+    dir
+}
+
+#[test]
+fn test_directory_with_sidecar() {
+    let dir = write_frame_directory("imageseq_sidecar", 4);
+
+    // Frame 2 was pruned by hand, frame 3 was left out of the sidecar
+    fs::remove_file(format!("{}/frame_00002.png", dir)).unwrap();
+    imageseq::write_timestamps_sidecar(
+        &dir,
+        &[
+            ("frame_00001.png".to_string(), synthetic_timestamp(1)),
+            ("frame_00000.png".to_string(), synthetic_timestamp(0)),
+            ("frame_00002.png".to_string(), synthetic_timestamp(2)),
+        ],
+        false,
+    )
+    .unwrap();
+
+    let frame_source = framesource::open(&dir).unwrap();
+    assert_eq!(frame_source.frame_count(), 3);
+    assert_eq!(frame_source.image_width(), 12);
+    assert_eq!(frame_source.image_height(), 8);
+    assert_eq!(frame_source.num_bands(), 1);
+
+    let frames = frame_source
+        .frames()
+        .map(|f| f.unwrap())
+        .collect::<Vec<framesource::SourcedFrame>>();
+    assert_eq!(frames[0].source_file, dir);
+    assert_eq!(frames[1].frame.buffer.get_band(0).get(3, 4), 1500.0);
+    assert_eq!(frames[2].frame.buffer.get_band(0).get(3, 4), 3500.0);
+    assert_eq!(frames[1].frame.timestamp.timestamp, synthetic_timestamp(1));
+
+    // Unlisted frames fall back to their modification time, which is some time after the
+    // synthetic capture times
+    assert!(frames[2].frame.timestamp.timestamp > synthetic_timestamp(1));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_wildcard_pattern() {
+    let dir = write_frame_directory("imageseq_pattern", 12);
+    fs::write(
+        format!("{}/{}", dir, imageseq::TIMESTAMPS_SIDECAR_FILE),
+        "# pre-processed frames\nframe_00010.png 2023-06-16T18:25:03.736\n",
+    )
+    .unwrap();
+
+    let pattern = format!("{}/frame_0001?.png", dir);
+    assert!(framesource::exists(&pattern));
+
+    let frame_source = framesource::open(&pattern).unwrap();
+    assert_eq!(frame_source.frame_count(), 2);
+    let first = frame_source.read_frame(0).unwrap();
+    assert_eq!(first.frame.buffer.get_band(0).get(3, 4), 10500.0);
+    assert_eq!(first.frame.timestamp.timestamp, 638225367037360000);
+
+    assert!(!framesource::exists(&format!("{}_missing/*.png", dir)));
+    assert!(framesource::open(&format!("{}/*.tif", dir)).is_err());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_derotated_marker() {
+    let dir = write_frame_directory("imageseq_derotated", 2);
+    let timestamps = [
+        ("frame_00000.png".to_string(), synthetic_timestamp(0)),
+        ("frame_00001.png".to_string(), synthetic_timestamp(1)),
+    ];
+
+    imageseq::write_timestamps_sidecar(&dir, &timestamps, false).unwrap();
+    assert!(!framesource::open(&dir).unwrap().derotated());
+
+    imageseq::write_timestamps_sidecar(&dir, &timestamps, true).unwrap();
+    let frame_source = framesource::open(&dir).unwrap();
+    assert!(frame_source.derotated());
+    assert_eq!(
+        frame_source
+            .read_frame(1)
+            .unwrap()
+            .frame
+            .timestamp
+            .timestamp,
+        synthetic_timestamp(1)
+    );
+
+    fs::remove_dir_all(&dir).unwrap();
+}
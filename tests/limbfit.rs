@@ -0,0 +1,83 @@
+mod common;
+
+use common::*;
+use sciimg::{enums::ImageMode, image};
+use solhat::alignment::{Aligner, AlignmentOptions};
+use solhat::enums::AlignmentMethod;
+use solhat::limbfit::{LimbFit, LimbFitOptions};
+
+const WIDTH: usize = 120;
+const HEIGHT: usize = 100;
+
+// A limb darkened disk with a softened edge and a couple of prominences off the limb
+fn disk_image(cx: f32, cy: f32, radius: f32) -> image::Image {
+    mono_image(WIDTH, HEIGHT, |x, y| {
+        let prominence = [(0.3_f32, 7.0_f32), (2.2, 5.0)]
+            .iter()
+            .map(|(angle, height)| {
+                let px = cx + (radius + height / 2.0) * angle.cos();
+                let py = cy + (radius + height / 2.0) * angle.sin();
+                let p = ((x as f32 - px).powi(2) + (y as f32 - py).powi(2)).sqrt();
+                if p < height / 2.0 {
+                    3000.0
+                } else {
+                    0.0
+                }
+            })
+            .sum::<f32>();
+
+        300.0 + limb_darkened_disk(x, y, cx, cy, radius, 0.6) + prominence
+    })
+}
+
+#[test]
+fn test_limb_fit_finds_center_and_radius() {
+    let img = disk_image(61.3, 48.6, 35.0);
+    let fit = LimbFit::find(&img, &LimbFitOptions::default()).unwrap();
+    assert!((fit.center_x - 61.3).abs() < 0.3, "{:?}", fit);
+    assert!((fit.center_y - 48.6).abs() < 0.3, "{:?}", fit);
+    assert!((fit.radius - 35.0).abs() < 0.5, "{:?}", fit);
+    assert!(fit.inlier_fraction() > 0.8, "{:?}", fit);
+}
+
+#[test]
+fn test_limb_fit_on_clipped_disk() {
+    // A third of the disk is off the left edge of the frame
+    let img = disk_image(20.0, 52.0, 40.0);
+    let options = LimbFitOptions {
+        obj_detect_threshold: Some(1000.0),
+        ..Default::default()
+    };
+    let fit = LimbFit::find(&img, &options).unwrap();
+    assert!((fit.center_x - 20.0).abs() < 0.5, "{:?}", fit);
+    assert!((fit.center_y - 52.0).abs() < 0.5, "{:?}", fit);
+    assert!((fit.radius - 40.0).abs() < 0.5, "{:?}", fit);
+
+    // Whereas the center of mass is pulled well onto the frame
+    let com = img.calc_center_of_mass_offset(1000.0, 0);
+    assert!((WIDTH as f32 / 2.0 - com.h - 20.0).abs() > 5.0);
+}
+
+#[test]
+fn test_limb_fit_without_disk() {
+    let img = image::Image::new_with_bands(WIDTH, HEIGHT, 1, ImageMode::U16BIT).unwrap();
+    assert!(LimbFit::find(&img, &LimbFitOptions::default()).is_none());
+}
+
+#[test]
+fn test_limb_fit_alignment_centers_the_limb() {
+    let options = AlignmentOptions {
+        method: AlignmentMethod::LimbFit,
+        ..Default::default()
+    };
+    let aligner = Aligner::new(&disk_image(60.0, 50.0, 35.0), &options, 1000.0);
+
+    let alignment = aligner.align(&disk_image(52.5, 55.0, 35.0));
+    assert_eq!(alignment.method, AlignmentMethod::LimbFit);
+    assert!((alignment.offset.h - 7.5).abs() < 0.3, "{:?}", alignment);
+    assert!((alignment.offset.v + 5.0).abs() < 0.3, "{:?}", alignment);
+    assert!((alignment.radius.unwrap() - 35.0).abs() < 0.5);
+
+    let empty = image::Image::new_with_bands(WIDTH, HEIGHT, 1, ImageMode::U16BIT).unwrap();
+    assert_eq!(aligner.align(&empty).method, AlignmentMethod::CenterOfMass);
+}
@@ -0,0 +1,170 @@
+mod common;
+
+use common::*;
+use sciimg::{enums::ImageMode, image};
+use solhat::master::{self, CombineMethod, MasterOptions};
+use solhat::ser;
+use std::fs;
+
+const WIDTH: usize = 16;
+const HEIGHT: usize = 12;
+
+#[test]
+fn test_combine_values() {
+    let stack = [100.0, 101.0, 99.0, 100.0, 5000.0];
+
+    let (v, rejected) = master::combine_values(&mut stack.clone(), CombineMethod::Mean, 3.0, 3.0);
+    assert_eq!((v, rejected), (1080.0, 0));
+
+    let (v, _) = master::combine_values(&mut stack.clone(), CombineMethod::Median, 3.0, 3.0);
+    assert_eq!(v, 100.0);
+
+    let (v, rejected) =
+        master::combine_values(&mut stack.clone(), CombineMethod::SigmaClip, 3.0, 3.0);
+    assert_eq!((v, rejected), (100.0, 1));
+
+    let (v, clamped) =
+        master::combine_values(&mut stack.clone(), CombineMethod::Winsorized, 3.0, 3.0);
+    assert!((v - 100.0).abs() < 1.0);
+    assert_eq!(clamped, 1);
+}
+
+#[test]
+fn test_master_dark_rejects_cosmic_ray() {
+    // Frame 3 catches a cosmic ray at (5, 5)
+    let ser_path = write_ser_from_fn(
+        "master_dark",
+        WIDTH,
+        HEIGHT,
+        ser::ColorFormatId::Mono,
+        7,
+        |i, x, y| {
+            if i == 3 && x == 5 && y == 5 {
+                60000
+            } else {
+                1000 + ((i * 7 + x * 3 + y) % 5) as u16
+            }
+        },
+    );
+
+    for method in [
+        CombineMethod::Median,
+        CombineMethod::SigmaClip,
+        CombineMethod::Winsorized,
+    ] {
+        let options = MasterOptions {
+            method,
+            ..Default::default()
+        };
+        let master_frame = master::build_master(&[ser_path.as_str()], &options).unwrap();
+        let v = master_frame.image.get_band(0).get(5, 5);
+        assert!((1000.0..1005.0).contains(&v), "{:?} gave {}", method, v);
+        assert_eq!(master_frame.metadata.frame_count, 7);
+    }
+
+    let master_frame =
+        master::build_master(&[ser_path.as_str()], &MasterOptions::default()).unwrap();
+    assert_eq!(master_frame.metadata.settings.exposure_ms, Some(2.5));
+    assert_eq!(master_frame.metadata.settings.gain, Some(160.0));
+    assert_eq!(
+        master_frame.metadata.settings.sensor_temperature,
+        Some(20.5)
+    );
+
+    let output_path = std::env::temp_dir()
+        .join("solhat_master_dark.png")
+        .to_string_lossy()
+        .to_string();
+    master_frame.save(&output_path).unwrap();
+    let metadata: master::MasterMetadata =
+        serde_json::from_str(&fs::read_to_string(master::metadata_path(&output_path)).unwrap())
+            .unwrap();
+    assert_eq!(metadata.method, CombineMethod::SigmaClip);
+    assert_eq!(metadata.frame_count, 7);
+
+    fs::remove_file(&ser_path).ok();
+    fs::remove_file(&output_path).ok();
+    fs::remove_file(master::metadata_path(&output_path)).ok();
+}
+
+#[test]
+fn test_master_flat_bias_subtracted_and_normalized() {
+    // Vignetted flats over a bias level of 100
+    let ser_path = write_ser_from_fn(
+        "master_flat",
+        WIDTH,
+        HEIGHT,
+        ser::ColorFormatId::Mono,
+        5,
+        |_, x, _| 100 + 2000 + x as u16 * 100,
+    );
+
+    let mut bias = image::Image::new_with_bands(WIDTH, HEIGHT, 1, ImageMode::U16BIT).unwrap();
+    for y in 0..HEIGHT {
+        for x in 0..WIDTH {
+            bias.put(x, y, 100.0, 0);
+        }
+    }
+
+    let options = MasterOptions {
+        method: CombineMethod::Median,
+        bias: Some(bias),
+        normalize: true,
+        ..Default::default()
+    };
+    let master_frame = master::build_master(&[ser_path.as_str()], &options).unwrap();
+    assert!(master_frame.metadata.bias_subtracted);
+    assert!(master_frame.metadata.normalized);
+
+    let band = master_frame.image.get_band(0);
+    let mean: f32 = (0..HEIGHT)
+        .flat_map(|y| (0..WIDTH).map(move |x| (x, y)))
+        .map(|(x, y)| band.get(x, y))
+        .sum::<f32>()
+        / (WIDTH * HEIGHT) as f32;
+    assert!((mean - 65535.0 * 0.5).abs() < 1.0);
+
+    // With the bias removed, the vignetting ratio is that of the light alone
+    assert!((band.get(WIDTH - 1, 0) / band.get(0, 0) - 3500.0 / 2000.0).abs() < 1e-3);
+
+    fs::remove_file(&ser_path).ok();
+}
+
+#[test]
+fn test_master_combined_in_strips() {
+    let ser_path = write_ser_from_fn(
+        "master_strips",
+        WIDTH,
+        HEIGHT,
+        ser::ColorFormatId::Mono,
+        5,
+        |i, x, y| 1000 + (x * 10 + y * 100) as u16 + if i == 2 && y == 7 { 30000 } else { 0 },
+    );
+
+    let options = MasterOptions {
+        method: CombineMethod::Median,
+        ..Default::default()
+    };
+    let whole = master::build_master(&[ser_path.as_str()], &options).unwrap();
+
+    // No room for more than one row of every frame at a time
+    let options = MasterOptions {
+        max_memory_mb: 0,
+        ..options
+    };
+    let strips = master::build_master(&[ser_path.as_str()], &options).unwrap();
+
+    for y in 0..HEIGHT {
+        for x in 0..WIDTH {
+            let v = strips.image.get_band(0).get(x, y);
+            assert_eq!(v, whole.image.get_band(0).get(x, y));
+            assert_eq!(v, (1000 + x * 10 + y * 100) as f32);
+        }
+    }
+    assert_eq!(
+        strips.metadata.rejected_fraction,
+        whole.metadata.rejected_fraction
+    );
+
+    fs::remove_file(&ser_path).ok();
+}
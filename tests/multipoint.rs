@@ -0,0 +1,137 @@
+use sciimg::imagebuffer::{ImageBuffer, Offset};
+use sciimg::{enums::ImageMode, image};
+use solhat::drizzle::{self, BilinearDrizzle, Scale};
+use solhat::multipoint::{LocalShift, MultiPointAligner, MultiPointOptions, WarpField};
+
+const WIDTH: usize = 128;
+const HEIGHT: usize = 96;
+
+// Surface detail everywhere, seen through a warp that moves it by warp(x, y)
+fn textured(warp: impl Fn(f32, f32) -> (f32, f32)) -> ImageBuffer {
+    let mut buffer = ImageBuffer::new(WIDTH, HEIGHT).unwrap();
+    for y in 0..HEIGHT {
+        for x in 0..WIDTH {
+            let (dx, dy) = warp(x as f32, y as f32);
+            let (u, v) = (x as f32 - dx, y as f32 - dy);
+            let value = 10000.0
+                + 2000.0 * (u * 0.41).sin() * (v * 0.29).cos()
+                + 1500.0 * (u * 0.13 + v * 0.37).sin()
+                + 1000.0 * (u * 0.07 - v * 0.11).cos();
+            buffer.put(x, y, value);
+        }
+    }
+    buffer
+}
+
+// Seeing that pushes the left of the frame right, fading to nothing on the right
+fn seeing(x: f32, _y: f32) -> (f32, f32) {
+    (2.0 * (1.0 - (x / WIDTH as f32)).clamp(0.0, 1.0), 0.0)
+}
+
+#[test]
+fn test_local_shifts_follow_seeing() {
+    let reference = textured(|_, _| (0.0, 0.0));
+    let options = MultiPointOptions {
+        patch_size: 24,
+        ..Default::default()
+    };
+    let aligner = MultiPointAligner::new(&reference, &options);
+    assert!(aligner.num_patches() > 0);
+
+    let frame = textured(seeing);
+    let shifts = aligner.measure(&frame);
+    assert_eq!(shifts.len(), aligner.num_patches());
+    for s in shifts.iter() {
+        let (expected, _) = seeing(s.x, s.y);
+        assert!((s.dx - expected).abs() < 0.3, "{:?} vs {}", s, expected);
+        assert!(s.dy.abs() < 0.3, "{:?}", s);
+    }
+
+    // The same frame registers without shifts
+    for s in aligner.measure(&reference) {
+        assert!(s.dx.abs() < 0.05 && s.dy.abs() < 0.05, "{:?}", s);
+    }
+}
+
+#[test]
+fn test_warp_field_is_smooth_and_fades_out() {
+    let shifts: Vec<LocalShift> = (0..4)
+        .flat_map(|j| {
+            (0..4).map(move |i| LocalShift {
+                x: 16.0 + i as f32 * 16.0,
+                y: 16.0 + j as f32 * 16.0,
+                dx: 1.5,
+                dy: -0.5,
+            })
+        })
+        .collect();
+    let field = WarpField::from_shifts(256, 256, 16, &shifts);
+
+    let (dx, dy) = field.at(40.0, 40.0);
+    assert!(
+        (dx - 1.5).abs() < 0.1 && (dy + 0.5).abs() < 0.1,
+        "{} {}",
+        dx,
+        dy
+    );
+
+    // Far from any patch
+    let (dx, dy) = field.at(240.0, 240.0);
+    assert!(dx.abs() < 0.01 && dy.abs() < 0.01, "{} {}", dx, dy);
+
+    let empty = WarpField::from_shifts(64, 64, 16, &[]);
+    assert_eq!(empty.at(30.0, 30.0), (0.0, 0.0));
+}
+
+#[test]
+fn test_drizzle_follows_warp() {
+    let reference = textured(|_, _| (0.0, 0.0));
+    let shifted = textured(|_, _| (2.0, 1.0));
+    let frame = image::Image::new_from_buffers_rgb(&shifted, &shifted, &shifted, ImageMode::U16BIT)
+        .unwrap();
+
+    let shifts = vec![LocalShift {
+        x: 64.0,
+        y: 48.0,
+        dx: 2.0,
+        dy: 1.0,
+    }];
+    let field = WarpField::from_shifts(WIDTH, HEIGHT, 1000, &shifts);
+    let (dx, dy) = field.at(64.0, 48.0);
+    assert!((dx - 2.0).abs() < 0.5 && (dy - 1.0).abs() < 0.25);
+
+    // A uniform field, so the check below is exact
+    let uniform: Vec<LocalShift> = (0..=WIDTH / 8)
+        .flat_map(|i| {
+            (0..=HEIGHT / 8).map(move |j| LocalShift {
+                x: (i * 8) as f32,
+                y: (j * 8) as f32,
+                dx: 2.0,
+                dy: 1.0,
+            })
+        })
+        .collect();
+    let field = WarpField::from_shifts(WIDTH, HEIGHT, 8, &uniform);
+
+    let mut drizzle = BilinearDrizzle::new(WIDTH, HEIGHT, Scale::Scale1_0, 3);
+    drizzle
+        .add_with_warp(&frame, Offset { h: 0.0, v: 0.0 }, 0.0, Some(&field))
+        .unwrap();
+    let stacked = drizzle.get_finalized().unwrap();
+    for (x, y) in [(20, 20), (64, 48), (100, 70)] {
+        let v = stacked.get_band(0).get(x, y);
+        let expected = reference.get(x, y);
+        assert!(
+            (v - expected).abs() < 30.0,
+            "{} vs {} at ({}, {})",
+            v,
+            expected,
+            x,
+            y
+        );
+    }
+
+    // Resampling with the offset the drizzle would use lines the frame up the same way
+    let resampled = drizzle::resample(&shifted, Offset { h: -2.0, v: -1.0 }, 0.0);
+    assert!((resampled.get(50, 40) - reference.get(50, 40)).abs() < 1.0);
+}
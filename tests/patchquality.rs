@@ -0,0 +1,151 @@
+use sciimg::imagebuffer::{ImageBuffer, Offset};
+use sciimg::{enums::ImageMode, image};
+use solhat::drizzle::{BilinearDrizzle, Scale};
+use solhat::enums::QualityMethod;
+use solhat::patchquality;
+use solhat::patchquality::{PatchGrid, PatchQualityOptions, PatchSelection};
+
+const WIDTH: usize = 64;
+const HEIGHT: usize = 32;
+
+// Fine detail where sharp(x) holds, flat brightness elsewhere
+fn frame(sharp: impl Fn(usize) -> bool) -> ImageBuffer {
+    let mut buffer = ImageBuffer::new(WIDTH, HEIGHT).unwrap();
+    for y in 0..HEIGHT {
+        for x in 0..WIDTH {
+            let detail = if sharp(x) {
+                2000.0 * (x as f32 * 1.3).sin() * (y as f32 * 0.9).cos()
+            } else {
+                0.0
+            };
+            buffer.put(x, y, 10000.0 + detail);
+        }
+    }
+    buffer
+}
+
+#[test]
+fn test_patches_score_local_detail() {
+    let grid = PatchGrid::new(WIDTH, HEIGHT, 32);
+    assert_eq!((grid.cols, grid.rows), (2, 1));
+
+    // Scored with the default metric, as when no quality metric is asked for
+    let options = PatchQualityOptions::default();
+    assert_eq!(options.quality_method, patchquality::DEFAULT_QUALITY_METHOD);
+    assert_ne!(options.quality_method, QualityMethod::default());
+    let metric = options.metric();
+
+    let scores = grid.score(&frame(|x| x < 32), metric.as_ref());
+    assert!(scores[0] > 0.0);
+    assert!(scores[1] < scores[0] * 0.1);
+
+    // Brightness alone doesn't make a patch sharper
+    let mut brighter = frame(|x| x < 32);
+    brighter.scale_mut(2.0);
+    let brighter_scores = grid.score(&brighter, metric.as_ref());
+    assert!((brighter_scores[0] - scores[0]).abs() < scores[0] * 0.01);
+}
+
+#[test]
+fn test_each_patch_keeps_its_sharpest_frames() {
+    let grid = PatchGrid::new(WIDTH, HEIGHT, 32);
+    let frames = [
+        frame(|x| x < 32),
+        frame(|x| x >= 32),
+        frame(|x| x < 32),
+        frame(|_| false),
+    ];
+    let options = PatchQualityOptions {
+        patch_size: 32,
+        top_percentage: 50,
+        min_relative_quality: 0.5,
+        ..Default::default()
+    };
+    let metric = options.metric();
+    let scores: Vec<Vec<f32>> = frames
+        .iter()
+        .map(|f| grid.score(f, metric.as_ref()))
+        .collect();
+    let selection = PatchSelection::new(grid, &scores, &options);
+    assert_eq!(selection.num_frames(), 4);
+
+    // Two sharp frames on the left, only one on the right good enough to keep
+    assert_eq!(selection.frames_per_patch(), vec![2, 1]);
+
+    let at_left = |f: usize| selection.weights(f).at(8.0, 16.0);
+    let at_right = |f: usize| selection.weights(f).at(56.0, 16.0);
+    assert_eq!(
+        (at_left(0), at_left(1), at_left(2), at_left(3)),
+        (1.0, 0.0, 1.0, 0.0)
+    );
+    assert_eq!(
+        (at_right(0), at_right(1), at_right(2), at_right(3)),
+        (0.0, 1.0, 0.0, 0.0)
+    );
+
+    // Blended across the seam between the patches
+    let middle = selection.weights(1).at(32.0, 16.0);
+    assert!(middle > 0.4 && middle < 0.6, "{}", middle);
+}
+
+#[test]
+fn test_nan_scores_rank_last() {
+    let grid = PatchGrid::new(WIDTH, HEIGHT, 32);
+    let scores = vec![vec![f32::NAN, 5.0], vec![10.0, f32::NAN], vec![4.0, 8.0]];
+    let options = PatchQualityOptions {
+        patch_size: 32,
+        top_percentage: 34,
+        min_relative_quality: 0.0,
+        ..Default::default()
+    };
+    let selection = PatchSelection::new(grid, &scores, &options);
+    assert_eq!(selection.frames_per_patch(), vec![1, 1]);
+    assert_eq!(selection.weights(1).at(8.0, 16.0), 1.0);
+    assert_eq!(selection.weights(2).at(56.0, 16.0), 1.0);
+}
+
+#[test]
+fn test_drizzle_stacks_regions_from_selected_frames() {
+    let grid = PatchGrid::new(WIDTH, HEIGHT, 16);
+    let left = frame(|x| x < 32);
+    let right = frame(|x| x >= 32);
+    let options = PatchQualityOptions {
+        patch_size: 16,
+        top_percentage: 50,
+        ..Default::default()
+    };
+    let metric = options.metric();
+    let scores = vec![
+        grid.score(&left, metric.as_ref()),
+        grid.score(&right, metric.as_ref()),
+    ];
+    let selection = PatchSelection::new(grid, &scores, &options);
+
+    // Each frame carries its own flat level, so the stack shows which frame each region came from
+    let mut drizzle = BilinearDrizzle::new(WIDTH, HEIGHT, Scale::Scale1_0, 3);
+    for (i, level) in [100.0, 200.0].iter().enumerate() {
+        let mut img = image::Image::new_with_bands(WIDTH, HEIGHT, 1, ImageMode::U16BIT).unwrap();
+        for y in 0..HEIGHT {
+            for x in 0..WIDTH {
+                img.put(x, y, *level, 0);
+            }
+        }
+        drizzle
+            .add_weighted(
+                &img,
+                Offset::default(),
+                0.0,
+                None,
+                Some(&selection.weights(i)),
+            )
+            .unwrap();
+    }
+
+    let stacked = drizzle.get_finalized().unwrap();
+    assert!((stacked.get_band(0).get(4, 16) - 100.0).abs() < 0.5);
+    assert!((stacked.get_band(0).get(58, 16) - 200.0).abs() < 0.5);
+
+    let weights = drizzle.get_weight_map();
+    assert!((weights.get(4, 16) - 1.0).abs() < 0.01);
+    assert!((weights.get(58, 16) - 1.0).abs() < 0.01);
+}
@@ -0,0 +1,98 @@
+use sciimg::imagebuffer::ImageBuffer;
+use sciimg::{enums::ImageMode, image};
+use solhat::enums::QualityMethod;
+use solhat::quality;
+
+const WIDTH: usize = 64;
+const HEIGHT: usize = 64;
+
+const NORMALIZED: [QualityMethod; 4] = [
+    QualityMethod::Laplacian,
+    QualityMethod::Tenengrad,
+    QualityMethod::LocalContrast,
+    QualityMethod::FftHighFrequency,
+];
+
+// Granulation-like detail at a given level, blurred by a box of the given radius
+fn frame(level: f32, blur: usize) -> image::Image {
+    let mut sharp = ImageBuffer::new(WIDTH, HEIGHT).unwrap();
+    for y in 0..HEIGHT {
+        for x in 0..WIDTH {
+            let (u, v) = (x as f32, y as f32);
+            let detail = 0.2 * (u * 1.9).sin() * (v * 1.7).cos()
+                + 0.1 * (u * 0.7 + v * 1.1).sin()
+                + 0.05 * (u * 0.23 - v * 0.31).cos();
+            sharp.put(x, y, level * (1.0 + detail));
+        }
+    }
+
+    let mut img = image::Image::new_with_bands(WIDTH, HEIGHT, 1, ImageMode::U16BIT).unwrap();
+    for y in 0..HEIGHT {
+        for x in 0..WIDTH {
+            let (mut sum, mut count) = (0.0, 0.0);
+            for yy in y.saturating_sub(blur)..(y + blur + 1).min(HEIGHT) {
+                for xx in x.saturating_sub(blur)..(x + blur + 1).min(WIDTH) {
+                    sum += sharp.get(xx, yy);
+                    count += 1.0;
+                }
+            }
+            img.put(x, y, sum / count, 0);
+        }
+    }
+    img
+}
+
+#[test]
+fn test_sharper_frames_score_higher() {
+    for method in NORMALIZED {
+        let metric = quality::metric(method);
+        assert_eq!(metric.method(), method);
+        let sharp = metric.estimate(&frame(1000.0, 0));
+        let blurred = metric.estimate(&frame(1000.0, 1));
+        let very_blurred = metric.estimate(&frame(1000.0, 3));
+        assert!(
+            sharp > blurred && blurred > very_blurred,
+            "{:?}: {} {} {}",
+            method,
+            sharp,
+            blurred,
+            very_blurred
+        );
+    }
+}
+
+#[test]
+fn test_scores_are_independent_of_bit_depth() {
+    // The same scene from an 8 bit and a 16 bit camera
+    for method in NORMALIZED {
+        let metric = quality::metric(method);
+        let eight_bit = metric.estimate(&frame(100.0, 1));
+        let sixteen_bit = metric.estimate(&frame(100.0 * 256.0, 1));
+        assert!(
+            (eight_bit - sixteen_bit).abs() < eight_bit * 0.01,
+            "{:?}: {} {}",
+            method,
+            eight_bit,
+            sixteen_bit
+        );
+    }
+}
+
+#[test]
+fn test_quality_method_from_str() {
+    assert_eq!(QualityMethod::from("sigma"), Some(QualityMethod::Sigma));
+    assert_eq!(
+        QualityMethod::from("Laplacian"),
+        Some(QualityMethod::Laplacian)
+    );
+    assert_eq!(QualityMethod::from("sobel"), Some(QualityMethod::Tenengrad));
+    assert_eq!(
+        QualityMethod::from("contrast"),
+        Some(QualityMethod::LocalContrast)
+    );
+    assert_eq!(
+        QualityMethod::from("FFT"),
+        Some(QualityMethod::FftHighFrequency)
+    );
+    assert_eq!(QualityMethod::from("sharpest"), None);
+}
@@ -10,7 +10,7 @@ fn test_load_ser() {
 
     // Load SER file and validate
     let ser_file = ser::SerFile::load_ser(test_ser_file).expect("Unable to load SER file");
-    ser_file.validate();
+    assert!(ser_file.validate().is_intact());
 
     // Print header details to stdout
     ser_file.print_header_details();
@@ -23,18 +23,14 @@ fn test_load_ser() {
     assert_eq!(ser_file.image_height, 1216);
     assert_eq!(ser_file.pixel_depth, 16);
     assert_eq!(ser_file.frame_count, 5);
+    assert_eq!(ser_file.observer, "Kevin M. Gill");
+    assert_eq!(ser_file.instrument, "ASI=ZWO ASI174MMtemp=45.8");
+    assert_eq!(ser_file.telescope, "fps=55.25gain=160exp=2.50");
     assert_eq!(
-        ser_file.observer,
-        "Kevin M. Gill\0\0\0\0\0\0\0@\u{7}\0\0\0\0\0\0\0\0\0\0\0\0\0\0\u{5}\0\0\0"
-    );
-    assert_eq!(
-        ser_file.instrument,
-        "ASI=ZWO ASI174MMtemp=45.8\0P\0l\0a\0y\0e\0r\0\\\0"
-    );
-    assert_eq!(
-        ser_file.telescope,
-        "fps=55.25gain=160exp=2.50\0:\00\00\00\00\00\02\0"
+        ser_file.header_metadata.camera_model,
+        Some(String::from("ZWO ASI174MM"))
     );
+    assert_eq!(ser_file.header_metadata.sensor_temperature, Some(45.8));
     assert_eq!(
         ser_file.date_time,
         timestamp::TimeStamp::from_u64(638225427037360000)
@@ -0,0 +1,26 @@
+#[path = "common/perpixel.rs"]
+mod perpixel;
+
+use perpixel::decode_per_pixel;
+use sciimg::binfilereader::*;
+use solhat::ser;
+
+const TEST_SER_FILE: &str = "testdata/Sun_130540_F0001-0005.ser";
+
+#[test]
+fn test_bulk_decode_matches_per_pixel() {
+    let ser_file = ser::SerFile::load_ser(TEST_SER_FILE).expect("Unable to load SER file");
+    let file_reader = BinFileReader::new(&TEST_SER_FILE.to_string());
+
+    for frame_num in 0..ser_file.frame_count {
+        let per_pixel = decode_per_pixel(&ser_file, &file_reader, frame_num);
+        let bulk = ser_file.get_frame(frame_num).unwrap();
+        let per_pixel_band = per_pixel.buffer.get_band(0);
+        let bulk_band = bulk.buffer.get_band(0);
+        for y in 0..ser_file.image_height {
+            for x in 0..ser_file.image_width {
+                assert_eq!(bulk_band.get(x, y), per_pixel_band.get(x, y));
+            }
+        }
+    }
+}
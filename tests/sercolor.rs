@@ -0,0 +1,174 @@
+mod common;
+
+use common::*;
+use solhat::ser;
+use std::fs;
+
+const RED: f32 = 1000.0;
+const GREEN: f32 = 2000.0;
+const BLUE: f32 = 3000.0;
+
+// A flat colored scene, as recorded through the 2x2 filter pattern of the given format
+fn mosaic_frame_bytes(width: usize, height: usize, pattern: [&str; 4]) -> Vec<u8> {
+    let mut frame_bytes: Vec<u8> = Vec::with_capacity(width * height * 2);
+    for y in 0..height {
+        for x in 0..width {
+            let v = match pattern[(y % 2) * 2 + (x % 2)] {
+                "R" => RED,
+                "G" => GREEN,
+                "B" => BLUE,
+                "C" => GREEN + BLUE,
+                "M" => RED + BLUE,
+                "Y" => RED + GREEN,
+                _ => panic!("Invalid filter"),
+            };
+            frame_bytes.extend_from_slice(&(v as u16).to_le_bytes());
+        }
+    }
+    frame_bytes
+}
+
+fn check_mosaic_format(name: &str, color_id: ser::ColorFormatId, pattern: [&str; 4]) {
+    let ser_path = temp_ser_path(name);
+
+    let mut writer = ser::SerWriter::new(&ser_path, 17, 13, 16, color_id);
+    let frame_bytes = mosaic_frame_bytes(17, 13, pattern);
+    writer.add_frame_bytes(&frame_bytes, None).unwrap();
+    writer.close().unwrap();
+
+    let ser_file = ser::SerFile::load_ser(&ser_path).unwrap();
+    assert_eq!(ser_file.color_id, color_id);
+
+    let frame = ser_file.get_frame(0).unwrap();
+    assert_eq!(frame.buffer.num_bands(), 3);
+    assert_eq!(frame.buffer.width, 17);
+    assert_eq!(frame.buffer.height, 13);
+
+    // Check away from the edges, where interpolation has a full neighborhood
+    for (x, y) in [(6, 6), (7, 6), (6, 7), (7, 7)] {
+        assert!((frame.buffer.get_band(0).get(x, y) - RED).abs() < 1.0);
+        assert!((frame.buffer.get_band(1).get(x, y) - GREEN).abs() < 1.0);
+        assert!((frame.buffer.get_band(2).get(x, y) - BLUE).abs() < 1.0);
+    }
+
+    // The raw mosaic comes back untouched
+    let mosaic = ser_file.get_frame_mosaic(0).unwrap();
+    assert_eq!(mosaic.buffer.num_bands(), 1);
+    for (i, px) in frame_bytes.chunks(2).enumerate() {
+        assert_eq!(
+            mosaic.buffer.get_band(0).get(i % 17, i / 17),
+            u16::from_le_bytes([px[0], px[1]]) as f32
+        );
+    }
+
+    fs::remove_file(&ser_path).unwrap();
+}
+
+#[test]
+fn test_bayer_rggb() {
+    check_mosaic_format("rggb", ser::ColorFormatId::BayerRggb, ["R", "G", "G", "B"]);
+}
+
+#[test]
+fn test_bayer_grbg() {
+    check_mosaic_format("grbg", ser::ColorFormatId::BayerGrbg, ["G", "R", "B", "G"]);
+}
+
+#[test]
+fn test_bayer_gbrg() {
+    check_mosaic_format("gbrg", ser::ColorFormatId::BayerGbrg, ["G", "B", "R", "G"]);
+}
+
+#[test]
+fn test_bayer_bggr() {
+    check_mosaic_format("bggr", ser::ColorFormatId::BayerBggr, ["B", "G", "G", "R"]);
+}
+
+#[test]
+fn test_bayer_cyym() {
+    check_mosaic_format("cyym", ser::ColorFormatId::BayerCyym, ["C", "Y", "Y", "M"]);
+}
+
+#[test]
+fn test_bayer_ycmy() {
+    check_mosaic_format("ycmy", ser::ColorFormatId::BayerYcmy, ["Y", "C", "M", "Y"]);
+}
+
+#[test]
+fn test_bayer_ymcy() {
+    check_mosaic_format("ymcy", ser::ColorFormatId::BayerYmcy, ["Y", "M", "C", "Y"]);
+}
+
+#[test]
+fn test_bayer_myyc() {
+    check_mosaic_format("myyc", ser::ColorFormatId::BayerMyyc, ["M", "Y", "Y", "C"]);
+}
+
+fn check_three_plane_format(name: &str, color_id: ser::ColorFormatId, order: [f32; 3]) {
+    let ser_path = temp_ser_path(name);
+
+    let mut writer = ser::SerWriter::new(&ser_path, 8, 6, 8, color_id);
+    let frame_bytes: Vec<u8> = (0..(8 * 6))
+        .flat_map(|i| order.iter().map(move |v| (*v / 20.0) as u8 + i as u8))
+        .collect();
+    writer.add_frame_bytes(&frame_bytes, None).unwrap();
+    writer.close().unwrap();
+
+    let ser_file = ser::SerFile::load_ser(&ser_path).unwrap();
+    let frame = ser_file.get_frame(0).unwrap();
+    assert_eq!(frame.buffer.num_bands(), 3);
+
+    for y in 0..6 {
+        for x in 0..8 {
+            let i = (y * 8 + x) as f32;
+            assert_eq!(frame.buffer.get_band(0).get(x, y), RED / 20.0 + i);
+            assert_eq!(frame.buffer.get_band(1).get(x, y), GREEN / 20.0 + i);
+            assert_eq!(frame.buffer.get_band(2).get(x, y), BLUE / 20.0 + i);
+        }
+    }
+
+    fs::remove_file(&ser_path).unwrap();
+}
+
+#[test]
+fn test_rgb() {
+    check_three_plane_format("rgb", ser::ColorFormatId::Rgb, [RED, GREEN, BLUE]);
+}
+
+#[test]
+fn test_bgr() {
+    check_three_plane_format("bgr", ser::ColorFormatId::Bgr, [BLUE, GREEN, RED]);
+}
+
+#[test]
+fn test_mono() {
+    let ser_path = temp_ser_path("mono_color");
+    write_synthetic_ser(&ser_path, 8, 6, 8, ser::ColorFormatId::Mono, 1, false);
+
+    let ser_file = ser::SerFile::load_ser(&ser_path).unwrap();
+    let frame = ser_file.get_frame(0).unwrap();
+    assert_eq!(frame.buffer.num_bands(), 1);
+    assert_eq!(
+        frame.buffer.get_band(0).get(3, 2),
+        synthetic_frame_bytes(48, 0)[19] as f32
+    );
+
+    fs::remove_file(&ser_path).unwrap();
+}
+
+#[test]
+fn test_unknown_color_format() {
+    assert!(ser::ColorFormatId::from_i32(5).is_err());
+
+    let ser_path = temp_ser_path("unknown_color");
+    write_synthetic_ser(&ser_path, 8, 6, 8, ser::ColorFormatId::Mono, 1, false);
+
+    // Overwrite the color id field with an undefined value
+    let mut ser_bytes = fs::read(&ser_path).unwrap();
+    ser_bytes[18..22].copy_from_slice(&5_i32.to_le_bytes());
+    fs::write(&ser_path, &ser_bytes).unwrap();
+
+    assert!(ser::SerFile::load_ser(&ser_path).is_err());
+
+    fs::remove_file(&ser_path).unwrap();
+}
@@ -0,0 +1,62 @@
+mod common;
+
+use common::*;
+use solhat::{ser, sercrop};
+use std::fs;
+
+#[test]
+fn test_centered_crop() {
+    let input_file = temp_ser_path("crop_input");
+    let output_file = temp_ser_path("crop_output");
+
+    // Disk wanders across the frame, including close to the edge
+    let centers = [(20.0, 15.0), (40.0, 30.0), (65.0, 52.0)];
+
+    let mut writer = ser::SerWriter::new(&input_file, 80, 60, 16, ser::ColorFormatId::Mono);
+    for (i, (cx, cy)) in centers.iter().enumerate() {
+        writer
+            .add_frame_bytes(
+                &disk_frame_bytes(80, 60, *cx, *cy, 6.0),
+                Some(synthetic_timestamp(i)),
+            )
+            .unwrap();
+    }
+    writer.close().unwrap();
+
+    let written = sercrop::crop_ser_files(&[&input_file], &output_file, 32, 24, 1000.0).unwrap();
+    assert_eq!(written, 3);
+
+    let ser_file = ser::SerFile::load_ser(&output_file).unwrap();
+    assert!(ser_file.validate().is_intact());
+    assert_eq!(ser_file.image_width, 32);
+    assert_eq!(ser_file.image_height, 24);
+    assert_eq!(ser_file.frame_count, 3);
+    assert_eq!(
+        ser_file.get_frame_timestamp(2).unwrap(),
+        synthetic_timestamp(2)
+    );
+
+    // The first two disks fit inside a centered box, the last is clamped against the frame edge
+    let (x0, y0) = bright_center_of_frame(ser_file.get_frame_bytes(0).unwrap(), 32, 1000);
+    assert!((x0 - 16.0).abs() <= 1.0 && (y0 - 12.0).abs() <= 1.0);
+
+    let (x1, y1) = bright_center_of_frame(ser_file.get_frame_bytes(1).unwrap(), 32, 1000);
+    assert!((x1 - 16.0).abs() <= 1.0 && (y1 - 12.0).abs() <= 1.0);
+
+    let (x2, y2) = bright_center_of_frame(ser_file.get_frame_bytes(2).unwrap(), 32, 1000);
+    assert!((x2 - (65.0 - 48.0)).abs() <= 1.0 && (y2 - (52.0 - 36.0)).abs() <= 1.0);
+
+    fs::remove_file(&input_file).unwrap();
+    fs::remove_file(&output_file).unwrap();
+}
+
+#[test]
+fn test_crop_larger_than_frame() {
+    let input_file = temp_ser_path("crop_too_large");
+    let output_file = temp_ser_path("crop_too_large_output");
+    write_synthetic_ser(&input_file, 32, 24, 8, ser::ColorFormatId::Mono, 1, false);
+
+    assert!(sercrop::crop_ser_files(&[&input_file], &output_file, 64, 24, 40.0).is_err());
+
+    fs::remove_file(&input_file).unwrap();
+}
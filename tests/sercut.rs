@@ -0,0 +1,94 @@
+mod common;
+
+use common::*;
+use solhat::{ser, sercut};
+use std::fs;
+
+fn frame_ids_in(ser_file: &ser::SerFile) -> Vec<usize> {
+    // Synthetic frames encode their index in the pixel pattern, match them back up
+    (0..ser_file.frame_count)
+        .map(|i| {
+            let bytes = ser_file.get_frame_bytes(i).unwrap();
+            (0..50)
+                .find(|f| synthetic_frame_bytes(bytes.len(), *f) == bytes)
+                .expect("Unrecognized frame data")
+        })
+        .collect()
+}
+
+#[test]
+fn test_cut_range_and_decimate() {
+    let input_file = temp_ser_path("cut_input");
+    let output_file = temp_ser_path("cut_output");
+    write_synthetic_ser(&input_file, 32, 24, 16, ser::ColorFormatId::Mono, 20, true);
+
+    let selection = sercut::FrameSelection {
+        start_frame: 2,
+        end_frame: Some(14),
+        every_nth: 3,
+        drop_frames: vec![8],
+    };
+    let written = sercut::cut_ser_files(&[&input_file], &output_file, &selection).unwrap();
+    assert_eq!(written, 4);
+
+    let ser_file = ser::SerFile::load_ser(&output_file).unwrap();
+    assert!(ser_file.validate().is_intact());
+    assert_eq!(ser_file.frame_count, 4);
+    assert_eq!(frame_ids_in(&ser_file), vec![2, 5, 11, 14]);
+    assert_eq!(
+        ser_file.get_frame_timestamp(2).unwrap(),
+        synthetic_timestamp(11)
+    );
+
+    fs::remove_file(&input_file).unwrap();
+    fs::remove_file(&output_file).unwrap();
+}
+
+#[test]
+fn test_concatenate() {
+    let first_file = temp_ser_path("concat_first");
+    let second_file = temp_ser_path("concat_second");
+    let output_file = temp_ser_path("concat_output");
+    write_synthetic_ser(&first_file, 32, 24, 8, ser::ColorFormatId::Mono, 3, true);
+    write_synthetic_ser(&second_file, 32, 24, 8, ser::ColorFormatId::Mono, 4, true);
+
+    let written = sercut::cut_ser_files(
+        &[&first_file, &second_file],
+        &output_file,
+        &sercut::FrameSelection::default(),
+    )
+    .unwrap();
+    assert_eq!(written, 7);
+
+    let ser_file = ser::SerFile::load_ser(&output_file).unwrap();
+    assert!(ser_file.validate().is_intact());
+    assert!(ser_file.has_timestamps());
+    assert_eq!(frame_ids_in(&ser_file), vec![0, 1, 2, 0, 1, 2, 3]);
+    assert_eq!(
+        ser_file.get_frame_timestamp(4).unwrap(),
+        synthetic_timestamp(1)
+    );
+
+    fs::remove_file(&first_file).unwrap();
+    fs::remove_file(&second_file).unwrap();
+    fs::remove_file(&output_file).unwrap();
+}
+
+#[test]
+fn test_concatenate_mismatched_geometry() {
+    let first_file = temp_ser_path("mismatch_first");
+    let second_file = temp_ser_path("mismatch_second");
+    let output_file = temp_ser_path("mismatch_output");
+    write_synthetic_ser(&first_file, 32, 24, 8, ser::ColorFormatId::Mono, 2, false);
+    write_synthetic_ser(&second_file, 32, 24, 16, ser::ColorFormatId::Mono, 2, false);
+
+    assert!(sercut::cut_ser_files(
+        &[&first_file, &second_file],
+        &output_file,
+        &sercut::FrameSelection::default(),
+    )
+    .is_err());
+
+    fs::remove_file(&first_file).unwrap();
+    fs::remove_file(&second_file).unwrap();
+}
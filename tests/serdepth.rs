@@ -0,0 +1,160 @@
+mod common;
+
+use common::*;
+use sciimg::enums::ImageMode;
+use solhat::ser;
+use std::fs;
+
+fn write_ser_with_values(ser_path: &str, pixel_depth: usize, values: &[u16]) {
+    let mut writer = ser::SerWriter::new(ser_path, 4, 2, pixel_depth, ser::ColorFormatId::Mono);
+    let frame_bytes: Vec<u8> = values.iter().flat_map(|v| v.to_le_bytes()).collect();
+    writer.add_frame_bytes(&frame_bytes, None).unwrap();
+    writer.close().unwrap();
+}
+
+fn frame_values(ser_file: &ser::SerFile) -> Vec<f32> {
+    let frame = ser_file.get_frame(0).unwrap();
+    (0..8)
+        .map(|i| frame.buffer.get_band(0).get(i % 4, i / 4))
+        .collect()
+}
+
+#[test]
+fn test_12bit_lsb_aligned() {
+    let ser_path = temp_ser_path("depth12_lsb");
+    let values = [0, 1, 17, 255, 1024, 2048, 4000, 4095];
+    write_ser_with_values(&ser_path, 12, &values);
+
+    let ser_file = ser::SerFile::load_ser(&ser_path).unwrap();
+    assert_eq!(ser_file.pixel_depth, 12);
+    assert_eq!(ser_file.bytes_per_pixel(), 2);
+    assert_eq!(ser_file.max_value(), 4095.0);
+    assert!(!ser_file.msb_aligned);
+    assert!(matches!(ser_file.image_mode(), ImageMode::U12BIT));
+    assert_eq!(
+        frame_values(&ser_file),
+        values.iter().map(|v| *v as f32).collect::<Vec<f32>>()
+    );
+
+    fs::remove_file(&ser_path).unwrap();
+}
+
+#[test]
+fn test_12bit_msb_aligned() {
+    let ser_path = temp_ser_path("depth12_msb");
+    let values = [0_u16, 1, 17, 255, 1024, 2048, 4000, 4095];
+    let shifted: Vec<u16> = values.iter().map(|v| v << 4).collect();
+    write_ser_with_values(&ser_path, 12, &shifted);
+
+    let ser_file = ser::SerFile::load_ser(&ser_path).unwrap();
+    assert!(ser_file.msb_aligned);
+    assert_eq!(
+        frame_values(&ser_file),
+        values.iter().map(|v| *v as f32).collect::<Vec<f32>>()
+    );
+
+    fs::remove_file(&ser_path).unwrap();
+}
+
+#[test]
+fn test_12bit_msb_aligned_dark_first_frame() {
+    let ser_path = temp_ser_path("depth12_msb_dark");
+    let mut writer = ser::SerWriter::new(&ser_path, 4, 2, 12, ser::ColorFormatId::Mono);
+    for frame_num in 0..3_u16 {
+        // Only the later frames are bright enough to reach past the 12 bit range
+        let values: Vec<u16> = (0..8_u16).map(|i| (i * frame_num * 250) << 4).collect();
+        let frame_bytes: Vec<u8> = values.iter().flat_map(|v| v.to_le_bytes()).collect();
+        writer.add_frame_bytes(&frame_bytes, None).unwrap();
+    }
+    writer.close().unwrap();
+
+    let ser_file = ser::SerFile::load_ser(&ser_path).unwrap();
+    assert!(ser_file.msb_aligned);
+
+    fs::remove_file(&ser_path).unwrap();
+}
+
+#[test]
+fn test_10bit_and_14bit() {
+    for (pixel_depth, max_value, mode_max) in [(10, 1023_u16, 4095.0), (14, 16383_u16, 65535.0)] {
+        let ser_path = temp_ser_path(&format!("depth{}", pixel_depth));
+        let values = [0, 3, 64, 100, 512, 1000, max_value - 1, max_value];
+        write_ser_with_values(&ser_path, pixel_depth, &values);
+
+        let ser_file = ser::SerFile::load_ser(&ser_path).unwrap();
+        assert!(!ser_file.msb_aligned);
+        assert_eq!(ser_file.max_value(), max_value as f32);
+
+        // Scaled to the image mode, so saturation is at the mode's maximum
+        let scale = mode_max / max_value as f32;
+        assert_eq!(ser_file.value_scale(), scale);
+        let decoded = frame_values(&ser_file);
+        assert_eq!(decoded[7], mode_max);
+        for (d, v) in decoded.iter().zip(values.iter()) {
+            assert!((d - *v as f32 * scale).abs() < 0.01);
+        }
+
+        fs::remove_file(&ser_path).unwrap();
+    }
+}
+
+#[test]
+fn test_saturation_round_trip() {
+    for pixel_depth in [10, 14] {
+        let ser_path = temp_ser_path(&format!("depth{}_saturated", pixel_depth));
+        let max_value = (1_u16 << pixel_depth) - 1;
+        write_ser_with_values(&ser_path, pixel_depth, &[max_value; 8]);
+        let frame = ser::SerFile::load_ser(&ser_path)
+            .unwrap()
+            .get_frame(0)
+            .unwrap();
+
+        let copy_path = temp_ser_path(&format!("depth{}_saturated_copy", pixel_depth));
+        let mut writer =
+            ser::SerWriter::new(&copy_path, 4, 2, pixel_depth, ser::ColorFormatId::Mono);
+        writer.add_frame(&frame).unwrap();
+        writer.close().unwrap();
+
+        let copy = ser::SerFile::load_ser(&copy_path).unwrap();
+        assert_eq!(
+            copy.get_frame_bytes(0).unwrap(),
+            max_value.to_le_bytes().repeat(8)
+        );
+
+        fs::remove_file(&ser_path).unwrap();
+        fs::remove_file(&copy_path).unwrap();
+    }
+}
+
+#[test]
+fn test_low_bit_depth() {
+    let ser_path = temp_ser_path("depth6");
+    let mut writer = ser::SerWriter::new(&ser_path, 4, 2, 6, ser::ColorFormatId::Mono);
+    writer
+        .add_frame_bytes(&[0, 1, 2, 3, 60, 61, 62, 63], None)
+        .unwrap();
+    writer.close().unwrap();
+
+    let ser_file = ser::SerFile::load_ser(&ser_path).unwrap();
+    assert_eq!(ser_file.bytes_per_pixel(), 1);
+    assert_eq!(ser_file.max_value(), 63.0);
+    assert!(matches!(ser_file.image_mode(), ImageMode::U8BIT));
+    assert_eq!(ser_file.value_scale(), 255.0 / 63.0);
+    assert_eq!(frame_values(&ser_file)[7], 255.0);
+
+    fs::remove_file(&ser_path).unwrap();
+}
+
+#[test]
+fn test_unsupported_pixel_depth() {
+    let ser_path = temp_ser_path("depth_invalid");
+    write_synthetic_ser(&ser_path, 4, 2, 16, ser::ColorFormatId::Mono, 1, false);
+
+    let mut ser_bytes = fs::read(&ser_path).unwrap();
+    ser_bytes[34..38].copy_from_slice(&24_i32.to_le_bytes());
+    fs::write(&ser_path, &ser_bytes).unwrap();
+
+    assert!(ser::SerFile::load_ser(&ser_path).is_err());
+
+    fs::remove_file(&ser_path).unwrap();
+}
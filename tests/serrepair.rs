@@ -0,0 +1,149 @@
+mod common;
+
+use common::*;
+use solhat::framesource::{self, FrameSource};
+use solhat::ser;
+use std::fs::{self, OpenOptions};
+use std::io::{Seek, SeekFrom, Write};
+
+const FRAME_SIZE_BYTES: usize = 32 * 32;
+
+fn write_damaged_ser(name: &str, with_timestamps: bool, damage: impl Fn(&fs::File)) -> String {
+    let ser_path = temp_ser_path(name);
+    write_synthetic_ser(
+        &ser_path,
+        32,
+        32,
+        8,
+        ser::ColorFormatId::Mono,
+        4,
+        with_timestamps,
+    );
+    let file = OpenOptions::new().write(true).open(&ser_path).unwrap();
+    damage(&file);
+    ser_path
+}
+
+#[test]
+fn test_truncated_frames() {
+    let ser_path = write_damaged_ser("truncated_frames", true, |f| {
+        f.set_len((178 + FRAME_SIZE_BYTES * 2 + 100) as u64)
+            .unwrap()
+    });
+
+    let ser_file = ser::SerFile::load_ser(&ser_path).expect("Unable to load SER file");
+    let report = ser_file.validate();
+    assert!(!report.is_intact());
+    assert_eq!(report.complete_frames, 2);
+    assert_eq!(
+        report.issues[0],
+        ser::SerIssue::TruncatedFrames {
+            complete_frames: 2,
+            declared_frames: 4
+        }
+    );
+    assert!(!ser_file.has_timestamps());
+
+    // Opening as a frame source skips the lost frames instead of failing
+    let frame_source = framesource::open(&ser_path).expect("Failed to open frame source");
+    assert_eq!(frame_source.frame_count(), 2);
+    assert!(frame_source.read_frame(1).is_ok());
+
+    let repaired_path = temp_ser_path("truncated_frames_repaired");
+    assert_eq!(ser_file.write_repaired(&repaired_path).unwrap(), 2);
+    let repaired = ser::SerFile::load_ser(&repaired_path).expect("Unable to load SER file");
+    assert!(repaired.validate().is_intact());
+    assert_eq!(repaired.frame_count, 2);
+    for i in 0..2 {
+        assert_eq!(
+            repaired.get_frame_bytes(i).unwrap(),
+            &synthetic_frame_bytes(FRAME_SIZE_BYTES, i)[..]
+        );
+    }
+
+    fs::remove_file(&ser_path).ok();
+    fs::remove_file(&repaired_path).ok();
+}
+
+#[test]
+fn test_partial_timestamp_trailer() {
+    let ser_path = write_damaged_ser("partial_timestamps", true, |f| {
+        f.set_len((178 + FRAME_SIZE_BYTES * 4 + 8 * 3) as u64)
+            .unwrap()
+    });
+
+    let ser_file = ser::SerFile::load_ser(&ser_path).expect("Unable to load SER file");
+    let report = ser_file.validate();
+    assert!(!report.is_intact());
+    assert_eq!(report.complete_frames, 4);
+    assert_eq!(
+        report.issues,
+        vec![ser::SerIssue::PartialTimestamps {
+            timestamps_present: 3,
+            declared_frames: 4
+        }]
+    );
+
+    // All frames are kept, but a trailer that doesn't cover them all is dropped
+    let repaired_path = temp_ser_path("partial_timestamps_repaired");
+    assert_eq!(ser_file.write_repaired(&repaired_path).unwrap(), 4);
+    let repaired = ser::SerFile::load_ser(&repaired_path).expect("Unable to load SER file");
+    assert!(repaired.validate().is_intact());
+    assert!(!repaired.has_timestamps());
+
+    fs::remove_file(&ser_path).ok();
+    fs::remove_file(&repaired_path).ok();
+}
+
+#[test]
+fn test_frame_count_mismatch() {
+    let ser_path = write_damaged_ser("frame_count_mismatch", false, |mut f| {
+        f.seek(SeekFrom::Start(38)).unwrap();
+        f.write_all(&1_i32.to_le_bytes()).unwrap();
+    });
+
+    let ser_file = ser::SerFile::load_ser(&ser_path).expect("Unable to load SER file");
+    let report = ser_file.validate();
+    assert_eq!(
+        report.issues,
+        vec![ser::SerIssue::FrameCountMismatch {
+            declared_frames: 1,
+            frames_in_file: 4
+        }]
+    );
+
+    // The frames the header doesn't account for are recovered
+    let repaired_path = temp_ser_path("frame_count_mismatch_repaired");
+    assert_eq!(ser_file.write_repaired(&repaired_path).unwrap(), 4);
+    let repaired = ser::SerFile::load_ser(&repaired_path).expect("Unable to load SER file");
+    assert!(repaired.validate().is_intact());
+    assert_eq!(repaired.frame_count, 4);
+    assert_eq!(
+        repaired.get_frame_bytes(3).unwrap(),
+        &synthetic_frame_bytes(FRAME_SIZE_BYTES, 3)[..]
+    );
+
+    fs::remove_file(&ser_path).ok();
+    fs::remove_file(&repaired_path).ok();
+}
+
+#[test]
+fn test_non_monotonic_timestamps() {
+    let ser_path = write_damaged_ser("non_monotonic", true, |mut f| {
+        // Give frame 2 an earlier timestamp than frame 1
+        f.seek(SeekFrom::Start((178 + FRAME_SIZE_BYTES * 4 + 8 * 2) as u64))
+            .unwrap();
+        f.write_all(&synthetic_timestamp(0).to_le_bytes()).unwrap();
+    });
+
+    let ser_file = ser::SerFile::load_ser(&ser_path).expect("Unable to load SER file");
+    let report = ser_file.validate();
+    assert!(report.is_intact());
+    assert_eq!(report.timestamps_present, 4);
+    assert_eq!(
+        report.issues,
+        vec![ser::SerIssue::NonMonotonicTimestamps { frame_num: 2 }]
+    );
+
+    fs::remove_file(&ser_path).ok();
+}
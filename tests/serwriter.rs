@@ -0,0 +1,221 @@
+mod common;
+
+use common::*;
+use sciimg::path;
+use solhat::ser;
+use std::fs;
+
+fn rewrite_ser(input_file: &str, output_file: &str) {
+    let ser_file = ser::SerFile::load_ser(input_file).expect("Unable to load SER file");
+    let mut writer =
+        ser::SerWriter::new_from_ser(output_file, &ser_file).expect("Failed to create writer");
+    for i in 0..ser_file.frame_count {
+        let timestamp = if ser_file.has_timestamps() {
+            Some(ser_file.get_frame_timestamp(i).unwrap())
+        } else {
+            None
+        };
+        writer
+            .add_frame_bytes(ser_file.get_frame_bytes(i).unwrap(), timestamp)
+            .expect("Failed to write frame");
+    }
+    writer.close().expect("Failed to close SER writer");
+}
+
+fn check_round_trip(
+    name: &str,
+    pixel_depth: usize,
+    color_id: ser::ColorFormatId,
+    with_timestamps: bool,
+) {
+    let first_path = temp_ser_path(&format!("{}_a", name));
+    let second_path = temp_ser_path(&format!("{}_b", name));
+
+    write_synthetic_ser(
+        &first_path,
+        64,
+        48,
+        pixel_depth,
+        color_id,
+        4,
+        with_timestamps,
+    );
+
+    let ser_file = ser::SerFile::load_ser(&first_path).expect("Unable to load SER file");
+    assert!(ser_file.validate().is_intact());
+    assert_eq!(ser_file.file_id, "LUCAM-RECORDER");
+    assert_eq!(ser_file.color_id, color_id);
+    assert_eq!(ser_file.image_width, 64);
+    assert_eq!(ser_file.image_height, 48);
+    assert_eq!(ser_file.pixel_depth, pixel_depth);
+    assert_eq!(ser_file.frame_count, 4);
+    assert_eq!(ser_file.date_time.timestamp, 638225427037360000);
+    assert_eq!(ser_file.date_time_utc.timestamp, 638225427036930000);
+    assert_eq!(ser_file.has_timestamps(), with_timestamps);
+    assert_eq!(ser_file.total_size, ser_file.expected_size());
+
+    for i in 0..ser_file.frame_count {
+        assert_eq!(
+            ser_file.get_frame_bytes(i).unwrap(),
+            &synthetic_frame_bytes(ser_file.image_frame_size_bytes(), i)[..]
+        );
+        let expected_ts = if with_timestamps {
+            synthetic_timestamp(i)
+        } else {
+            0
+        };
+        assert_eq!(ser_file.get_frame_timestamp(i).unwrap(), expected_ts);
+    }
+
+    rewrite_ser(&first_path, &second_path);
+    assert_eq!(
+        fs::read(&first_path).unwrap(),
+        fs::read(&second_path).unwrap()
+    );
+
+    fs::remove_file(&first_path).unwrap();
+    fs::remove_file(&second_path).unwrap();
+}
+
+#[test]
+fn test_round_trip_mono_8bit() {
+    check_round_trip("mono8", 8, ser::ColorFormatId::Mono, true);
+}
+
+#[test]
+fn test_round_trip_mono_16bit() {
+    check_round_trip("mono16", 16, ser::ColorFormatId::Mono, true);
+}
+
+#[test]
+fn test_round_trip_mono_16bit_no_timestamps() {
+    check_round_trip("mono16_nots", 16, ser::ColorFormatId::Mono, false);
+}
+
+#[test]
+fn test_round_trip_bayer_8bit() {
+    check_round_trip("rggb8", 8, ser::ColorFormatId::BayerRggb, true);
+}
+
+#[test]
+fn test_round_trip_bayer_16bit() {
+    check_round_trip("gbrg16", 16, ser::ColorFormatId::BayerGbrg, true);
+}
+
+#[test]
+fn test_round_trip_rgb_8bit() {
+    check_round_trip("rgb8", 8, ser::ColorFormatId::Rgb, true);
+}
+
+#[test]
+fn test_round_trip_bgr_16bit() {
+    check_round_trip("bgr16", 16, ser::ColorFormatId::Bgr, false);
+}
+
+#[test]
+fn test_rejects_wrong_frame_size() {
+    let output_file = temp_ser_path("wrong_size");
+    let mut writer = ser::SerWriter::new(&output_file, 16, 16, 16, ser::ColorFormatId::Mono);
+    assert!(writer.add_frame_bytes(&[0; 16], None).is_err());
+    assert_eq!(writer.frame_count(), 0);
+}
+
+#[test]
+fn test_round_trip_fixture() {
+    let test_ser_file = "testdata/Sun_130540_F0001-0005.ser";
+    assert!(path::file_exists(test_ser_file));
+
+    let output_file = temp_ser_path("fixture_copy");
+    rewrite_ser(test_ser_file, &output_file);
+
+    assert_eq!(
+        fs::read(test_ser_file).unwrap(),
+        fs::read(&output_file).unwrap()
+    );
+    fs::remove_file(&output_file).unwrap();
+}
+
+#[test]
+fn test_header_strings_trimmed_and_parsed() {
+    let output_path = temp_ser_path("header_metadata");
+    let mut writer = ser::SerWriter::new(&output_path, 16, 16, 8, ser::ColorFormatId::Mono);
+    writer.observer = "Kevin M. Gill\0\0@\u{7}\0junk".to_string();
+    writer.instrument = "ASI=ZWO ASI174MMtemp=45.8\0P\0l\0a\0y".to_string();
+    writer.telescope = "fps=55.25gain=160exp=2.50\0:\0junk".to_string();
+    writer
+        .add_frame_bytes(
+            &synthetic_frame_bytes(writer.image_frame_size_bytes(), 0),
+            None,
+        )
+        .expect("Failed to write frame");
+    writer.close().expect("Failed to close SER writer");
+
+    let ser_file = ser::SerFile::load_ser(&output_path).expect("Unable to load SER file");
+    assert_eq!(ser_file.observer, "Kevin M. Gill");
+    assert_eq!(ser_file.instrument, "ASI=ZWO ASI174MMtemp=45.8");
+    assert_eq!(ser_file.telescope, "fps=55.25gain=160exp=2.50");
+    assert_eq!(
+        ser_file.header_metadata,
+        ser::HeaderMetadata {
+            camera_model: Some(String::from("ZWO ASI174MM")),
+            sensor_temperature: Some(45.8),
+            fps: Some(55.25),
+            gain: Some(160.0),
+            exposure_ms: Some(2.5),
+        }
+    );
+
+    let summary = serde_json::to_string(&ser_file.header_summary()).unwrap();
+    assert!(summary.contains("\"camera_model\":\"ZWO ASI174MM\""));
+    assert!(summary.contains("\"observer\":\"Kevin M. Gill\""));
+
+    fs::remove_file(&output_path).ok();
+}
+
+#[test]
+fn test_header_metadata_without_pairs() {
+    let metadata = ser::HeaderMetadata::parse("QHY5III462C", "");
+    assert_eq!(metadata.camera_model, Some(String::from("QHY5III462C")));
+    assert_eq!(metadata.gain, None);
+
+    let metadata = ser::HeaderMetadata::parse("", "Gain=300 Exposure=4.1");
+    assert_eq!(metadata.camera_model, None);
+    assert_eq!(metadata.gain, Some(300.0));
+    assert_eq!(metadata.exposure_ms, Some(4.1));
+}
+
+#[test]
+fn test_encode_frames_from_big_endian_source() {
+    let source_path = temp_ser_path("big_endian_source");
+    let copy_path = temp_ser_path("big_endian_copy");
+    let values: Vec<u16> = (0..16).map(|i| i * 4000).collect();
+
+    let mut writer = ser::SerWriter::new(&source_path, 4, 4, 16, ser::ColorFormatId::Mono);
+    writer.big_endian = true;
+    writer.observer = "Observer".to_string();
+    let frame_bytes: Vec<u8> = values.iter().flat_map(|v| v.to_be_bytes()).collect();
+    writer.add_frame_bytes(&frame_bytes, None).unwrap();
+    writer.close().unwrap();
+
+    let source = ser::SerFile::load_ser(&source_path).expect("Unable to load SER file");
+    assert_eq!(source.image_width, 4);
+    assert_eq!(source.pixel_depth, 16);
+    let frame = source.get_frame(0).unwrap();
+    assert_eq!(frame.buffer.get_band(0).get(1, 0), 4000.0);
+
+    let mut writer = ser::SerWriter::new_from_ser(&copy_path, &source).unwrap();
+    writer.observer = "Someone Else".to_string();
+    writer.add_frame(&frame).unwrap();
+    writer.close().unwrap();
+
+    let copy = ser::SerFile::load_ser(&copy_path).expect("Unable to load SER file");
+    assert_eq!(copy.observer, "Someone Else");
+    assert_eq!(copy.frame_count, 1);
+    let copy_frame = copy.get_frame(0).unwrap();
+    for (i, v) in values.iter().enumerate() {
+        assert_eq!(copy_frame.buffer.get_band(0).get(i % 4, i / 4), *v as f32);
+    }
+
+    fs::remove_file(&source_path).unwrap();
+    fs::remove_file(&copy_path).unwrap();
+}
@@ -0,0 +1,106 @@
+mod common;
+
+use common::*;
+use solhat::geometry::Disk;
+use solhat::master;
+use solhat::ser;
+use solhat::synthflat::{self, SyntheticFlatOptions};
+use solhat::util;
+
+const SIZE: usize = 64;
+const RADIUS: f32 = 24.0;
+
+// Banding fixed to the sensor, with a period of six columns
+fn banding(x: usize) -> f32 {
+    1.0 + 0.05 * (x as f32 * std::f32::consts::TAU / 6.0).sin()
+}
+
+// Limb darkened disks at the given centers, one per frame, seen through the banding
+fn write_disks_ser(name: &str, centers: &[(f32, f32)]) -> String {
+    write_ser_from_fn(
+        name,
+        SIZE,
+        SIZE,
+        ser::ColorFormatId::Mono,
+        centers.len(),
+        |i, x, y| {
+            let (cx, cy) = centers[i];
+            (limb_darkened_disk(x, y, cx, cy, RADIUS, 0.0) * banding(x)) as u16
+        },
+    )
+}
+
+// A limb darkened disk drifting by a pixel or so between frames
+fn write_disk_ser(name: &str, num_frames: usize) -> String {
+    let centers: Vec<(f32, f32)> = (0..num_frames)
+        .map(|i| {
+            (
+                SIZE as f32 / 2.0 + (i % 3) as f32 - 1.0,
+                SIZE as f32 / 2.0 + (i % 2) as f32 - 0.5,
+            )
+        })
+        .collect();
+    write_disks_ser(name, &centers)
+}
+
+#[test]
+fn test_synthetic_flat_keeps_banding() {
+    let ser_path = write_disk_ser("synthetic_flat", 12);
+    let options = SyntheticFlatOptions {
+        obj_detect_threshold: 100.0,
+        limb_margin: 0.8,
+        highpass_radius: 12,
+        ..Default::default()
+    };
+    let flat = synthflat::build_synthetic_flat(&[&ser_path], &None, &options).unwrap();
+    assert_eq!(flat.metadata.frame_count, 12);
+    assert!(flat.metadata.normalized);
+
+    let level = util::mode_max_value(flat.image.get_mode()) * master::NORMALIZED_FLAT_LEVEL;
+    let band = flat.image.get_band(0);
+
+    // Inside the disk the flat follows the banding, with the limb darkening gone
+    let c = SIZE / 2;
+    for (x, y) in [(c, c), (c - 10, c + 4), (c + 13, c - 3), (c + 5, c + 12)] {
+        let v = band.get(x, y) / level;
+        assert!(
+            (v - banding(x)).abs() < 0.02,
+            "{} vs {} at ({}, {})",
+            v,
+            banding(x),
+            x,
+            y
+        );
+    }
+
+    // Off the disk the flat leaves frames alone
+    assert_eq!(band.get(1, 1), level);
+}
+
+#[test]
+fn test_synthetic_flat_needs_a_steady_disk() {
+    // Two frames with the disk far apart leave none near the typical position between them
+    let ser_path = write_disks_ser("synthetic_flat_moving", &[(26.0, 32.0), (38.0, 32.0)]);
+    let options = SyntheticFlatOptions {
+        obj_detect_threshold: 100.0,
+        ..Default::default()
+    };
+    assert!(synthflat::build_synthetic_flat(&[&ser_path], &None, &options).is_err());
+}
+
+#[test]
+fn test_disk_detection() {
+    let ser_path = write_disk_ser("synthetic_flat_disk", 1);
+    let frame = ser::SerFile::load_ser(&ser_path)
+        .unwrap()
+        .get_frame(0)
+        .unwrap()
+        .buffer;
+
+    let disk = Disk::find(&frame, 100.0).unwrap();
+    assert!((disk.center_x - 31.0).abs() < 0.5);
+    assert!((disk.center_y - 31.5).abs() < 0.5);
+    assert!((disk.radius - RADIUS).abs() < 1.0);
+
+    assert!(Disk::find(&frame, 60000.0).is_none());
+}
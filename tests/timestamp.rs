@@ -0,0 +1,124 @@
+use chrono::{Datelike, NaiveDate, Timelike};
+use solhat::timestamp::TimeStamp;
+
+const TICKS_PER_DAY: u64 = 864000000000;
+
+// Civil date from days since 0001-01-01, independent of chrono
+// (http://howardhinnant.github.io/date_algorithms.html, shifted to the 0001-01-01 epoch)
+fn civil_from_days(days: u64) -> (i32, i32, i32) {
+    let z = days as i64 + 306; // Days since 0000-03-01
+    let era = z / 146097;
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year as i32, month as i32, day as i32)
+}
+
+// Deterministic pseudo-random ticks spanning 0001-01-01 to 9999-12-31
+fn sample_ticks(count: usize) -> Vec<u64> {
+    let max_ticks = 3155378975999999999_u64;
+    let mut state: u64 = 0x2545F4914F6CDD1D;
+    (0..count)
+        .map(|_| {
+            state = state
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (state >> 1) % max_ticks
+        })
+        .collect()
+}
+
+#[test]
+fn test_known_ser_timestamps() {
+    let ts = TimeStamp::from_u64(638225427037360000);
+    assert_eq!(
+        (ts.year, ts.month, ts.day, ts.hour, ts.minute, ts.second),
+        (2023, 6, 16, 20, 5, 3)
+    );
+    assert_eq!(ts.microsecond, 736000);
+
+    let ts = TimeStamp::from_u64(637648348476340000);
+    assert_eq!((ts.year, ts.month, ts.day), (2021, 8, 17));
+    assert_eq!((ts.hour, ts.minute, ts.second), (22, 7, 27));
+
+    let ts = TimeStamp::from_u64(0);
+    assert_eq!((ts.year, ts.month, ts.day, ts.hour), (1, 1, 1, 0));
+}
+
+#[test]
+fn test_leap_years_and_centuries() {
+    // 2000 is a leap year, being divisible by 400
+    let ts = TimeStamp::from_u64(630874224000000000);
+    assert_eq!((ts.year, ts.month, ts.day, ts.hour), (2000, 2, 29, 12));
+
+    // 1900 and 2100 aren't, being centuries
+    let ts = TimeStamp::from_u64(599317056000000000);
+    assert_eq!((ts.year, ts.month, ts.day), (1900, 3, 1));
+    let ts = TimeStamp::from_u64(599317056000000000 - 1);
+    assert_eq!((ts.year, ts.month, ts.day), (1900, 2, 28));
+
+    let ts = TimeStamp::from_u64(662431391999999990);
+    assert_eq!((ts.year, ts.month, ts.day), (2100, 2, 28));
+    assert_eq!((ts.hour, ts.minute, ts.second), (23, 59, 59));
+    assert_eq!(ts.microsecond, 999999);
+    let ts = TimeStamp::from_u64(662431391999999990 + 10);
+    assert_eq!((ts.year, ts.month, ts.day), (2100, 3, 1));
+
+    let ts = TimeStamp::from_u64(638447850152500000);
+    assert_eq!((ts.year, ts.month, ts.day), (2024, 2, 29));
+    assert_eq!(ts.microsecond, 250000);
+}
+
+#[test]
+fn test_sub_second_precision() {
+    let ts = TimeStamp::from_u64(638225427037360000);
+    assert!((ts.to_unix_timestamp() - 1686945903.736).abs() < 1e-6);
+
+    let ts = TimeStamp::from_u64(621355968000000000);
+    assert_eq!(ts.to_unix_timestamp(), 0.0);
+
+    // J2000.0, then the same instant 1.5 seconds later
+    let ts = TimeStamp::from_u64(630823248000000000);
+    assert_eq!(ts.to_julian_day(), 2451545.0);
+    let ts = TimeStamp::from_u64(630823248000000000 + 15000000);
+    assert!((ts.to_julian_day() - (2451545.0 + 1.5 / 86400.0)).abs() < 1e-8);
+}
+
+#[test]
+fn test_calendar_fields_match_independent_conversion() {
+    for ticks in sample_ticks(20000) {
+        let ts = TimeStamp::from_u64(ticks);
+        let (year, month, day) = civil_from_days(ticks / TICKS_PER_DAY);
+        assert_eq!((ts.year, ts.month, ts.day), (year, month, day), "{}", ticks);
+
+        let ticks_into_day = ticks % TICKS_PER_DAY;
+        assert_eq!(ts.hour as u64, ticks_into_day / 36000000000);
+        assert_eq!(ts.minute as u64, ticks_into_day / 600000000 % 60);
+        assert_eq!(ts.second as u64, ticks_into_day / 10000000 % 60);
+        assert_eq!(ts.microsecond as u64, ticks_into_day / 10 % 1000000);
+    }
+}
+
+#[test]
+fn test_naive_date_time_round_trip() {
+    for ticks in sample_ticks(20000) {
+        let ts = TimeStamp::from_u64(ticks);
+        let date_time = ts.to_naive_date_time();
+        assert_eq!(date_time.year(), ts.year);
+        assert_eq!(date_time.nanosecond() as u64, ticks % 10000000 * 100);
+        assert_eq!(TimeStamp::from_naive_date_time(&date_time), ts);
+    }
+
+    let date_time = NaiveDate::from_ymd_opt(2024, 2, 29)
+        .unwrap()
+        .and_hms_nano_opt(6, 30, 15, 250000100)
+        .unwrap();
+    assert_eq!(
+        TimeStamp::from_naive_date_time(&date_time).timestamp,
+        638447850152500001
+    );
+}
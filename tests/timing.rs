@@ -0,0 +1,90 @@
+use solhat::capturemeta::CaptureMetadata;
+use solhat::timing::{TimingIssue, TimingReport, DEFAULT_GAP_FACTOR};
+
+const TICKS_PER_HOUR: u64 = 36000000000;
+const START: u64 = 638225427036930000;
+
+fn evenly_spaced(count: usize, interval_ticks: u64) -> Vec<u64> {
+    (0..count as u64)
+        .map(|i| START + i * interval_ticks)
+        .collect()
+}
+
+#[test]
+fn test_firecapture_log_gaps() {
+    let metadata = CaptureMetadata::load("testdata/Sun_150729.txt").unwrap();
+    let report =
+        TimingReport::from_timestamps(&metadata.frame_timestamps, DEFAULT_GAP_FACTOR).unwrap();
+
+    assert_eq!(report.frame_count, 114);
+    assert!((report.median_interval_ms - 11.0).abs() < 0.5);
+    assert!(report.effective_fps < report.nominal_fps / 2.0);
+
+    // The bursts in the log are split by half second stalls before frames 8, 20 and 85 (1-based)
+    let gap_frames: Vec<usize> = report
+        .issues
+        .iter()
+        .filter_map(|i| match i {
+            TimingIssue::Gap {
+                frame_num,
+                interval_ms,
+                ..
+            } if *interval_ms > 500.0 => Some(*frame_num),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(gap_frames, vec![7, 19, 84]);
+    assert!(report.estimated_dropped_frames() > 100);
+}
+
+#[test]
+fn test_duplicate_and_out_of_order() {
+    let mut timestamps = evenly_spaced(10, 100000);
+    timestamps[4] = timestamps[3];
+    timestamps[7] = timestamps[6] - 1;
+
+    let report = TimingReport::from_timestamps(&timestamps, DEFAULT_GAP_FACTOR).unwrap();
+    assert!(report
+        .issues
+        .contains(&TimingIssue::Duplicate { frame_num: 4 }));
+    assert!(report
+        .issues
+        .contains(&TimingIssue::OutOfOrder { frame_num: 7 }));
+    assert!((report.nominal_fps - 100.0).abs() < 1e-6);
+
+    assert!(TimingReport::from_timestamps(&timestamps[0..1], DEFAULT_GAP_FACTOR).is_err());
+    assert!(TimingReport::from_timestamps(&timestamps, 1.0).is_err());
+    assert!(TimingReport::from_timestamps(&timestamps, 0.25).is_err());
+}
+
+#[test]
+fn test_header_times() {
+    let timestamps = evenly_spaced(100, 100000);
+
+    // Local time seven hours behind UTC, header stamped as the capture started
+    let mut report = TimingReport::from_timestamps(&timestamps, DEFAULT_GAP_FACTOR).unwrap();
+    report.check_header_times(START - 7 * TICKS_PER_HOUR, START);
+    assert!(report.issues.is_empty());
+
+    // Frame timestamps written in local time
+    let local_timestamps: Vec<u64> = timestamps.iter().map(|t| t - 7 * TICKS_PER_HOUR).collect();
+    let mut report = TimingReport::from_timestamps(&local_timestamps, DEFAULT_GAP_FACTOR).unwrap();
+    report.check_header_times(START - 7 * TICKS_PER_HOUR, START);
+    assert_eq!(
+        report.issues,
+        vec![TimingIssue::TimestampsInLocalTime { offset_hours: -7.0 }]
+    );
+
+    // A UTC clock that's off by neither zero nor a time zone
+    let mut report = TimingReport::from_timestamps(&timestamps, DEFAULT_GAP_FACTOR).unwrap();
+    report.check_header_times(START, START + TICKS_PER_HOUR / 3);
+    assert_eq!(report.issues.len(), 2);
+    assert!(matches!(
+        report.issues[0],
+        TimingIssue::IrregularUtcOffset { .. }
+    ));
+    assert!(matches!(
+        report.issues[1],
+        TimingIssue::HeaderUtcMismatch { .. }
+    ));
+}